@@ -0,0 +1,68 @@
+//! Times the programs in `programs/`, taking the fastest of a few runs of each.
+//! Run it with `cargo bench --bench programs`.
+
+use std::{
+    fs,
+    time::{Duration, Instant},
+};
+
+use bft::{Dialect, Interpreter, SeededRandom, Settings, Status, VecIO};
+
+/// The number of times each program is run
+const RUNS: usize = 15;
+
+/// Runs a program for up to a number of steps without recording anything,
+/// returning the number of steps it took
+fn run_quietly(program: &[u8], settings: Settings) -> u64 {
+    let space = settings.space(program);
+    let io = VecIO::with_input(b"25 13\nabc\n").close_input();
+    let mut interpreter = Interpreter::new(space, io, ())
+        .with_settings(settings)
+        .with_random(SeededRandom::new(7));
+    while interpreter.steps() < 5_000_000 && interpreter.step() == Status::Completed {}
+    interpreter.steps()
+}
+
+fn main() {
+    let programs = concat!(env!("CARGO_MANIFEST_DIR"), "/programs");
+    let mut paths: Vec<_> = fs::read_dir(programs)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    paths.sort();
+    println!(
+        "{:<16} {:>9} {:>10} {:>10}",
+        "program", "steps", "time", "ns/step"
+    );
+    let (mut total_steps, mut total_time) = (0, Duration::ZERO);
+    for path in paths {
+        let program = fs::read(&path).unwrap();
+        let settings = Settings {
+            dialect: Dialect::from_path(&path).unwrap_or_default(),
+            ..Default::default()
+        };
+        let mut time = Duration::MAX;
+        let mut steps = 0;
+        for _ in 0..RUNS {
+            let started = Instant::now();
+            steps = run_quietly(&program, settings);
+            time = time.min(started.elapsed());
+        }
+        println!(
+            "{:<16} {:>9} {:>10.2?} {:>10.1}",
+            path.file_name().unwrap().to_string_lossy(),
+            steps,
+            time,
+            time.as_nanos() as f64 / steps as f64
+        );
+        total_steps += steps;
+        total_time += time;
+    }
+    println!(
+        "{:<16} {:>9} {:>10.2?} {:>10.1}",
+        "total",
+        total_steps,
+        total_time,
+        total_time.as_nanos() as f64 / total_steps as f64
+    );
+}
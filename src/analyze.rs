@@ -2,20 +2,20 @@ use core::fmt;
 use std::collections::VecDeque;
 
 use crate::{
-    core::{Direction, GridCell, Mode, Position},
+    core::{Dialect, Direction, GridCell, Mode, Position},
     space::Space,
 };
 
-pub fn analyze_path(space: &Space<GridCell>) -> PathAnalysis {
-    PathAnalysisState::new(space).analyze()
+pub fn analyze_path(space: &Space<GridCell>, dialect: Dialect) -> PathAnalysis {
+    PathAnalysisState::new(space, dialect).analyze()
 }
 
 pub struct PathAnalysis {
-    pub cell_states: Space<State>,
+    pub cell_states: Space<CellState>,
 }
 
 #[derive(Default, Clone, Copy, PartialEq, Eq)]
-pub struct State(u8);
+pub struct CellState(u8);
 
 // Quoted: Up, Down, Left, and Right masks
 const QU_MASK: u8 = 0b0001;
@@ -39,7 +39,7 @@ impl fmt::Debug for Unquoted<'_> {
     }
 }
 
-impl fmt::Debug for State {
+impl fmt::Debug for CellState {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut ds = f.debug_set();
         if (self.0 & QU_MASK) != 0 {
@@ -86,7 +86,7 @@ pub enum Directions {
     Both,
 }
 
-impl State {
+impl CellState {
     pub fn modes(self) -> Modes {
         let normal = (self.0 & N_MASK) != 0;
         let quoted = (self.0 & Q_MASK) != 0;
@@ -123,6 +123,8 @@ impl State {
             (Direction::Left, Mode::Normal) => NL_MASK,
             (Direction::Right, Mode::Quote) => QR_MASK,
             (Direction::Right, Mode::Normal) => NR_MASK,
+            // The analysis only follows the cardinal directions
+            (Direction::Delta(..), _) => 0,
         };
         Self(self.0 | mask)
     }
@@ -130,17 +132,19 @@ impl State {
 
 struct PathAnalysisState<'src> {
     space: &'src Space<GridCell>,
-    states: Space<State>,
+    dialect: Dialect,
+    states: Space<CellState>,
     queue: VecDeque<(Position, Direction, Mode)>,
 }
 
 impl<'src> PathAnalysisState<'src> {
-    fn new(space: &'src Space<GridCell>) -> Self {
-        let states: Space<State> = Space::with_size(space.rows(), space.cols());
+    fn new(space: &'src Space<GridCell>, dialect: Dialect) -> Self {
+        let states: Space<CellState> = Space::with_size(space.rows(), space.cols());
         let mut queue: VecDeque<(Position, Direction, Mode)> = Default::default();
         queue.push_back((Position::ORIGIN, Direction::Right, Mode::Normal));
         Self {
             space,
+            dialect,
             states,
             queue,
         }
@@ -151,8 +155,8 @@ impl<'src> PathAnalysisState<'src> {
             let cell = self.space.get_cell(pos);
 
             // Fake out the mode so that quotes always show as quoted
-            let draw_mode = match (cell.0, mode) {
-                (b'"', _) => Mode::Quote,
+            let draw_mode = match (cell.opcode(), mode) {
+                (Some(b'"'), _) => Mode::Quote,
                 (_, mode) => mode,
             };
             let old = self.states.get_cell(pos);
@@ -163,9 +167,9 @@ impl<'src> PathAnalysisState<'src> {
             self.states.set_cell(pos, new);
 
             // Actually update the mode
-            let mode = match (cell.0, mode) {
-                (b'"', Mode::Quote) => Mode::Normal,
-                (b'"', Mode::Normal) => Mode::Quote,
+            let mode = match (cell.opcode(), mode) {
+                (Some(b'"'), Mode::Quote) => Mode::Normal,
+                (Some(b'"'), Mode::Normal) => Mode::Quote,
                 (_, mode) => mode,
             };
 
@@ -174,39 +178,43 @@ impl<'src> PathAnalysisState<'src> {
                 continue;
             }
 
-            match cell.0 {
-                b'^' => {
+            if self.dialect == Dialect::Funge98 && self.analyze_98(cell, pos, dir, mode) {
+                continue;
+            }
+
+            match cell.opcode() {
+                Some(b'^') => {
                     self.up(pos, mode);
                 }
-                b'v' => {
+                Some(b'v') => {
                     self.down(pos, mode);
                 }
-                b'<' => {
+                Some(b'<') => {
                     self.left(pos, mode);
                 }
-                b'>' => {
+                Some(b'>') => {
                     self.right(pos, mode);
                 }
-                b'?' => {
+                Some(b'?') => {
                     self.up(pos, mode);
                     self.down(pos, mode);
                     self.left(pos, mode);
                     self.right(pos, mode);
                 }
-                b'|' => {
+                Some(b'|') => {
                     self.up(pos, mode);
                     self.down(pos, mode);
                 }
-                b'_' => {
+                Some(b'_') => {
                     self.left(pos, mode);
                     self.right(pos, mode);
                 }
-                b'#' => {
+                Some(b'#') => {
                     let pos = self.space.move_pos(pos, dir);
                     let pos = self.space.move_pos(pos, dir);
                     self.queue.push_back((pos, dir, mode));
                 }
-                b'@' => {
+                Some(b'@') => {
                     continue;
                 }
                 _ => {
@@ -219,6 +227,47 @@ impl<'src> PathAnalysisState<'src> {
         }
     }
 
+    /// Follows the Funge-98 instructions that affect control flow.
+    /// Returns false if the instruction should be treated like Befunge-93.
+    fn analyze_98(&mut self, cell: GridCell, pos: Position, dir: Direction, mode: Mode) -> bool {
+        match cell.opcode() {
+            Some(b'\'' | b's') => {
+                let pos = self.space.move_pos(pos, dir);
+                let pos = self.space.move_pos(pos, dir);
+                self.queue.push_back((pos, dir, mode));
+            }
+            Some(b';') => {
+                let mut end = self.space.move_pos(pos, dir);
+                while end != pos && self.space.get_cell(end).opcode() != Some(b';') {
+                    end = self.space.move_pos(end, dir);
+                }
+                self.forward(end, dir, mode);
+            }
+            Some(b'[') => self.forward(pos, dir.turn_left(), mode),
+            Some(b']') => self.forward(pos, dir.turn_right(), mode),
+            Some(b'r') => self.forward(pos, dir.reverse(), mode),
+            // Splits go both ways and the rest reflect when they can't be executed
+            Some(b't' | b'}' | b'u' | b'(' | b')' | b'A'..=b'Z') => {
+                self.forward(pos, dir, mode);
+                self.forward(pos, dir.reverse(), mode);
+            }
+            Some(b'w') => {
+                self.forward(pos, dir, mode);
+                self.forward(pos, dir.turn_left(), mode);
+                self.forward(pos, dir.turn_right(), mode);
+            }
+            Some(b'x') => {
+                self.up(pos, mode);
+                self.down(pos, mode);
+                self.left(pos, mode);
+                self.right(pos, mode);
+            }
+            Some(b'q') => {}
+            _ => return false,
+        }
+        true
+    }
+
     fn forward(&mut self, pos: Position, dir: Direction, mode: Mode) {
         let pos = self.space.move_pos(pos, dir);
         self.queue.push_back((pos, dir, mode));
@@ -251,7 +300,7 @@ mod tests {
 
     #[test]
     fn test_state_masks() {
-        let state = State::default();
+        let state = CellState::default();
         assert_eq!(state.directions(), Directions::None);
         assert_eq!(state.modes(), Modes::None);
         let state = state.update(Direction::Right, Mode::Normal);
@@ -0,0 +1,613 @@
+use std::collections::{HashMap, VecDeque};
+use std::num::Wrapping;
+
+use thiserror::Error;
+
+use crate::{
+    analyze::{self, Modes},
+    core::{Cursor, Dialect, Direction, DivisionByZero, EndOfInput, GridCell, Mode, Position},
+    interpreter::{ErrorKind, Settings},
+    space::Space,
+};
+
+mod c;
+mod rust;
+
+/// The longest a block can get before it is split, to bound the size of the output
+/// when paths through the program overlap
+const MAX_BLOCK_OPS: usize = 4096;
+
+/// The order "?" picks directions in, matching the interpreter's random number generator
+const RANDOM_DIRECTIONS: [Direction; 4] = [
+    Direction::Right,
+    Direction::Left,
+    Direction::Up,
+    Direction::Down,
+];
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Default, clap::ValueEnum)]
+/// The language a program is compiled to
+pub enum Target {
+    /// A Rust program with no dependencies, built with `rustc -O`
+    #[default]
+    Rust,
+    /// A C99 program, built with `cc -O2`
+    C,
+}
+
+#[derive(Debug, Error)]
+pub enum CompileError {
+    #[error("Only Befunge-93 programs can be compiled")]
+    Dialect,
+    #[error("{0} is not supported by compiled programs")]
+    Unsupported(&'static str),
+}
+
+/// Compiles a Befunge-93 program to the source code of a standalone program.
+///
+/// The paths the program can take are split into basic blocks that run without
+/// looking at the program space. Cells on those paths are marked as code,
+/// and if "p" changes one of them, or grows the space so that the IP wraps differently,
+/// the compiled program hands over to an interpreter embedded in it.
+/// With a seed, "?" picks the same directions as `bft run --seed`.
+pub fn compile(
+    space: &Space<GridCell>,
+    settings: Settings,
+    seed: Option<u64>,
+    target: Target,
+) -> Result<String, CompileError> {
+    if settings.dialect != Dialect::Befunge93 {
+        return Err(CompileError::Dialect);
+    }
+    if settings.detect_loops {
+        return Err(CompileError::Unsupported("Loop detection"));
+    }
+    if settings.underflow != Default::default() {
+        return Err(CompileError::Unsupported("Underflow reporting"));
+    }
+    let program = Program::new(space, settings, seed);
+    Ok(match target {
+        Target::Rust => rust::emit(&program),
+        Target::C => c::emit(&program),
+    })
+}
+
+/// A program split into basic blocks, ready to be emitted as source code
+struct Program {
+    settings: Settings,
+    seed: Option<u64>,
+    rows: usize,
+    cols: usize,
+    /// The initial program space, row by row
+    cells: Vec<i32>,
+    /// Whether each cell is on a path the blocks were built from, row by row
+    code: Vec<bool>,
+    /// The blocks, starting with the one at the origin
+    blocks: Vec<Block>,
+}
+
+/// Instructions that always run one after another,
+/// followed by a jump to the next block
+struct Block {
+    entry: Cursor,
+    ops: Vec<Op>,
+    exit: Exit,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+enum Op {
+    Push(i32),
+    Add,
+    Subtract,
+    Multiply,
+    Greater,
+    Not,
+    Duplicate,
+    Swap,
+    Discard,
+    OutputNumber,
+    OutputChar,
+    Get,
+    /// "p", which hands over to the interpreter if it changes the code
+    Put {
+        at: Position,
+        dir: Direction,
+    },
+    /// "/" or "%", which can reflect when the user is asked for the result and input has ended
+    Divide {
+        remainder: bool,
+        at: Position,
+        reflect: Option<usize>,
+    },
+    /// "&" or "~", which can reflect when input has ended
+    Input {
+        number: bool,
+        at: Position,
+        reflect: Option<usize>,
+    },
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+enum Exit {
+    Jump(usize),
+    /// "_" or "|", which pop a value and go one way if it is zero and the other if it isn't
+    Branch {
+        zero: usize,
+        nonzero: usize,
+    },
+    /// "?", with the block for each direction in [`RANDOM_DIRECTIONS`] order
+    Random([usize; 4]),
+    Terminate,
+    /// An error that is certain to happen when the block runs
+    Fail {
+        message: String,
+        at: Position,
+    },
+}
+
+impl Program {
+    fn new(space: &Space<GridCell>, settings: Settings, seed: Option<u64>) -> Self {
+        let (rows, cols) = (space.rows(), space.cols());
+        let positions = (0..rows).flat_map(|y| {
+            (0..cols).map(move |x| Position {
+                x: x as i32,
+                y: y as i32,
+            })
+        });
+        let analysis = analyze::analyze_path(space, settings.dialect);
+        let (cells, code) = positions
+            .map(|pos| {
+                let cell = space.get_cell(pos).0;
+                let code = analysis.cell_states.get_cell(pos).modes() != Modes::None;
+                (cell, code)
+            })
+            .unzip();
+        Self {
+            settings,
+            seed,
+            rows,
+            cols,
+            cells,
+            code,
+            blocks: Builder::new(space, settings).build(),
+        }
+    }
+}
+
+/// Follows the paths through a program to build its blocks
+struct Builder<'a> {
+    space: &'a Space<GridCell>,
+    settings: Settings,
+    blocks: Vec<Block>,
+    /// The block that starts at each cursor
+    entries: HashMap<Cursor, usize>,
+    /// Blocks that have been jumped to but not built yet
+    queue: VecDeque<usize>,
+}
+
+impl<'a> Builder<'a> {
+    fn new(space: &'a Space<GridCell>, settings: Settings) -> Self {
+        Self {
+            space,
+            settings,
+            blocks: Vec::new(),
+            entries: HashMap::new(),
+            queue: VecDeque::new(),
+        }
+    }
+
+    fn build(mut self) -> Vec<Block> {
+        self.block(Cursor::default());
+        while let Some(id) = self.queue.pop_front() {
+            let (ops, exit) = self.trace(self.blocks[id].entry);
+            let block = &mut self.blocks[id];
+            block.ops = fold_constants(ops);
+            block.exit = exit;
+        }
+        self.blocks
+    }
+
+    /// The block starting at a cursor, which is queued to be built if it is new
+    fn block(&mut self, entry: Cursor) -> usize {
+        if let Some(id) = self.entries.get(&entry) {
+            return *id;
+        }
+        let id = self.blocks.len();
+        self.blocks.push(Block {
+            entry,
+            ops: Vec::new(),
+            exit: Exit::Terminate,
+        });
+        self.entries.insert(entry, id);
+        self.queue.push_back(id);
+        id
+    }
+
+    /// The block that continues from a cell in a direction
+    fn block_after(&mut self, pos: Position, dir: Direction) -> usize {
+        let pos = self.space.move_pos(pos, dir);
+        self.block(Cursor {
+            pos,
+            dir,
+            mode: Mode::Normal,
+        })
+    }
+
+    /// The block an input instruction continues with if it reflects
+    fn reflect_block(&mut self, pos: Position, dir: Direction) -> Option<usize> {
+        (self.settings.end_of_input() == EndOfInput::Reflect)
+            .then(|| self.block_after(pos, dir.reverse()))
+    }
+
+    /// Follows the path from a cursor until the next instruction that can go more than one way
+    fn trace(&mut self, entry: Cursor) -> (Vec<Op>, Exit) {
+        let mut ops = Vec::new();
+        let mut cursor = entry;
+        // The number of instructions that had been executed when each cursor was visited,
+        // to tell a loop of spaces apart from a loop that does something
+        let mut visited = HashMap::new();
+        let mut executed = 0;
+        loop {
+            if cursor != entry && self.entries.contains_key(&cursor) {
+                return (ops, Exit::Jump(self.entries[&cursor]));
+            }
+            if let Some(before) = visited.insert(cursor, executed) {
+                if before == executed {
+                    return (ops, self.space_loop(cursor));
+                }
+                return (ops, Exit::Jump(self.block(cursor)));
+            }
+            if ops.len() >= MAX_BLOCK_OPS {
+                return (ops, Exit::Jump(self.block(cursor)));
+            }
+
+            let Cursor { pos, dir, mode } = cursor;
+            let cell = self.space.get_cell(pos);
+            if mode == Mode::Quote {
+                executed += 1;
+                if cell.opcode() == Some(b'"') {
+                    cursor.mode = Mode::Normal;
+                } else {
+                    ops.push(Op::Push(cell.0));
+                }
+                cursor.pos = self.space.move_pos(pos, dir);
+                continue;
+            }
+
+            let Some(opcode) = cell.opcode() else {
+                return (ops, invalid_opcode(cell, pos));
+            };
+            if opcode == b' ' {
+                cursor.pos = self.space.move_pos(pos, dir);
+                continue;
+            }
+            executed += 1;
+            let op = match opcode {
+                b'0'..=b'9' => Op::Push((opcode - b'0') as i32),
+                b'+' => Op::Add,
+                b'-' => Op::Subtract,
+                b'*' => Op::Multiply,
+                b'`' => Op::Greater,
+                b'!' => Op::Not,
+                b':' => Op::Duplicate,
+                b'\\' => Op::Swap,
+                b'$' => Op::Discard,
+                b'.' => Op::OutputNumber,
+                b',' => Op::OutputChar,
+                b'g' => Op::Get,
+                b'p' => Op::Put { at: pos, dir },
+                b'/' | b'%' => {
+                    // Only asking the user for the result reads input
+                    let prompts = self.settings.division_by_zero() == DivisionByZero::Prompt;
+                    Op::Divide {
+                        remainder: opcode == b'%',
+                        at: pos,
+                        reflect: prompts.then(|| self.reflect_block(pos, dir)).flatten(),
+                    }
+                }
+                b'&' | b'~' => Op::Input {
+                    number: opcode == b'&',
+                    at: pos,
+                    reflect: self.reflect_block(pos, dir),
+                },
+                b'>' | b'<' | b'^' | b'v' => {
+                    cursor.dir = match opcode {
+                        b'>' => Direction::Right,
+                        b'<' => Direction::Left,
+                        b'^' => Direction::Up,
+                        _ => Direction::Down,
+                    };
+                    cursor.pos = self.space.move_pos(pos, cursor.dir);
+                    continue;
+                }
+                b'"' => {
+                    cursor.mode = Mode::Quote;
+                    cursor.pos = self.space.move_pos(pos, dir);
+                    continue;
+                }
+                b'#' => {
+                    let skipped = self.space.move_pos(pos, dir);
+                    cursor.pos = self.space.move_pos(skipped, dir);
+                    continue;
+                }
+                b'_' => {
+                    let exit = Exit::Branch {
+                        zero: self.block_after(pos, Direction::Right),
+                        nonzero: self.block_after(pos, Direction::Left),
+                    };
+                    return (ops, exit);
+                }
+                b'|' => {
+                    let exit = Exit::Branch {
+                        zero: self.block_after(pos, Direction::Down),
+                        nonzero: self.block_after(pos, Direction::Up),
+                    };
+                    return (ops, exit);
+                }
+                b'?' => {
+                    let exit =
+                        Exit::Random(RANDOM_DIRECTIONS.map(|dir| self.block_after(pos, dir)));
+                    return (ops, exit);
+                }
+                b'@' => return (ops, Exit::Terminate),
+                _ => return (ops, invalid_opcode(cell, pos)),
+            };
+            ops.push(op);
+            cursor.pos = self.space.move_pos(pos, dir);
+        }
+    }
+
+    /// The error the interpreter stops with when it looks for an instruction
+    /// along a line with nothing but spaces
+    fn space_loop(&self, start: Cursor) -> Exit {
+        let mut cells = vec![start.pos];
+        let mut pos = self.space.move_pos(start.pos, start.dir);
+        while pos != start.pos {
+            cells.push(pos);
+            pos = self.space.move_pos(pos, start.dir);
+        }
+        let length = cells.len();
+        Exit::Fail {
+            message: ErrorKind::InfiniteLoop { cells, length }.to_string(),
+            at: start.pos,
+        }
+    }
+}
+
+fn invalid_opcode(cell: GridCell, at: Position) -> Exit {
+    Exit::Fail {
+        message: ErrorKind::InvalidOpcode(cell.0).to_string(),
+        at,
+    }
+}
+
+/// Computes arithmetic on constants ahead of time
+fn fold_constants(ops: Vec<Op>) -> Vec<Op> {
+    let mut folded: Vec<Op> = Vec::with_capacity(ops.len());
+    for op in ops {
+        let binary = |lower: i32, upper: i32| {
+            let (lower, upper) = (Wrapping(lower), Wrapping(upper));
+            match op {
+                Op::Add => Some((lower + upper).0),
+                Op::Subtract => Some((lower - upper).0),
+                Op::Multiply => Some((lower * upper).0),
+                Op::Greater => Some((lower > upper) as i32),
+                _ => None,
+            }
+        };
+        if let [.., Op::Push(lower), Op::Push(upper)] = folded[..]
+            && let Some(result) = binary(lower, upper)
+        {
+            folded.truncate(folded.len() - 2);
+            folded.push(Op::Push(result));
+            continue;
+        }
+        match (folded.last(), &op) {
+            (Some(Op::Push(value)), Op::Not) => {
+                let result = (*value == 0) as i32;
+                folded.pop();
+                folded.push(Op::Push(result));
+            }
+            (Some(Op::Push(value)), Op::Duplicate) => folded.push(Op::Push(*value)),
+            (Some(Op::Push(_)), Op::Discard) => {
+                folded.pop();
+            }
+            _ => folded.push(op),
+        }
+    }
+    folded
+}
+
+/// The position of a direction in [`RANDOM_DIRECTIONS`],
+/// which compiled programs use to represent it
+fn direction_index(dir: Direction) -> usize {
+    RANDOM_DIRECTIONS.iter().position(|d| *d == dir).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        path::{Path, PathBuf},
+        process::{Command, Stdio},
+    };
+
+    use super::*;
+    use crate::{interpreter::Interpreter, io::VecIO, random::SeededRandom};
+
+    fn blocks(program: &[u8]) -> Vec<Block> {
+        let space = Space::new(program);
+        Builder::new(&space, Settings::default()).build()
+    }
+
+    #[test]
+    fn test_straight_line() {
+        let blocks = blocks(b"25*.\"ab\"#v@\n");
+        assert_eq!(1, blocks.len());
+        let expected = vec![
+            Op::Push(10),
+            Op::OutputNumber,
+            Op::Push(b'a' as i32),
+            Op::Push(b'b' as i32),
+        ];
+        assert_eq!(expected, blocks[0].ops);
+        assert_eq!(Exit::Terminate, blocks[0].exit);
+    }
+
+    #[test]
+    fn test_branches_and_loops() {
+        // Counts down from 3, looping back through the top row
+        let blocks = blocks(b"3>:.1-:v\n ^     _@");
+        let Exit::Branch { zero, nonzero } = blocks[0].exit else {
+            panic!("expected a branch, found {:?}", blocks[0].exit);
+        };
+        assert_eq!(Exit::Terminate, blocks[zero].exit);
+        // The loop body ends at the same branch
+        assert_eq!(blocks[0].exit, blocks[nonzero].exit);
+        assert_eq!(3, blocks.len());
+    }
+
+    #[test]
+    fn test_errors() {
+        let invalid = blocks(b"1a");
+        assert_eq!(
+            invalid_opcode(GridCell::from(b'a'), Position { x: 1, y: 0 }),
+            invalid[0].exit
+        );
+        let space_loop = blocks(b"\n@");
+        assert!(
+            matches!(&space_loop[0].exit, Exit::Fail { message, .. } if message.contains("1 cells"))
+        );
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        let ops = vec![
+            Op::Push(6),
+            Op::Push(7),
+            Op::Multiply,
+            Op::Push(1),
+            Op::Greater,
+            Op::Not,
+            Op::Duplicate,
+            Op::Push(9),
+            Op::Discard,
+            Op::Add,
+        ];
+        assert_eq!(vec![Op::Push(0)], fold_constants(ops));
+        let ops = vec![Op::Get, Op::Push(1), Op::Add];
+        assert_eq!(ops.clone(), fold_constants(ops));
+    }
+
+    fn unsupported(settings: Settings) -> bool {
+        let space = Space::new(b"@");
+        compile(&space, settings, None, Target::C).is_err()
+    }
+
+    #[test]
+    fn test_unsupported_settings() {
+        let dialect = Dialect::Funge98;
+        assert!(unsupported(Settings {
+            dialect,
+            ..Default::default()
+        }));
+        assert!(unsupported(Settings {
+            detect_loops: true,
+            ..Default::default()
+        }));
+        assert!(!unsupported(Settings::default()));
+    }
+
+    /// Output of the interpreter, to compare compiled programs against
+    fn interpret(program: &[u8], input: &[u8], settings: Settings) -> Vec<u8> {
+        let space = settings.space(program);
+        let io = VecIO::with_input(input).close_input();
+        let mut interpreter = Interpreter::new(space, io, ())
+            .with_settings(settings)
+            .with_random(SeededRandom::new(7));
+        loop {
+            match interpreter.step() {
+                crate::interpreter::Status::Completed => {}
+                crate::interpreter::Status::Terminated => break,
+                status => panic!("Unexpected status {:?}", status),
+            }
+        }
+        interpreter.io().output().to_vec()
+    }
+
+    /// Builds a compiled program with the compiler for its target
+    fn build(source: &str, target: Target, dir: &Path) -> PathBuf {
+        let (file, compiler, args): (_, _, &[&str]) = match target {
+            Target::Rust => ("main.rs", "rustc", &["-o"]),
+            Target::C => ("main.c", "cc", &["-std=c99", "-Wall", "-Werror", "-o"]),
+        };
+        let source_path = dir.join(file);
+        let binary = dir.join("main");
+        fs::write(&source_path, source).unwrap();
+        let output = Command::new(compiler)
+            .args(args)
+            .arg(&binary)
+            .arg(&source_path)
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success(), "{compiler} failed:\n{stderr}");
+        binary
+    }
+
+    fn run_binary(binary: &Path, input: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+        let mut child = Command::new(binary)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(input).unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success());
+        output.stdout
+    }
+
+    /// Programs that cover every kind of op and exit, with input for them
+    const CASES: &[(&[u8], &[u8])] = &[
+        // Arithmetic, including wrapping and division by zero prompts
+        (b"2- 3* 7/ 5% .@", b""),
+        (b"\"~\"9*:*:*:*:* . 10/.@", b"12\n"),
+        // Strings, "#", branches and loops
+        (b"91+\"!olleH\">:#,_@", b""),
+        (b"5>:.1-:v\n ^     _@", b""),
+        // Input, including the end of input
+        (b"&&+.~,~.@", b"-12 30x"),
+        // "g" and "p" off the code, then onto it to hand over to the interpreter
+        (b"77*01p01g.@\n ", b""),
+        (b"\"@\"70p 1.@", b""),
+        // Growing the space changes where the IP wraps
+        (b"\"@\"8p   5.@", b""),
+        // Random directions, reproduced from the seed
+        (b"v\n?2.@\n1\n.\n@", b""),
+        // Output of characters beyond ASCII
+        ("\"☺\",@".as_bytes(), b""),
+    ];
+
+    #[test]
+    fn test_compiled_output() {
+        let dir = env::temp_dir().join(format!("bft-compile-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for target in [Target::C, Target::Rust] {
+            for (program, input) in CASES {
+                let settings = Settings::default();
+                let source = compile(&settings.space(program), settings, Some(7), target).unwrap();
+                let binary = build(&source, target, &dir);
+                let expected = interpret(program, input, settings);
+                let actual = run_binary(&binary, input);
+                assert_eq!(
+                    String::from_utf8_lossy(&expected),
+                    String::from_utf8_lossy(&actual),
+                    "{target:?} output of {}",
+                    String::from_utf8_lossy(program)
+                );
+            }
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
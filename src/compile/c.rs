@@ -0,0 +1,144 @@
+use std::fmt::Write;
+
+use crate::core::{DivisionByZero, EndOfInput};
+
+use super::{Block, Exit, Op, Program, direction_index};
+
+const RUNTIME: &str = include_str!("runtime.c");
+
+/// Writes a program as C99 source code, with a label for every block
+pub fn emit(program: &Program) -> String {
+    let mut out = String::new();
+    writeln!(
+        out,
+        "/* Compiled from Befunge-93 by bft. Build with: cc -O2 -o program program.c */"
+    )
+    .unwrap();
+    writeln!(out, "#define _POSIX_C_SOURCE 200809L").unwrap();
+    for header in ["stdint.h", "stdio.h", "stdlib.h", "time.h", "unistd.h"] {
+        writeln!(out, "#include <{header}>").unwrap();
+    }
+    writeln!(out).unwrap();
+
+    let settings = program.settings;
+    let division_by_zero = match settings.division_by_zero() {
+        DivisionByZero::Zero => "DIVIDE_ZERO",
+        DivisionByZero::Prompt => "DIVIDE_PROMPT",
+        DivisionByZero::Error => "DIVIDE_ERROR",
+    };
+    let end_of_input = match settings.end_of_input() {
+        EndOfInput::Wait => "INPUT_WAIT",
+        EndOfInput::PushNegative => "INPUT_PUSH_NEGATIVE",
+        EndOfInput::Reflect => "INPUT_REFLECT",
+        EndOfInput::Error => "INPUT_ERROR",
+    };
+    writeln!(out, "#define ROWS {}", program.rows).unwrap();
+    writeln!(out, "#define COLS {}", program.cols).unwrap();
+    writeln!(out, "#define STRICT_93 {}", settings.strict_93 as u8).unwrap();
+    writeln!(out, "#define DIVISION_BY_ZERO {division_by_zero}").unwrap();
+    writeln!(out, "#define END_OF_INPUT {end_of_input}").unwrap();
+    writeln!(out, "#define HAS_SEED {}", program.seed.is_some() as u8).unwrap();
+    writeln!(out, "#define SEED {}ull", program.seed.unwrap_or(0)).unwrap();
+    writeln!(out).unwrap();
+
+    writeln!(out, "static int32_t grid[ROWS * COLS] = {{").unwrap();
+    write_rows(&mut out, program, |i| program.cells[i].to_string());
+    writeln!(out, "}};").unwrap();
+    writeln!(out, "/* The cells the compiled code was built from */").unwrap();
+    writeln!(out, "static const unsigned char code[ROWS * COLS] = {{").unwrap();
+    write_rows(&mut out, program, |i| (program.code[i] as u8).to_string());
+    writeln!(out, "}};").unwrap();
+    writeln!(out).unwrap();
+
+    out.push_str(RUNTIME);
+    writeln!(out).unwrap();
+    writeln!(out, "int main(void) {{").unwrap();
+    writeln!(out, "    seed_random();").unwrap();
+    writeln!(out, "    goto b0;").unwrap();
+    for (id, block) in program.blocks.iter().enumerate() {
+        write_block(&mut out, id, block);
+    }
+    writeln!(out, "}}").unwrap();
+    out
+}
+
+/// Writes the values of the cells in an array initializer, one row per line
+fn write_rows(out: &mut String, program: &Program, value: impl Fn(usize) -> String) {
+    for y in 0..program.rows {
+        let row: Vec<_> = (0..program.cols)
+            .map(|x| value(y * program.cols + x))
+            .collect();
+        writeln!(out, "    {},", row.join(", ")).unwrap();
+    }
+}
+
+fn write_block(out: &mut String, id: usize, block: &Block) {
+    let entry = block.entry;
+    writeln!(out, "b{id}: /* {} moving {} */", entry.pos, entry.dir).unwrap();
+    for op in &block.ops {
+        let statement = match op {
+            Op::Push(value) => format!("push({value});"),
+            Op::Add => String::from("add();"),
+            Op::Subtract => String::from("subtract();"),
+            Op::Multiply => String::from("multiply();"),
+            Op::Greater => String::from("greater();"),
+            Op::Not => String::from("not();"),
+            Op::Duplicate => String::from("duplicate();"),
+            Op::Swap => String::from("swap();"),
+            Op::Discard => String::from("discard();"),
+            Op::OutputNumber => String::from("output_number();"),
+            Op::OutputChar => String::from("output_char();"),
+            Op::Get => String::from("get();"),
+            Op::Put { at, dir } => format!(
+                "if (put()) interpret({}, {}, {});",
+                at.x,
+                at.y,
+                direction_index(*dir)
+            ),
+            Op::Divide {
+                remainder,
+                at,
+                reflect,
+            } => {
+                let call = format!("divide({}, {}, {})", *remainder as u8, at.x, at.y);
+                reflecting(call, *reflect)
+            }
+            Op::Input {
+                number,
+                at,
+                reflect,
+            } => {
+                let call = format!("input({}, {}, {})", *number as u8, at.x, at.y);
+                reflecting(call, *reflect)
+            }
+        };
+        writeln!(out, "    {statement}").unwrap();
+    }
+    match &block.exit {
+        Exit::Jump(next) => writeln!(out, "    goto b{next};").unwrap(),
+        Exit::Branch { zero, nonzero } => {
+            writeln!(out, "    if (pop()) goto b{nonzero};").unwrap();
+            writeln!(out, "    goto b{zero};").unwrap();
+        }
+        Exit::Random([right, left, up, down]) => {
+            writeln!(out, "    switch (random_direction()) {{").unwrap();
+            writeln!(out, "    case RIGHT: goto b{right};").unwrap();
+            writeln!(out, "    case LEFT: goto b{left};").unwrap();
+            writeln!(out, "    case UP: goto b{up};").unwrap();
+            writeln!(out, "    default: goto b{down};").unwrap();
+            writeln!(out, "    }}").unwrap();
+        }
+        Exit::Terminate => writeln!(out, "    finish();").unwrap(),
+        Exit::Fail { message, at } => {
+            writeln!(out, "    fail({message:?}, {}, {});", at.x, at.y).unwrap()
+        }
+    }
+}
+
+/// A statement that runs a call and jumps to another block if it reflects
+fn reflecting(call: String, reflect: Option<usize>) -> String {
+    match reflect {
+        Some(block) => format!("if ({call}) goto b{block};"),
+        None => format!("{call};"),
+    }
+}
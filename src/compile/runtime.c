@@ -0,0 +1,422 @@
+/* The runtime shared by every compiled program, with functions marked inline
+ * so that the ones a program doesn't use don't cause warnings.
+ * The generated code before it includes the headers and defines the settings,
+ * ROWS, COLS, grid and code, and the generated code after it defines main. */
+
+enum { RIGHT, LEFT, UP, DOWN };
+enum { DIVIDE_ZERO, DIVIDE_PROMPT, DIVIDE_ERROR };
+enum { INPUT_WAIT, INPUT_PUSH_NEGATIVE, INPUT_REFLECT, INPUT_ERROR };
+
+typedef struct {
+    int32_t x, y, value;
+} SparseCell;
+
+static int32_t *stack;
+static size_t stack_len, stack_cap;
+/* Cells written outside of the grid */
+static SparseCell *sparse;
+static size_t sparse_len, sparse_cap;
+/* The bounds the IP wraps around at */
+static int32_t least_x = 0, least_y = 0, greatest_x = COLS - 1, greatest_y = ROWS - 1;
+static uint64_t random_state;
+
+static inline void *grow(void *items, size_t *cap, size_t size) {
+    *cap = *cap ? *cap * 2 : 1024;
+    items = realloc(items, *cap * size);
+    if (!items) {
+        abort();
+    }
+    return items;
+}
+
+static inline void push(int32_t value) {
+    if (stack_len == stack_cap) {
+        stack = grow(stack, &stack_cap, sizeof *stack);
+    }
+    stack[stack_len++] = value;
+}
+
+static inline int32_t pop(void) {
+    return stack_len ? stack[--stack_len] : 0;
+}
+
+static inline int32_t peek(size_t depth) {
+    return stack_len > depth ? stack[stack_len - 1 - depth] : 0;
+}
+
+static inline void finish(void) {
+    fflush(stdout);
+    exit(0);
+}
+
+static inline void fail(const char *message, int32_t x, int32_t y) {
+    fflush(stdout);
+    fprintf(stderr, "error: %s at (%d, %d)\n", message, (int)x, (int)y);
+    exit(1);
+}
+
+/* Arithmetic wraps around like the interpreter's */
+static inline int32_t wrap(uint32_t value) {
+    return (int32_t)value;
+}
+
+static inline void add(void) {
+    uint32_t upper = (uint32_t)pop();
+    push(wrap((uint32_t)pop() + upper));
+}
+
+static inline void subtract(void) {
+    uint32_t upper = (uint32_t)pop();
+    push(wrap((uint32_t)pop() - upper));
+}
+
+static inline void multiply(void) {
+    uint32_t upper = (uint32_t)pop();
+    push(wrap((uint32_t)pop() * upper));
+}
+
+static inline void greater(void) {
+    int32_t upper = pop();
+    push(pop() > upper);
+}
+
+static inline void not(void) {
+    push(!pop());
+}
+
+static inline void duplicate(void) {
+    int32_t value = pop();
+    push(value);
+    push(value);
+}
+
+static inline void swap(void) {
+    int32_t upper = pop();
+    int32_t lower = pop();
+    push(upper);
+    push(lower);
+}
+
+static inline void discard(void) {
+    pop();
+}
+
+static inline void output_number(void) {
+    printf("%d ", (int)pop());
+}
+
+static inline void output_char(void) {
+    int32_t value = pop();
+    uint32_t c = (uint32_t)value;
+    int valid = !STRICT_93 && c < 0x110000 && (c < 0xD800 || c > 0xDFFF);
+    if (!valid || c < 0x80) {
+        /* Values that aren't code points are written as a single byte */
+        putchar((unsigned char)value);
+    } else if (c < 0x800) {
+        putchar(0xC0 | (c >> 6));
+        putchar(0x80 | (c & 0x3F));
+    } else if (c < 0x10000) {
+        putchar(0xE0 | (c >> 12));
+        putchar(0x80 | ((c >> 6) & 0x3F));
+        putchar(0x80 | (c & 0x3F));
+    } else {
+        putchar(0xF0 | (c >> 18));
+        putchar(0x80 | ((c >> 12) & 0x3F));
+        putchar(0x80 | ((c >> 6) & 0x3F));
+        putchar(0x80 | (c & 0x3F));
+    }
+}
+
+static inline int read_byte(void) {
+    fflush(stdout);
+    int c = getchar();
+    while (c == EOF && END_OF_INPUT == INPUT_WAIT) {
+        clearerr(stdin);
+        sleep(1);
+        c = getchar();
+    }
+    return c;
+}
+
+/* Reads the next number in the input, skipping anything before it.
+ * Returns whether there was a number before the input ended. */
+static inline int read_number(int32_t *number) {
+    int previous = EOF;
+    int c = read_byte();
+    while (c != EOF && (c < '0' || c > '9')) {
+        previous = c;
+        c = read_byte();
+    }
+    if (c == EOF) {
+        return 0;
+    }
+    int negative = previous == '-';
+    int64_t value = negative ? -(c - '0') : c - '0';
+    /* Digits that would overflow are left to be read as the next number */
+    while ((c = getchar()) >= '0' && c <= '9') {
+        int64_t next = value * 10 + (negative ? -(c - '0') : c - '0');
+        if (next > INT32_MAX || next < INT32_MIN) {
+            break;
+        }
+        value = next;
+    }
+    if (c != EOF) {
+        ungetc(c, stdin);
+    }
+    *number = (int32_t)value;
+    return 1;
+}
+
+/* Handles input having ended, returning whether the IP reflects */
+static inline int end_of_input(int32_t *value, int32_t x, int32_t y) {
+    switch (END_OF_INPUT) {
+    case INPUT_REFLECT:
+        return 1;
+    case INPUT_ERROR:
+        fail("Tried to read past the end of input", x, y);
+        return 1;
+    default:
+        *value = -1;
+        return 0;
+    }
+}
+
+/* Executes "&" or "~", returning whether the IP reflects */
+static inline int input(int number, int32_t x, int32_t y) {
+    int32_t value = 0;
+    if (number) {
+        if (!read_number(&value) && end_of_input(&value, x, y)) {
+            return 1;
+        }
+    } else {
+        int c = read_byte();
+        if (c != EOF) {
+            value = c;
+        } else if (end_of_input(&value, x, y)) {
+            return 1;
+        }
+    }
+    push(value);
+    return 0;
+}
+
+/* Executes "/" or "%", returning whether the IP reflects */
+static inline int divide(int remainder, int32_t x, int32_t y) {
+    int32_t upper = peek(0);
+    int32_t lower = peek(1);
+    int32_t result = 0;
+    if (upper == -1) {
+        /* Avoids overflowing the most negative number */
+        result = remainder ? 0 : wrap(0u - (uint32_t)lower);
+    } else if (upper != 0) {
+        result = remainder ? lower % upper : lower / upper;
+    } else if (DIVISION_BY_ZERO == DIVIDE_ZERO) {
+        result = 0;
+    } else if (DIVISION_BY_ZERO == DIVIDE_ERROR) {
+        fail("Division by zero", x, y);
+        return 1;
+    } else {
+        printf("What do you want %d%c0 to be? ", (int)lower, remainder ? '%' : '/');
+        if (!read_number(&result) && end_of_input(&result, x, y)) {
+            return 1;
+        }
+    }
+    pop();
+    pop();
+    push(result);
+    return 0;
+}
+
+static inline int in_grid(int32_t x, int32_t y) {
+    return x >= 0 && y >= 0 && x < COLS && y < ROWS;
+}
+
+static inline SparseCell *find_sparse(int32_t x, int32_t y) {
+    for (size_t i = 0; i < sparse_len; i++) {
+        if (sparse[i].x == x && sparse[i].y == y) {
+            return &sparse[i];
+        }
+    }
+    return NULL;
+}
+
+static inline int32_t get_cell(int32_t x, int32_t y) {
+    if (in_grid(x, y)) {
+        return grid[y * COLS + x];
+    }
+    SparseCell *cell = find_sparse(x, y);
+    return cell ? cell->value : ' ';
+}
+
+/* Writes a cell, returning whether the compiled code no longer matches the program */
+static inline int set_cell(int32_t x, int32_t y, int32_t value) {
+    if (in_grid(x, y)) {
+        int changed = grid[y * COLS + x] != value;
+        grid[y * COLS + x] = value;
+        return changed && code[y * COLS + x];
+    }
+    SparseCell *cell = find_sparse(x, y);
+    if (value == ' ') {
+        /* Writing an empty cell never needs to grow the space */
+        if (cell) {
+            *cell = sparse[--sparse_len];
+        }
+        return 0;
+    }
+    if (cell) {
+        cell->value = value;
+        return 0;
+    }
+    if (sparse_len == sparse_cap) {
+        sparse = grow(sparse, &sparse_cap, sizeof *sparse);
+    }
+    sparse[sparse_len++] = (SparseCell){x, y, value};
+    int grows = x < least_x || y < least_y || x > greatest_x || y > greatest_y;
+    least_x = x < least_x ? x : least_x;
+    least_y = y < least_y ? y : least_y;
+    greatest_x = x > greatest_x ? x : greatest_x;
+    greatest_y = y > greatest_y ? y : greatest_y;
+    return grows;
+}
+
+static inline void get(void) {
+    int32_t y = pop();
+    int32_t x = pop();
+    if (!STRICT_93) {
+        push(get_cell(x, y));
+    } else if (in_grid(x, y)) {
+        /* The reference implementation stores cells as signed chars */
+        push((int8_t)grid[y * COLS + x]);
+    } else {
+        push(0);
+    }
+}
+
+/* Executes "p", returning whether the compiled code no longer matches the program */
+static inline int put(void) {
+    int32_t y = pop();
+    int32_t x = pop();
+    int32_t value = pop();
+    if (!STRICT_93) {
+        return set_cell(x, y, value);
+    }
+    return in_grid(x, y) && set_cell(x, y, (uint8_t)value);
+}
+
+static inline void move(int32_t *x, int32_t *y, int dir) {
+    switch (dir) {
+    case RIGHT:
+        *x = *x >= greatest_x ? least_x : *x + 1;
+        break;
+    case LEFT:
+        *x = *x <= least_x ? greatest_x : *x - 1;
+        break;
+    case UP:
+        *y = *y <= least_y ? greatest_y : *y - 1;
+        break;
+    default:
+        *y = *y >= greatest_y ? least_y : *y + 1;
+        break;
+    }
+}
+
+/* Picks a direction with SplitMix64, like the interpreter */
+static inline int random_direction(void) {
+    random_state += 0x9E3779B97F4A7C15ull;
+    uint64_t z = random_state;
+    z = (z ^ (z >> 30)) * 0xBF58476D1CE4E5B9ull;
+    z = (z ^ (z >> 27)) * 0x94D049BB133111EBull;
+    z ^= z >> 31;
+    return (int)(z >> 62);
+}
+
+static inline void seed_random(void) {
+    random_state = HAS_SEED ? SEED : (uint64_t)time(NULL) ^ ((uint64_t)clock() << 32);
+}
+
+/* Moves past a cell, then past any spaces after it like the interpreter does */
+static inline void advance(int32_t *x, int32_t *y, int dir, int quoted) {
+    move(x, y, dir);
+    if (quoted) {
+        return;
+    }
+    int32_t start_x = *x, start_y = *y;
+    long cells = 1;
+    while (get_cell(*x, *y) == ' ') {
+        move(x, y, dir);
+        if (*x == start_x && *y == start_y) {
+            char message[80];
+            snprintf(message, sizeof message, "Infinite loop of %ld steps through %ld cells",
+                     cells, cells);
+            fail(message, start_x, start_y);
+        }
+        cells++;
+    }
+}
+
+/* Runs the rest of the program one cell at a time once the compiled code is out of date,
+ * starting after the instruction at (x, y) */
+static inline void interpret(int32_t x, int32_t y, int dir) {
+    int quoted = 0;
+    for (;;) {
+        advance(&x, &y, dir, quoted);
+        int32_t cell = get_cell(x, y);
+        if (quoted) {
+            if (cell == '"') {
+                quoted = 0;
+            } else {
+                push(cell);
+            }
+            continue;
+        }
+        switch (cell) {
+        case ' ': break;
+        case '0': case '1': case '2': case '3': case '4':
+        case '5': case '6': case '7': case '8': case '9':
+            push(cell - '0');
+            break;
+        case '+': add(); break;
+        case '-': subtract(); break;
+        case '*': multiply(); break;
+        case '`': greater(); break;
+        case '!': not(); break;
+        case ':': duplicate(); break;
+        case '\\': swap(); break;
+        case '$': discard(); break;
+        case '.': output_number(); break;
+        case ',': output_char(); break;
+        case 'g': get(); break;
+        case 'p': put(); break;
+        case '/': case '%':
+            if (divide(cell == '%', x, y)) {
+                dir ^= 1;
+            }
+            break;
+        case '&': case '~':
+            if (input(cell == '&', x, y)) {
+                dir ^= 1;
+            }
+            break;
+        case '>': dir = RIGHT; break;
+        case '<': dir = LEFT; break;
+        case '^': dir = UP; break;
+        case 'v': dir = DOWN; break;
+        case '?': dir = random_direction(); break;
+        case '_': dir = pop() ? LEFT : RIGHT; break;
+        case '|': dir = pop() ? UP : DOWN; break;
+        case '"': quoted = 1; break;
+        case '#': move(&x, &y, dir); break;
+        case '@': finish(); break;
+        default: {
+            char message[48];
+            /* Only printable ASCII is shown as a character, which Rust wouldn't escape */
+            if (cell >= ' ' && cell <= '~' && cell != '\'' && cell != '\\') {
+                snprintf(message, sizeof message, "Invalid opcode '%c' (%d) found", (char)cell, (int)cell);
+            } else {
+                snprintf(message, sizeof message, "Invalid opcode %d found", (int)cell);
+            }
+            fail(message, x, y);
+        }
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use std::fmt::Write;
+
+use crate::core::{DivisionByZero, EndOfInput};
+
+use super::{Block, Exit, Op, Program, direction_index};
+
+const RUNTIME: &str = include_str!("runtime.rs.in");
+
+/// Writes a program as Rust source code, with a match arm for every block
+pub fn emit(program: &Program) -> String {
+    let mut out = String::new();
+    writeln!(
+        out,
+        "// Compiled from Befunge-93 by bft. Build with: rustc -O main.rs"
+    )
+    .unwrap();
+    writeln!(out, "#![allow(dead_code, unreachable_code, unused_mut)]").unwrap();
+    writeln!(out).unwrap();
+
+    let settings = program.settings;
+    let division_by_zero = match settings.division_by_zero() {
+        DivisionByZero::Zero => "DIVIDE_ZERO",
+        DivisionByZero::Prompt => "DIVIDE_PROMPT",
+        DivisionByZero::Error => "DIVIDE_ERROR",
+    };
+    let end_of_input = match settings.end_of_input() {
+        EndOfInput::Wait => "INPUT_WAIT",
+        EndOfInput::PushNegative => "INPUT_PUSH_NEGATIVE",
+        EndOfInput::Reflect => "INPUT_REFLECT",
+        EndOfInput::Error => "INPUT_ERROR",
+    };
+    writeln!(out, "const ROWS: usize = {};", program.rows).unwrap();
+    writeln!(out, "const COLS: usize = {};", program.cols).unwrap();
+    writeln!(out, "const STRICT_93: bool = {};", settings.strict_93).unwrap();
+    writeln!(out, "const DIVISION_BY_ZERO: u8 = {division_by_zero};").unwrap();
+    writeln!(out, "const END_OF_INPUT: u8 = {end_of_input};").unwrap();
+    writeln!(out, "const SEED: Option<u64> = {:?};", program.seed).unwrap();
+    writeln!(out).unwrap();
+
+    writeln!(out, "static PROGRAM: [i32; ROWS * COLS] = [").unwrap();
+    write_rows(&mut out, program, |i| program.cells[i].to_string());
+    writeln!(out, "];").unwrap();
+    writeln!(out, "/// The cells the compiled code was built from").unwrap();
+    writeln!(out, "static CODE: [bool; ROWS * COLS] = [").unwrap();
+    write_rows(&mut out, program, |i| program.code[i].to_string());
+    writeln!(out, "];").unwrap();
+    writeln!(out).unwrap();
+
+    out.push_str(RUNTIME);
+    writeln!(out).unwrap();
+    writeln!(out, "fn main() {{").unwrap();
+    writeln!(out, "    let mut m = Machine::new();").unwrap();
+    writeln!(out, "    let mut block = 0;").unwrap();
+    writeln!(out, "    loop {{").unwrap();
+    writeln!(out, "        match block {{").unwrap();
+    for (id, block) in program.blocks.iter().enumerate() {
+        write_block(&mut out, id, block);
+    }
+    writeln!(out, "            _ => unreachable!(),").unwrap();
+    writeln!(out, "        }}").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out, "}}").unwrap();
+    out
+}
+
+/// Writes the values of the cells in an array initializer, one row per line
+fn write_rows(out: &mut String, program: &Program, value: impl Fn(usize) -> String) {
+    for y in 0..program.rows {
+        let row: Vec<_> = (0..program.cols)
+            .map(|x| value(y * program.cols + x))
+            .collect();
+        writeln!(out, "    {},", row.join(", ")).unwrap();
+    }
+}
+
+fn write_block(out: &mut String, id: usize, block: &Block) {
+    let entry = block.entry;
+    writeln!(out, "            // {} moving {}", entry.pos, entry.dir).unwrap();
+    writeln!(out, "            {id} => {{").unwrap();
+    for op in &block.ops {
+        let statement = match op {
+            Op::Push(value) => format!("m.push({value});"),
+            Op::Add => String::from("m.add();"),
+            Op::Subtract => String::from("m.subtract();"),
+            Op::Multiply => String::from("m.multiply();"),
+            Op::Greater => String::from("m.greater();"),
+            Op::Not => String::from("m.not();"),
+            Op::Duplicate => String::from("m.duplicate();"),
+            Op::Swap => String::from("m.swap();"),
+            Op::Discard => String::from("m.discard();"),
+            Op::OutputNumber => String::from("m.output_number();"),
+            Op::OutputChar => String::from("m.output_char();"),
+            Op::Get => String::from("m.get();"),
+            Op::Put { at, dir } => format!(
+                "if m.put() {{ m.interpret({}, {}, {}); }}",
+                at.x,
+                at.y,
+                direction_index(*dir)
+            ),
+            Op::Divide {
+                remainder,
+                at,
+                reflect,
+            } => {
+                let call = format!("m.divide({remainder}, {}, {})", at.x, at.y);
+                reflecting(call, *reflect)
+            }
+            Op::Input {
+                number,
+                at,
+                reflect,
+            } => {
+                let call = format!("m.input({number}, {}, {})", at.x, at.y);
+                reflecting(call, *reflect)
+            }
+        };
+        writeln!(out, "                {statement}").unwrap();
+    }
+    let next = match &block.exit {
+        Exit::Jump(next) => format!("block = {next};"),
+        Exit::Branch { zero, nonzero } => {
+            format!("block = if m.pop() != 0 {{ {nonzero} }} else {{ {zero} }};")
+        }
+        Exit::Random(blocks) => {
+            let blocks = blocks.map(|b| b.to_string()).join(", ");
+            format!("block = [{blocks}][m.random_direction()];")
+        }
+        Exit::Terminate => String::from("m.finish();"),
+        Exit::Fail { message, at } => format!("m.fail({message:?}, {}, {});", at.x, at.y),
+    };
+    writeln!(out, "                {next}").unwrap();
+    writeln!(out, "            }}").unwrap();
+}
+
+/// A statement that runs a call and jumps to another block if it reflects
+fn reflecting(call: String, reflect: Option<usize>) -> String {
+    match reflect {
+        Some(block) => format!("if {call} {{ block = {block}; continue; }}"),
+        None => format!("{call};"),
+    }
+}
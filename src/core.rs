@@ -1,14 +1,15 @@
 use core::fmt;
+use std::path::Path;
 
 #[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Default)]
 /// Represents a 2d position in the program space
 pub struct Position {
     /// The x dimension
     /// Corresponds to the column, indexed left to right.
-    pub x: u8,
+    pub x: i32,
     /// The y dimension
     /// Corresponds to the row, indexed from top to bottom.
-    pub y: u8,
+    pub y: i32,
 }
 
 impl fmt::Display for Position {
@@ -32,6 +33,159 @@ pub enum Direction {
     Left,
     /// The positive x direction
     Right,
+    /// Any other change in x and y, which only Funge-98's "x" sets.
+    /// Made with [`Direction::from_delta`], so it is never one of the cardinal directions.
+    Delta(i32, i32),
+}
+
+impl Direction {
+    /// The direction that moves by a change in x and y each step
+    pub fn from_delta(dx: i32, dy: i32) -> Direction {
+        match (dx, dy) {
+            (0, -1) => Direction::Up,
+            (0, 1) => Direction::Down,
+            (-1, 0) => Direction::Left,
+            (1, 0) => Direction::Right,
+            _ => Direction::Delta(dx, dy),
+        }
+    }
+
+    /// The change in x and y from moving one step in the direction
+    pub fn delta(self) -> (i32, i32) {
+        match self {
+            Direction::Up => (0, -1),
+            Direction::Down => (0, 1),
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+            Direction::Delta(dx, dy) => (dx, dy),
+        }
+    }
+
+    /// Whether the direction is up, down, left or right
+    pub fn is_cardinal(self) -> bool {
+        !matches!(self, Direction::Delta(..))
+    }
+
+    /// The direction after a 90 degree counterclockwise turn
+    pub fn turn_left(self) -> Direction {
+        let (dx, dy) = self.delta();
+        Direction::from_delta(dy, dx.wrapping_neg())
+    }
+
+    /// The direction after a 90 degree clockwise turn
+    pub fn turn_right(self) -> Direction {
+        let (dx, dy) = self.delta();
+        Direction::from_delta(dy.wrapping_neg(), dx)
+    }
+
+    /// The opposite direction
+    pub fn reverse(self) -> Direction {
+        let (dx, dy) = self.delta();
+        Direction::from_delta(dx.wrapping_neg(), dy.wrapping_neg())
+    }
+
+    /// The instruction that sends the IP in this direction
+    pub fn arrow(self) -> char {
+        match self {
+            Direction::Up => '^',
+            Direction::Down => 'v',
+            Direction::Left => '<',
+            Direction::Right => '>',
+            Direction::Delta(..) => 'x',
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Direction::Up => "up",
+            Direction::Down => "down",
+            Direction::Left => "left",
+            Direction::Right => "right",
+            Direction::Delta(dx, dy) => return write!(f, "({dx}, {dy})"),
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Default, clap::ValueEnum)]
+/// The member of the Funge family a program is written in
+pub enum Dialect {
+    /// Befunge-93
+    /// Unknown opcodes are errors.
+    #[default]
+    #[value(alias = "93")]
+    Befunge93,
+    /// Funge-98 (Befunge only)
+    /// Adds the core Funge-98 instructions and unknown opcodes reflect the cursor.
+    #[value(alias = "98")]
+    Funge98,
+}
+
+impl Dialect {
+    /// Guesses the dialect of a program from its file extension
+    pub fn from_path(path: &Path) -> Option<Dialect> {
+        let extension = path.extension()?.to_str()?;
+        match extension {
+            "b93" | "bf" => Some(Dialect::Befunge93),
+            "b98" | "bf98" => Some(Dialect::Funge98),
+            _ => None,
+        }
+    }
+
+    /// What input instructions do by default once input has ended
+    pub fn end_of_input(self) -> EndOfInput {
+        match self {
+            Dialect::Befunge93 => EndOfInput::PushNegative,
+            Dialect::Funge98 => EndOfInput::Reflect,
+        }
+    }
+
+    /// How the dialect specifies division and modulo by zero
+    pub fn division_by_zero(self) -> DivisionByZero {
+        match self {
+            Dialect::Befunge93 => DivisionByZero::Prompt,
+            Dialect::Funge98 => DivisionByZero::Zero,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, clap::ValueEnum)]
+/// What "/" and "%" do when the divisor is zero
+pub enum DivisionByZero {
+    /// Push zero like Funge-98
+    Zero,
+    /// Ask the user for the result like Befunge-93
+    Prompt,
+    /// Stop with an error
+    Error,
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, clap::ValueEnum)]
+/// What "~" and "&" do once input has ended
+pub enum EndOfInput {
+    /// Keep waiting for more input
+    Wait,
+    /// Push -1 like the reference Befunge-93 implementation
+    #[value(name = "push-1")]
+    PushNegative,
+    /// Reflect the cursor like Funge-98
+    Reflect,
+    /// Stop with an error
+    Error,
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Default)]
+/// What happens when an instruction pops from an empty stack and gets an implicit zero
+pub enum Underflow {
+    /// Nothing, as every dialect allows it
+    #[default]
+    Allow,
+    /// Report where it happened once the program stops
+    Warn,
+    /// Stop with an error
+    Deny,
 }
 
 #[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
@@ -69,31 +223,44 @@ impl Default for Cursor {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct GridCell(pub u8);
+pub struct GridCell(pub i32);
+
+impl GridCell {
+    /// The instruction stored in this cell, if it fits in a single byte
+    pub fn opcode(self) -> Option<u8> {
+        u8::try_from(self.0).ok()
+    }
+}
 
 impl Default for GridCell {
     fn default() -> Self {
-        Self(b' ')
+        Self(b' ' as i32)
     }
 }
 
 impl From<u8> for GridCell {
     fn from(value: u8) -> Self {
-        GridCell(value)
+        GridCell(value as i32)
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+impl From<char> for GridCell {
+    fn from(value: char) -> Self {
+        GridCell(value as i32)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct StackCell(pub i32);
 
 impl From<GridCell> for StackCell {
     fn from(value: GridCell) -> Self {
-        StackCell(value.0 as i32)
+        StackCell(value.0)
     }
 }
 
 impl From<StackCell> for GridCell {
     fn from(value: StackCell) -> Self {
-        GridCell(value.0 as u8)
+        GridCell(value.0)
     }
 }
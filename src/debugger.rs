@@ -1,20 +1,25 @@
 use std::collections::HashSet;
 
-use crate::{
-    analyze::{self, PathAnalysis},
-    core::Position,
-    interpreter::{Interpreter, Status},
-    record::Timeline,
-    space::Space,
-    terminal::VirtualTerminal,
+use bft::{
+    InstructionPointer, Interpreter, InterpreterError, PathAnalysis, Position, SeededRandom,
+    Settings, Snapshot, Status, Timeline, Trace, analyze_path,
 };
 
+use crate::terminal::VirtualTerminal;
+
 pub struct Debugger {
-    #[allow(dead_code)]
-    program: Vec<u8>,
     pub analysis: PathAnalysis,
     pub interpreter: Interpreter<VirtualTerminal, Timeline>,
     pub breakpoints: HashSet<Position>,
+    /// The IP whose stack is shown, or None to follow whichever IP executes next
+    pub selected_ip: Option<u32>,
+    /// The error that last paused the program, until it has been shown
+    error: Option<InterpreterError>,
+    /// Whether to pause after any step that pops from an empty stack
+    break_on_underflow: bool,
+    /// Whether no step can be executed after the last one in the timeline,
+    /// because the program terminated or the timeline is a trace of a run that is over
+    terminated: bool,
 
     state: State,
     ticks_per_step: u16,
@@ -24,21 +29,70 @@ pub struct Debugger {
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 enum State {
     Paused,
-    Stepping { steps: u16 },
+    Stepping {
+        steps: u16,
+    },
     Running,
+    /// Stepping back through the timeline a number of steps
+    Rewinding {
+        steps: u16,
+    },
+    /// Stepping back through the timeline until a breakpoint
+    RunningBack,
     Halted,
 }
 
 impl Debugger {
-    pub fn new(program: Vec<u8>) -> Self {
-        let space = Space::new(&program);
-        let analysis = analyze::analyze_path(&space);
-        let interpreter = Interpreter::new(space, VirtualTerminal::default(), Timeline::default());
+    pub fn new(
+        program: &[u8],
+        settings: Settings,
+        random: SeededRandom,
+        timeline: Timeline,
+    ) -> Self {
+        let interpreter = Interpreter::builder()
+            .with_settings(settings)
+            .with_io(VirtualTerminal::default())
+            .with_recorder(timeline)
+            .with_random(random)
+            .build(program);
+        Self::with_interpreter(interpreter)
+    }
+
+    /// Opens the debugger at the exact point a snapshot was taken
+    pub fn from_snapshot(snapshot: Snapshot, timeline: Timeline) -> Self {
+        let io = VirtualTerminal::with_pending_input(snapshot.input.clone());
+        let interpreter = Interpreter::from_snapshot(snapshot, io, timeline);
+        Self::with_interpreter(interpreter)
+    }
+
+    /// Opens a trace to step back and forth through, without running the program any further
+    pub fn from_trace(trace: &Trace, timeline: Timeline) -> Self {
+        let io = VirtualTerminal::default();
+        let mut interpreter = Interpreter::from_snapshot(trace.start.clone(), io, timeline);
+        for (step, events) in trace.step_events() {
+            interpreter.apply_step(step, events);
+        }
+        let start = interpreter.recorder().start();
+        interpreter.seek(start);
+        let mut debugger = Self::with_interpreter(interpreter);
+        debugger.terminated = true;
+        if !debugger.timeline().can_step_forward() {
+            debugger.state = State::Halted;
+        }
+        debugger
+    }
+
+    fn with_interpreter(interpreter: Interpreter<VirtualTerminal, Timeline>) -> Self {
+        let dialect = interpreter.settings().dialect;
+        let analysis = analyze_path(interpreter.space(), dialect);
         Self {
-            program,
             analysis,
             interpreter,
             breakpoints: Default::default(),
+            selected_ip: None,
+            error: None,
+            break_on_underflow: false,
+            terminated: false,
 
             state: State::Paused,
             ticks_per_step: 2,
@@ -49,10 +103,11 @@ impl Debugger {
     pub fn tick(&mut self) -> bool {
         self.ticks_since_step += 1;
         let time_for_step = self.ticks_since_step > self.ticks_per_step;
+        let backwards = matches!(self.state, State::Rewinding { .. } | State::RunningBack);
 
         let step_now = match self.state {
             State::Paused | State::Halted => false,
-            State::Stepping { steps: 1 } => {
+            State::Stepping { steps: 0 | 1 } => {
                 if time_for_step {
                     self.state = State::Paused;
                 }
@@ -64,32 +119,75 @@ impl Debugger {
                 }
                 time_for_step
             }
-            State::Running => time_for_step,
+            State::Running | State::RunningBack => time_for_step,
+            State::Rewinding { steps } => {
+                if time_for_step {
+                    self.state = match steps {
+                        0 | 1 => State::Paused,
+                        _ => State::Rewinding { steps: steps - 1 },
+                    };
+                }
+                time_for_step
+            }
         };
 
         if step_now {
             self.ticks_since_step = 0;
             let pos = self.interpreter.current_position();
-            if self.breakpoints.contains(&pos) {
+            if backwards {
+                self.step_back();
+            } else if self.breakpoints.contains(&pos) {
                 self.state = State::Paused;
+            } else if self.interpreter.recorder().can_step_forward() {
+                // Steps that were stepped back over are redone rather than executed again,
+                // since the input they read has already been used
+                self.interpreter.step_forward();
+                if self.terminated && !self.interpreter.recorder().can_step_forward() {
+                    self.state = State::Halted;
+                }
             } else {
+                let underflows = self.interpreter.underflows().total();
                 let status = self.interpreter.step();
+                if self.break_on_underflow && self.interpreter.underflows().total() > underflows {
+                    log::info!("Popped from an empty stack at {}", pos);
+                    self.state = State::Paused;
+                }
                 match status {
                     Status::Completed => {}
                     Status::Waiting => {}
-                    Status::Terminated => self.state = State::Halted,
-                    Status::Error(interpreter_error) => log::error!("{}", interpreter_error),
+                    Status::Terminated => {
+                        self.state = State::Halted;
+                        self.terminated = true;
+                    }
+                    Status::Error(interpreter_error) => {
+                        log::error!("{}", interpreter_error);
+                        self.state = State::Paused;
+                        self.error = Some(interpreter_error);
+                    }
                 }
             }
         }
         step_now
     }
 
+    /// Undoes a step, pausing at the start of the timeline
+    /// or, when running back, at a breakpoint
+    fn step_back(&mut self) {
+        if !self.interpreter.step_back() {
+            self.state = State::Paused;
+            return;
+        }
+        let pos = self.interpreter.current_position();
+        if self.state == State::RunningBack && self.breakpoints.contains(&pos) {
+            self.state = State::Paused;
+        }
+    }
+
     pub fn add_steps(&mut self, steps: u16) {
         self.state = match self.state {
             State::Halted => State::Halted,
             State::Stepping { steps: current } => State::Stepping {
-                steps: current + steps,
+                steps: current.saturating_add(steps),
             },
             _ => State::Stepping { steps },
         };
@@ -102,6 +200,39 @@ impl Debugger {
         self.state = State::Running;
     }
 
+    /// Steps back through the timeline, even once the program has halted
+    pub fn add_reverse_steps(&mut self, steps: u16) {
+        self.state = match self.state {
+            State::Rewinding { steps: current } => State::Rewinding {
+                steps: current.saturating_add(steps),
+            },
+            _ => State::Rewinding { steps },
+        };
+    }
+
+    /// Steps back through the timeline until the IP is at a breakpoint
+    pub fn start_running_back(&mut self) {
+        self.state = State::RunningBack;
+    }
+
+    /// Pauses at a step in the timeline, returning whether it is still kept
+    pub fn seek(&mut self, step: usize) -> bool {
+        if !self.interpreter.seek(step) {
+            return false;
+        }
+        self.state = if self.terminated && !self.interpreter.recorder().can_step_forward() {
+            State::Halted
+        } else {
+            State::Paused
+        };
+        true
+    }
+
+    /// The steps that can be gone back to
+    pub fn timeline(&self) -> &Timeline {
+        self.interpreter.recorder()
+    }
+
     pub fn pause(&mut self) {
         if self.state == State::Halted {
             return;
@@ -109,6 +240,11 @@ impl Debugger {
         self.state = State::Paused;
     }
 
+    /// The error that paused the program, if it hasn't been taken already
+    pub fn take_error(&mut self) -> Option<InterpreterError> {
+        self.error.take()
+    }
+
     pub fn io(&self) -> &VirtualTerminal {
         self.interpreter.io()
     }
@@ -123,11 +259,43 @@ impl Debugger {
         }
     }
 
+    /// Toggles pausing whenever a step pops from an empty stack, returning whether it is now on
+    pub fn toggle_break_on_underflow(&mut self) -> bool {
+        self.break_on_underflow = !self.break_on_underflow;
+        self.break_on_underflow
+    }
+
+    /// Shows the stack of a specific IP, or of whichever IP executes next if None
+    pub fn select_ip(&mut self, id: Option<u32>) {
+        self.selected_ip = id;
+    }
+
+    /// The IP whose stacks are shown in the sidebar.
+    /// Falls back to the next IP if the selected IP has terminated.
+    pub fn shown_ip(&self) -> &InstructionPointer {
+        let mut ips = self.interpreter.ips();
+        let next = ips.next().expect("there is always an IP");
+        self.selected_ip
+            .and_then(|id| std::iter::once(next).chain(ips).find(|ip| ip.id == id))
+            .unwrap_or(next)
+    }
+
+    /// The number of slots needed to show every stack of the shown IP,
+    /// including a separator between each stack
     pub fn stack_height(&self) -> u16 {
-        self.interpreter.stack().len() as u16
+        let ip = self.shown_ip();
+        let below: usize = ip.stacks_below.iter().map(|stack| stack.len() + 1).sum();
+        (ip.stack.len() + below) as u16
     }
 
     pub fn current_position(&self) -> Position {
         self.interpreter.current_position()
     }
+
+    /// The positions of the IPs other than the one that executes next
+    pub fn other_positions(&self) -> Vec<Position> {
+        let mut ips = self.interpreter.ips();
+        ips.next();
+        ips.map(|ip| ip.cursor.pos).collect()
+    }
 }
@@ -0,0 +1,239 @@
+use std::{cmp, fmt::Write, path::Path};
+
+use unicode_width::UnicodeWidthChar;
+
+use crate::{
+    core::{GridCell, Position},
+    interpreter::{ErrorKind, InterpreterError, Underflows},
+    space::Space,
+};
+
+/// The number of rows shown above and below the failing cell
+const CONTEXT_ROWS: i32 = 2;
+/// The most columns of a row that are shown
+const MAX_COLUMNS: i32 = 80;
+/// The most cells of an infinite loop that are listed
+const MAX_LOOP_CELLS: usize = 16;
+/// The most positions that popped from an empty stack that are listed
+const MAX_UNDERFLOW_LOCATIONS: usize = 10;
+
+/// Renders an error in the style of rustc's diagnostics,
+/// showing the rows of the program around the failing cell with a caret under it.
+///
+/// ```text
+/// error: Division by zero
+///  --> programs/example.b93:1:3
+///   |
+/// 1 | 50/.@
+///   |   ^ moving right after 2 steps
+///   |
+///   = stack: 5 0 (top last)
+/// ```
+pub fn render_error(error: &InterpreterError, space: &Space<GridCell>, path: &Path) -> String {
+    let Position { x, y } = error.position;
+    let least = space.least();
+    let greatest = space.greatest();
+    let first_row = cmp::max(least.y, y.saturating_sub(CONTEXT_ROWS));
+    let last_row = cmp::min(greatest.y, y.saturating_add(CONTEXT_ROWS));
+    let first_col = cmp::max(least.x, x.saturating_sub(MAX_COLUMNS / 2));
+    let last_col = cmp::min(greatest.x, first_col.saturating_add(MAX_COLUMNS - 1));
+
+    // Rows are numbered from one like lines in an editor
+    let row_number = |row: i32| row as i64 + 1;
+    let gutter = cmp::max(
+        row_number(first_row).to_string().len(),
+        row_number(last_row).to_string().len(),
+    );
+    let blank = "";
+
+    let mut out = String::new();
+    writeln!(out, "error: {}", error.kind).unwrap();
+    writeln!(
+        out,
+        "{blank:gutter$}--> {}:{}:{}",
+        path.display(),
+        row_number(y),
+        x as i64 + 1
+    )
+    .unwrap();
+    writeln!(out, "{blank:gutter$} |").unwrap();
+    for row in first_row..=last_row {
+        let text: String = (first_col..=last_col)
+            .map(|col| display_char(space.get_cell(Position { x: col, y: row })))
+            .collect();
+        let number = row_number(row);
+        let line = format!("{number:>gutter$} | {text}");
+        writeln!(out, "{}", line.trim_end()).unwrap();
+        if row == y {
+            let offset = (x - first_col) as usize;
+            let steps = match error.step {
+                1 => String::from("1 step"),
+                n => format!("{n} steps"),
+            };
+            let direction = error.direction;
+            writeln!(
+                out,
+                "{blank:gutter$} | {blank:offset$}^ moving {direction} after {steps}"
+            )
+            .unwrap();
+        }
+    }
+    writeln!(out, "{blank:gutter$} |").unwrap();
+
+    let cells: Vec<_> = error
+        .stack_top
+        .iter()
+        .map(|cell| cell.0.to_string())
+        .collect();
+    match error.stack_size {
+        0 => writeln!(out, "{blank:gutter$} = stack: empty").unwrap(),
+        size if size > cells.len() => writeln!(
+            out,
+            "{blank:gutter$} = stack: .. {} (top last, {size} cells)",
+            cells.join(" ")
+        )
+        .unwrap(),
+        _ => writeln!(
+            out,
+            "{blank:gutter$} = stack: {} (top last)",
+            cells.join(" ")
+        )
+        .unwrap(),
+    }
+    if error.ip != 0 {
+        writeln!(out, "{blank:gutter$} = note: in IP {}", error.ip).unwrap();
+    }
+    if let ErrorKind::InfiniteLoop { cells, .. } = &error.kind {
+        let shown: Vec<_> = cells
+            .iter()
+            .take(MAX_LOOP_CELLS)
+            .map(|pos| pos.to_string())
+            .collect();
+        write!(
+            out,
+            "{blank:gutter$} = note: looping through {}",
+            shown.join(" ")
+        )
+        .unwrap();
+        if cells.len() > MAX_LOOP_CELLS {
+            write!(out, " and {} more", cells.len() - MAX_LOOP_CELLS).unwrap();
+        }
+        writeln!(out).unwrap();
+    }
+    out
+}
+
+/// Summarizes where a program popped from an empty stack, most frequent first.
+///
+/// ```text
+/// warning: 3 pops from an empty stack
+///  --> programs/example.b93:1:4 (2 times)
+///  --> programs/example.b93:2:1 (once)
+/// ```
+pub fn render_underflows(underflows: &Underflows, path: &Path) -> String {
+    let mut locations = underflows.locations();
+    // Stable, so positions with the same count stay in reading order
+    locations.sort_by_key(|(_, count)| cmp::Reverse(*count));
+
+    let mut out = String::new();
+    let total = underflows.total();
+    let pops = if total == 1 { "pop" } else { "pops" };
+    writeln!(out, "warning: {total} {pops} from an empty stack").unwrap();
+    for (pos, count) in locations.iter().take(MAX_UNDERFLOW_LOCATIONS) {
+        let times = match count {
+            1 => String::from("once"),
+            n => format!("{n} times"),
+        };
+        let (line, col) = (pos.y as i64 + 1, pos.x as i64 + 1);
+        writeln!(out, " --> {}:{line}:{col} ({times})", path.display()).unwrap();
+    }
+    if locations.len() > MAX_UNDERFLOW_LOCATIONS {
+        let more = locations.len() - MAX_UNDERFLOW_LOCATIONS;
+        writeln!(out, "  = note: and {more} more locations").unwrap();
+    }
+    out
+}
+
+/// Shows every cell as a single column so the caret lines up
+fn display_char(cell: GridCell) -> char {
+    match char::from_u32(cell.0 as u32) {
+        Some(c) if c.width() == Some(1) => c,
+        _ => char::REPLACEMENT_CHARACTER,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{Direction, StackCell};
+
+    fn error(kind: ErrorKind, position: Position, stack: &[i32]) -> InterpreterError {
+        InterpreterError {
+            kind,
+            ip: 0,
+            position,
+            direction: Direction::Right,
+            step: 2,
+            stack_top: stack.iter().copied().map(StackCell).collect(),
+            stack_size: stack.len(),
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let space = Space::new(b"v\n>50/.@\n\n^  <\n");
+        let error = error(ErrorKind::DivisionByZero, Position { x: 3, y: 1 }, &[5, 0]);
+        let expected = "\
+error: Division by zero
+ --> test.b93:2:4
+  |
+1 | v
+2 | >50/.@
+  |    ^ moving right after 2 steps
+3 |
+4 | ^  <
+  |
+  = stack: 5 0 (top last)
+";
+        assert_eq!(
+            expected,
+            render_error(&error, &space, Path::new("test.b93"))
+        );
+    }
+
+    #[test]
+    fn test_render_wide_row() {
+        let mut program = vec![b' '; 200];
+        program[150] = b'x';
+        let space = Space::new(&program);
+        let mut error = error(
+            ErrorKind::InvalidOpcode(b'x' as i32),
+            Position { x: 150, y: 0 },
+            &[1, 2, 3, 4],
+        );
+        error.stack_size = 10;
+        let rendered = render_error(&error, &space, Path::new("wide.b93"));
+        let lines: Vec<_> = rendered.lines().collect();
+        assert_eq!(" --> wide.b93:1:151", lines[1]);
+        // The row starts 40 columns before the failing cell
+        assert_eq!(format!("1 | {}x", " ".repeat(40)), lines[3]);
+        assert_eq!(lines[3].find('x'), lines[4].find('^'));
+        assert_eq!("  = stack: .. 1 2 3 4 (top last, 10 cells)", lines[6]);
+    }
+
+    #[test]
+    fn test_render_underflows() {
+        let mut underflows = Underflows::default();
+        underflows.add(Position { x: 3, y: 1 }, 1);
+        underflows.add(Position { x: 0, y: 2 }, 1);
+        underflows.add(Position { x: 5, y: 0 }, 2);
+        let expected = "\
+warning: 4 pops from an empty stack
+ --> test.b93:1:6 (2 times)
+ --> test.b93:2:4 (once)
+ --> test.b93:3:1 (once)
+";
+        let rendered = render_underflows(&underflows, Path::new("test.b93"));
+        assert_eq!(expected, rendered);
+    }
+}
@@ -0,0 +1,198 @@
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use crate::{
+    core::{Direction, GridCell, Position, StackCell},
+    interpreter::Status,
+};
+
+mod standard;
+
+pub use standard::{Bool, Modu, Null, Roma};
+
+/// A Funge-98 fingerprint, a set of semantics for the instructions "A" to "Z"
+/// that programs load with "(" and unload with ")".
+pub trait Fingerprint: Send + Sync {
+    /// The fingerprint's ID, which is usually its name packed into an integer (see [`fingerprint_id`])
+    fn id(&self) -> i32;
+
+    /// The instructions from "A" to "Z" that the fingerprint defines
+    fn instructions(&self) -> &[u8];
+
+    /// Executes one of the fingerprint's instructions.
+    /// The instruction pointer moves past the instruction afterwards if it returns Completed.
+    fn execute(&self, instruction: u8, context: &mut dyn Context) -> Status;
+}
+
+/// The parts of the interpreter that fingerprint instructions can use
+pub trait Context {
+    /// Pops a value from the current IP's stack, or zero if it is empty
+    fn pop(&mut self) -> StackCell;
+    /// Pushes a value onto the current IP's stack
+    fn push(&mut self, cell: StackCell);
+    /// Reverses the current IP's direction
+    fn reflect(&mut self);
+    /// The current IP's position
+    fn position(&self) -> Position;
+    /// The current IP's direction
+    fn direction(&self) -> Direction;
+    /// Changes the current IP's direction
+    fn set_direction(&mut self, dir: Direction);
+    /// Reads a cell of the program space
+    fn get(&self, pos: Position) -> GridCell;
+    /// Writes a cell of the program space
+    fn put(&mut self, pos: Position, cell: GridCell);
+    /// Writes output
+    fn write(&mut self, buf: &[u8]);
+}
+
+/// Packs a fingerprint name into its ID the same way "(" does
+pub const fn fingerprint_id(name: &[u8]) -> i32 {
+    let mut id: i32 = 0;
+    let mut i = 0;
+    while i < name.len() {
+        id = id.wrapping_mul(256).wrapping_add(name[i] as i32);
+        i += 1;
+    }
+    id
+}
+
+/// A shared handle to a fingerprint.
+/// Two handles are equal if they are for fingerprints with the same ID.
+#[derive(Clone)]
+pub struct FingerprintRef(Arc<dyn Fingerprint>);
+
+impl FingerprintRef {
+    pub fn new(fingerprint: impl Fingerprint + 'static) -> Self {
+        Self(Arc::new(fingerprint))
+    }
+
+    pub fn id(&self) -> i32 {
+        self.0.id()
+    }
+
+    pub fn instructions(&self) -> &[u8] {
+        self.0.instructions()
+    }
+
+    pub fn execute(&self, instruction: u8, context: &mut dyn Context) -> Status {
+        self.0.execute(instruction, context)
+    }
+}
+
+impl PartialEq for FingerprintRef {
+    fn eq(&self, other: &Self) -> bool {
+        self.id() == other.id()
+    }
+}
+
+impl Eq for FingerprintRef {}
+
+impl fmt::Debug for FingerprintRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes = self.id().to_be_bytes();
+        let name: String = bytes
+            .iter()
+            .filter(|b| **b != 0)
+            .map(|b| *b as char)
+            .collect();
+        write!(f, "Fingerprint({:?}, {:#x})", name, self.id())
+    }
+}
+
+/// The fingerprints that programs are able to load, by ID
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Fingerprints {
+    registered: HashMap<i32, FingerprintRef>,
+}
+
+impl Default for Fingerprints {
+    /// The standard fingerprints that are always available
+    fn default() -> Self {
+        let mut fingerprints = Self::empty();
+        fingerprints.register(Null);
+        fingerprints.register(Roma);
+        fingerprints.register(Modu);
+        fingerprints.register(Bool);
+        fingerprints
+    }
+}
+
+impl Fingerprints {
+    /// A registry without any fingerprints
+    pub fn empty() -> Self {
+        Self {
+            registered: HashMap::new(),
+        }
+    }
+
+    /// Makes a fingerprint available to programs, replacing any with the same ID
+    pub fn register(&mut self, fingerprint: impl Fingerprint + 'static) {
+        let fingerprint = FingerprintRef::new(fingerprint);
+        self.registered.insert(fingerprint.id(), fingerprint);
+    }
+
+    pub fn get(&self, id: i32) -> Option<&FingerprintRef> {
+        self.registered.get(&id)
+    }
+}
+
+/// The stack of loaded semantics for each instruction from "A" to "Z"
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub struct Semantics {
+    stacks: [Vec<FingerprintRef>; 26],
+}
+
+impl Semantics {
+    /// Pushes the fingerprint's semantics onto the stacks of the instructions it defines
+    pub fn load(&mut self, fingerprint: &FingerprintRef) {
+        for &instruction in fingerprint.instructions() {
+            if let Some(stack) = self.stack_mut(instruction) {
+                stack.push(fingerprint.clone());
+            }
+        }
+    }
+
+    /// Pops the semantics of the instructions the fingerprint defines,
+    /// even if they were loaded by a different fingerprint
+    pub fn unload(&mut self, fingerprint: &FingerprintRef) {
+        for &instruction in fingerprint.instructions() {
+            if let Some(stack) = self.stack_mut(instruction) {
+                stack.pop();
+            }
+        }
+    }
+
+    /// The IDs of the fingerprints loaded for each instruction from "A" to "Z",
+    /// from the first loaded to the last
+    pub fn ids(&self) -> impl Iterator<Item = Vec<i32>> + '_ {
+        self.stacks
+            .iter()
+            .map(|stack| stack.iter().map(FingerprintRef::id).collect())
+    }
+
+    /// Recreates semantics from the IDs given by [`Semantics::ids`],
+    /// returning the first ID that isn't registered if there is one
+    pub fn from_ids(
+        ids: impl IntoIterator<Item = Vec<i32>>,
+        fingerprints: &Fingerprints,
+    ) -> Result<Self, i32> {
+        let mut semantics = Self::default();
+        for (stack, ids) in semantics.stacks.iter_mut().zip(ids) {
+            for id in ids {
+                stack.push(fingerprints.get(id).ok_or(id)?.clone());
+            }
+        }
+        Ok(semantics)
+    }
+
+    /// The fingerprint that currently defines an instruction
+    pub fn get(&self, instruction: u8) -> Option<&FingerprintRef> {
+        let index = instruction.checked_sub(b'A')? as usize;
+        self.stacks.get(index)?.last()
+    }
+
+    fn stack_mut(&mut self, instruction: u8) -> Option<&mut Vec<FingerprintRef>> {
+        let index = instruction.checked_sub(b'A')? as usize;
+        self.stacks.get_mut(index)
+    }
+}
@@ -0,0 +1,118 @@
+use crate::{core::StackCell, interpreter::Status};
+
+use super::{Context, Fingerprint, fingerprint_id};
+
+const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// "NULL" makes every instruction from "A" to "Z" reflect
+pub struct Null;
+
+impl Fingerprint for Null {
+    fn id(&self) -> i32 {
+        fingerprint_id(b"NULL")
+    }
+
+    fn instructions(&self) -> &[u8] {
+        ALPHABET
+    }
+
+    fn execute(&self, _instruction: u8, context: &mut dyn Context) -> Status {
+        context.reflect();
+        Status::Completed
+    }
+}
+
+/// "ROMA" pushes the values of roman numerals
+pub struct Roma;
+
+impl Fingerprint for Roma {
+    fn id(&self) -> i32 {
+        fingerprint_id(b"ROMA")
+    }
+
+    fn instructions(&self) -> &[u8] {
+        b"CDILMVX"
+    }
+
+    fn execute(&self, instruction: u8, context: &mut dyn Context) -> Status {
+        let value = match instruction {
+            b'C' => 100,
+            b'D' => 500,
+            b'I' => 1,
+            b'L' => 50,
+            b'M' => 1000,
+            b'V' => 5,
+            b'X' => 10,
+            _ => unreachable!("ROMA does not define {}", instruction as char),
+        };
+        context.push(StackCell(value));
+        Status::Completed
+    }
+}
+
+/// "MODU" adds modulo operations with different sign conventions.
+/// Like "%" in Funge-98 they push zero when dividing by zero.
+pub struct Modu;
+
+impl Fingerprint for Modu {
+    fn id(&self) -> i32 {
+        fingerprint_id(b"MODU")
+    }
+
+    fn instructions(&self) -> &[u8] {
+        b"MRU"
+    }
+
+    fn execute(&self, instruction: u8, context: &mut dyn Context) -> Status {
+        let b = context.pop().0;
+        let a = context.pop().0;
+        let result = if b == 0 {
+            0
+        } else {
+            let remainder = a.wrapping_rem(b);
+            match instruction {
+                // Signed-result modulo, which takes the sign of the divisor
+                b'M' if remainder != 0 && (remainder < 0) != (b < 0) => remainder + b,
+                b'M' => remainder,
+                // C-style remainder, which takes the sign of the dividend
+                b'R' => remainder,
+                // Unsigned-result modulo
+                b'U' => remainder.wrapping_abs(),
+                _ => unreachable!("MODU does not define {}", instruction as char),
+            }
+        };
+        context.push(StackCell(result));
+        Status::Completed
+    }
+}
+
+/// "BOOL" adds bitwise logic operations
+pub struct Bool;
+
+impl Fingerprint for Bool {
+    fn id(&self) -> i32 {
+        fingerprint_id(b"BOOL")
+    }
+
+    fn instructions(&self) -> &[u8] {
+        b"ANOX"
+    }
+
+    fn execute(&self, instruction: u8, context: &mut dyn Context) -> Status {
+        let result = match instruction {
+            b'N' => !context.pop().0,
+            _ => {
+                let b = context.pop().0;
+                let a = context.pop().0;
+                match instruction {
+                    b'A' => a & b,
+                    b'O' => a | b,
+                    b'X' => a ^ b,
+                    _ => unreachable!("BOOL does not define {}", instruction as char),
+                }
+            }
+        };
+        context.push(StackCell(result));
+        Status::Completed
+    }
+}
@@ -1,23 +1,142 @@
+use std::{collections::VecDeque, time::Duration};
+
 use thiserror::Error;
 
 use crate::{
-    core::{Cursor, Direction, GridCell, Mode, Position, StackCell},
-    io::{IO, StdIO},
-    record::Record,
-    space::Space,
+    core::{
+        Cursor, Dialect, Direction, DivisionByZero, EndOfInput, GridCell, Mode, Position,
+        StackCell, Underflow,
+    },
+    fingerprint::{Context, Fingerprint, Fingerprints},
+    io::{IO, Input, StdIO},
+    random::{Random, SeededRandom},
+    record::{Checkpoint, Event, Record, Step, Timeline},
+    snapshot::Snapshot,
+    space::{Space, in_torus_93},
 };
 
-#[derive(PartialEq, Eq, Clone, Debug)]
+mod builder;
+mod ip;
+mod loops;
+mod underflow;
+
+pub use builder::InterpreterBuilder;
+pub use ip::InstructionPointer;
+use loops::LoopDetector;
+pub use underflow::Underflows;
+
+#[derive(Clone, Debug)]
 /// An Interpreter represents a step by step executor for befunge code.
 /// It contains a program, all necessary state, and IO buffers.
-pub struct Interpreter<IOImpl, R> {
+pub struct Interpreter<IOImpl, R, G = SeededRandom> {
     space: Space<GridCell>,
 
-    cursor: Cursor,
-    stack: Vec<StackCell>,
+    /// The IP that executes the next instruction
+    ip: InstructionPointer,
+    /// The other IPs in the order they execute after the current one
+    queue: VecDeque<InstructionPointer>,
+    next_ip_id: u32,
 
     io: IOImpl,
     recorder: R,
+    random: G,
+
+    settings: Settings,
+    /// The fingerprints that can be loaded with "("
+    fingerprints: Fingerprints,
+    exit_code: i32,
+    /// Whether the user has been asked for the result of a division by zero
+    division_prompted: bool,
+    /// The number of steps executed, not counting those that waited for input
+    steps: u64,
+    /// Watches for the program repeating itself, if enabled in the settings
+    loops: Option<LoopDetector>,
+    /// Where the program has popped from an empty stack
+    underflows: Underflows,
+    /// The number of pops from an empty stack during the current step
+    step_underflows: u64,
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Default)]
+/// Options that control how a program is interpreted
+pub struct Settings {
+    /// The dialect the program is written in
+    pub dialect: Dialect,
+    /// Whether to match the reference Befunge-93 implementation exactly.
+    /// The program space is fixed at 80x25 byte cells, "p" and "g" outside of it
+    /// are reported and ignored, "g" reads cells as signed bytes,
+    /// and "," writes single bytes instead of UTF-8.
+    pub strict_93: bool,
+    /// Overrides how the dialect handles division and modulo by zero
+    pub division_by_zero: Option<DivisionByZero>,
+    /// Overrides how the dialect handles input instructions once input has ended
+    pub end_of_input: Option<EndOfInput>,
+    /// Whether to stop with an error when the program gets stuck in a loop
+    /// it can never leave, which is only detectable between uses of input and "?"
+    pub detect_loops: bool,
+    /// What happens when the program pops from an empty stack
+    pub underflow: Underflow,
+}
+
+/// Interpreters are equal when they are in the same state,
+/// whatever they've watched for on the way there
+impl<IOImpl: PartialEq, R: PartialEq, G: PartialEq> PartialEq for Interpreter<IOImpl, R, G> {
+    fn eq(&self, other: &Self) -> bool {
+        let Self {
+            space,
+            ip,
+            queue,
+            next_ip_id,
+            io,
+            recorder,
+            random,
+            settings,
+            fingerprints,
+            exit_code,
+            division_prompted,
+            steps,
+            loops: _,
+            underflows: _,
+            step_underflows: _,
+        } = self;
+        *space == other.space
+            && *ip == other.ip
+            && *queue == other.queue
+            && *next_ip_id == other.next_ip_id
+            && *io == other.io
+            && *recorder == other.recorder
+            && *random == other.random
+            && *settings == other.settings
+            && *fingerprints == other.fingerprints
+            && *exit_code == other.exit_code
+            && *division_prompted == other.division_prompted
+            && *steps == other.steps
+    }
+}
+
+impl<IOImpl: Eq, R: Eq, G: Eq> Eq for Interpreter<IOImpl, R, G> {}
+
+impl Settings {
+    /// Creates the program space for a program according to these settings
+    pub fn space(&self, program: &[u8]) -> Space<GridCell> {
+        if self.strict_93 {
+            Space::new_93(program)
+        } else {
+            Space::new(program)
+        }
+    }
+
+    /// How "/" and "%" behave when the divisor is zero
+    pub fn division_by_zero(&self) -> DivisionByZero {
+        self.division_by_zero
+            .unwrap_or_else(|| self.dialect.division_by_zero())
+    }
+
+    /// How "~" and "&" behave once input has ended
+    pub fn end_of_input(&self) -> EndOfInput {
+        self.end_of_input
+            .unwrap_or_else(|| self.dialect.end_of_input())
+    }
 }
 
 #[derive(PartialEq, Eq, Hash, Clone, Debug)]
@@ -35,23 +154,105 @@ pub enum Status {
     Error(InterpreterError),
 }
 
+/// The number of cells from the top of the stack that errors keep
+const ERROR_STACK_CELLS: usize = 4;
+
+/// An error that stopped the program, with where and when it happened
 #[derive(Debug, Error, PartialEq, Eq, Hash, Clone)]
-pub enum InterpreterError {
-    #[error("Infinite loop detected")]
-    InfiniteLoop,
-    #[error("Invalid opcode {0} found")]
-    InvalidOpcode(u8),
+#[error("{kind} at {position}")]
+pub struct InterpreterError {
+    pub kind: ErrorKind,
+    /// The ID of the IP that caused the error
+    pub ip: u32,
+    /// The cell the IP was at
+    pub position: Position,
+    /// The direction the IP was moving in
+    pub direction: Direction,
+    /// The number of steps that were executed before the error
+    pub step: u64,
+    /// The cells at the top of the IP's stack, with the top last
+    pub stack_top: Vec<StackCell>,
+    /// The number of cells on the IP's stack, including those not in `stack_top`
+    pub stack_size: usize,
+}
+
+impl InterpreterError {
+    /// The status code `bft run` exits with when it stops because of this error
+    pub fn exit_code(&self) -> i32 {
+        self.kind.exit_code()
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq, Hash, Clone)]
+/// What went wrong
+pub enum ErrorKind {
+    #[error("Popped from an empty stack")]
+    StackUnderflow,
+    #[error("Infinite loop of {length} steps through {} cells", .cells.len())]
+    InfiniteLoop {
+        /// The cells the loop goes through, in order
+        cells: Vec<Position>,
+        /// The number of steps before the loop repeats
+        length: usize,
+    },
+    #[error("Invalid opcode {} found", opcode_name(*.0))]
+    InvalidOpcode(i32),
+    #[error("Division by zero")]
+    DivisionByZero,
+    #[error("Tried to read past the end of input")]
+    EndOfInput,
+    #[error("Step limit of {0} reached")]
+    StepLimit(u64),
+    #[error("Timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("Stack limit of {0} cells exceeded")]
+    StackLimit(usize),
+}
+
+/// Shows a cell as the character it holds next to its value, or just the value if it isn't one
+fn opcode_name(value: i32) -> String {
+    match u32::try_from(value).ok().and_then(char::from_u32) {
+        Some(c) => format!("{c:?} ({value})"),
+        None => value.to_string(),
+    }
+}
+
+impl ErrorKind {
+    /// The status code `bft run` exits with when it stops because of this error.
+    /// Each limit has its own code so that scripts can tell them apart.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ErrorKind::StepLimit(_) => 3,
+            ErrorKind::Timeout(_) => 4,
+            ErrorKind::StackLimit(_) => 5,
+            _ => 1,
+        }
+    }
 }
 
 impl Interpreter<StdIO, ()> {
+    /// Starts configuring an interpreter, which uses standard IO unless told otherwise
+    pub fn builder() -> InterpreterBuilder {
+        InterpreterBuilder::default()
+    }
+
     pub fn new_std(space: Space<GridCell>) -> Self {
-        let cursor = Cursor::default();
         Interpreter {
             space,
-            cursor,
-            stack: Vec::new(),
+            ip: InstructionPointer::new(0),
+            queue: VecDeque::new(),
+            next_ip_id: 1,
             io: StdIO::default(),
             recorder: (),
+            random: SeededRandom::from_entropy(),
+            settings: Settings::default(),
+            fingerprints: Fingerprints::default(),
+            exit_code: 0,
+            division_prompted: false,
+            steps: 0,
+            loops: None,
+            underflows: Underflows::default(),
+            step_underflows: 0,
         }
     }
 }
@@ -60,17 +261,113 @@ impl<IOImpl: IO, R: Record> Interpreter<IOImpl, R> {
     /// Creates a new Interpreter that executes
     /// the provided program with the provided io
     /// and records events to the provided recorder.
+    /// The "?" instruction uses a randomly seeded generator.
     pub fn new(space: Space<GridCell>, io: IOImpl, recorder: R) -> Self {
-        let cursor = Cursor::default();
         Interpreter {
             space,
-            cursor,
-            stack: Vec::new(),
+            ip: InstructionPointer::new(0),
+            queue: VecDeque::new(),
+            next_ip_id: 1,
             io,
             recorder,
+            random: SeededRandom::from_entropy(),
+            settings: Settings::default(),
+            fingerprints: Fingerprints::default(),
+            exit_code: 0,
+            division_prompted: false,
+            steps: 0,
+            loops: None,
+            underflows: Underflows::default(),
+            step_underflows: 0,
+        }
+    }
+
+    /// Creates an Interpreter that resumes from a snapshot.
+    /// The IO should be given the snapshot's pending input.
+    /// The step count carries on from the snapshot, but the underflow counts start over.
+    pub fn from_snapshot(snapshot: Snapshot, io: IOImpl, recorder: R) -> Self {
+        let mut ips = VecDeque::from(snapshot.ips);
+        let ip = ips.pop_front().expect("snapshots have at least one IP");
+        Interpreter {
+            space: snapshot.space,
+            ip,
+            queue: ips,
+            next_ip_id: snapshot.next_ip_id,
+            io,
+            recorder,
+            random: snapshot.random,
+            settings: snapshot.settings,
+            fingerprints: Fingerprints::default(),
+            exit_code: snapshot.exit_code,
+            division_prompted: snapshot.division_prompted,
+            steps: snapshot.steps,
+            loops: None,
+            underflows: Underflows::default(),
+            step_underflows: 0,
+        }
+        .with_settings(snapshot.settings)
+    }
+
+    /// Captures the complete state of the interpreter so it can be resumed later
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            settings: self.settings,
+            random: self.random.clone(),
+            next_ip_id: self.next_ip_id,
+            exit_code: self.exit_code,
+            division_prompted: self.division_prompted,
+            steps: self.steps,
+            input: self.io.pending_input(),
+            space: self.space.clone(),
+            ips: self.ips().cloned().collect(),
+        }
+    }
+}
+
+impl<IOImpl: IO, R: Record, G: Random> Interpreter<IOImpl, R, G> {
+    /// Replaces the settings used to interpret the program
+    pub fn with_settings(mut self, settings: Settings) -> Self {
+        self.settings = settings;
+        self.loops = settings
+            .detect_loops
+            .then(|| LoopDetector::new(&self.space));
+        self
+    }
+
+    /// Makes a fingerprint available for Funge-98 programs to load,
+    /// replacing any standard fingerprint with the same ID
+    pub fn with_fingerprint(mut self, fingerprint: impl Fingerprint + 'static) -> Self {
+        self.fingerprints.register(fingerprint);
+        self
+    }
+
+    /// Replaces the source of randomness used by the "?" instruction
+    pub fn with_random<G2: Random>(self, random: G2) -> Interpreter<IOImpl, R, G2> {
+        Interpreter {
+            space: self.space,
+            ip: self.ip,
+            queue: self.queue,
+            next_ip_id: self.next_ip_id,
+            io: self.io,
+            recorder: self.recorder,
+            random,
+            settings: self.settings,
+            fingerprints: self.fingerprints,
+            exit_code: self.exit_code,
+            division_prompted: self.division_prompted,
+            steps: self.steps,
+            loops: self.loops,
+            underflows: self.underflows,
+            step_underflows: self.step_underflows,
         }
     }
 
+    /// The exit code requested by the program.
+    /// Only Funge-98 programs can set a non-zero exit code (using "q").
+    pub fn exit_code(&self) -> i32 {
+        self.exit_code
+    }
+
     pub fn io(&self) -> &IOImpl {
         &self.io
     }
@@ -79,45 +376,163 @@ impl<IOImpl: IO, R: Record> Interpreter<IOImpl, R> {
         &mut self.io
     }
 
+    pub fn recorder(&self) -> &R {
+        &self.recorder
+    }
+
+    pub fn recorder_mut(&mut self) -> &mut R {
+        &mut self.recorder
+    }
+
+    pub fn random(&self) -> &G {
+        &self.random
+    }
+
     pub fn space(&self) -> &Space<GridCell> {
         &self.space
     }
 
-    /// Get the position of the cursor
+    pub fn settings(&self) -> Settings {
+        self.settings
+    }
+
+    /// Get the position of the cursor of the IP that executes next
     pub fn current_position(&self) -> Position {
-        self.cursor.pos
+        self.ip.cursor.pos
     }
 
-    /// Get the direction of the cursor
-    #[allow(dead_code)]
+    /// Get the direction of the cursor of the IP that executes next
     pub fn current_direction(&self) -> Direction {
-        self.cursor.dir
+        self.ip.cursor.dir
+    }
+
+    /// The number of steps executed so far, not counting those that waited for input
+    pub fn steps(&self) -> u64 {
+        self.steps
     }
 
-    /// Get the current stack contents
+    /// Where the program has popped from an empty stack so far
+    pub fn underflows(&self) -> &Underflows {
+        &self.underflows
+    }
+
+    /// Describes an error happening to the IP that executes next, where it is now
+    pub fn error(&self, kind: ErrorKind) -> InterpreterError {
+        self.error_at(kind, self.ip.cursor.pos)
+    }
+
+    fn error_at(&self, kind: ErrorKind, position: Position) -> InterpreterError {
+        let stack = &self.ip.stack;
+        let top = stack.len().saturating_sub(ERROR_STACK_CELLS);
+        InterpreterError {
+            kind,
+            ip: self.ip.id,
+            position,
+            direction: self.ip.cursor.dir,
+            step: self.steps,
+            stack_top: stack[top..].to_vec(),
+            stack_size: stack.len(),
+        }
+    }
+
+    /// Get the current stack contents of the IP that executes next
     pub fn stack(&self) -> &[StackCell] {
-        &self.stack[..]
+        &self.ip.stack[..]
+    }
+
+    /// Lists every IP, in the order they will execute starting with the current one
+    pub fn ips(&self) -> impl Iterator<Item = &InstructionPointer> {
+        std::iter::once(&self.ip).chain(self.queue.iter())
     }
 
     fn put(&mut self, pos: Position, cell: GridCell) {
         let old = self.space.get_cell(pos);
         self.recorder.replace(pos, old, cell);
+        self.set_cell(pos, cell);
+    }
+
+    /// Writes a cell of the program space without recording it
+    fn set_cell(&mut self, pos: Position, cell: GridCell) {
+        let old = self.space.get_cell(pos);
+        if let Some(loops) = &mut self.loops {
+            loops.put(pos, old, cell);
+        }
         self.space.set_cell(pos, cell);
     }
 
+    /// Reads a byte of input, which the program could do anything with
+    fn read_byte(&mut self) -> Input<u8> {
+        let input = self.io.read_byte();
+        if input != Input::Pending {
+            self.recorder.read_byte(input);
+        }
+        if let Input::Value(_) = input {
+            self.forget_loops();
+        }
+        input
+    }
+
+    /// Reads a number from input, which the program could do anything with
+    fn read_number(&mut self) -> Input<i32> {
+        let input = self.io.read_number();
+        if input != Input::Pending {
+            self.recorder.read_number(input);
+        }
+        if let Input::Value(_) = input {
+            self.forget_loops();
+        }
+        input
+    }
+
+    /// Stops loop detection from comparing against states before an unpredictable step
+    fn forget_loops(&mut self) {
+        if let Some(loops) = &mut self.loops {
+            loops.forget();
+        }
+    }
+
+    /// Moves the current IP's cursor
+    fn move_to(&mut self, pos: Position) {
+        if pos != self.ip.cursor.pos {
+            self.recorder.move_cursor(self.ip.cursor.pos, pos);
+            self.ip.cursor.pos = pos;
+        }
+    }
+
+    /// Points the current IP's cursor in a direction
+    fn turn(&mut self, dir: Direction) {
+        if dir != self.ip.cursor.dir {
+            self.recorder.turn(self.ip.cursor.dir, dir);
+            self.ip.cursor.dir = dir;
+        }
+    }
+
+    fn write(&mut self, buf: &[u8]) {
+        self.recorder.output(buf);
+        self.io.write(buf);
+    }
+
+    fn set_division_prompted(&mut self, prompted: bool) {
+        if prompted != self.division_prompted {
+            self.recorder.division_prompted(prompted);
+            self.division_prompted = prompted;
+        }
+    }
+
     fn move_auto(&mut self) {
-        let Cursor { pos, dir, mode: _ } = self.cursor;
-        self.cursor.pos = self.space.move_pos(pos, dir);
+        let Cursor { pos, dir, mode: _ } = self.ip.cursor;
+        self.move_to(self.space.move_pos(pos, dir));
     }
 
     fn pop(&mut self) -> StackCell {
-        match self.stack.pop() {
+        match self.ip.stack.pop() {
             Some(top) => {
                 self.recorder.pop(top);
                 top
             }
             None => {
                 self.recorder.pop_bottom();
+                self.step_underflows += 1;
                 StackCell(0)
             }
         }
@@ -125,50 +540,387 @@ impl<IOImpl: IO, R: Record> Interpreter<IOImpl, R> {
 
     fn push(&mut self, cell: StackCell) {
         self.recorder.push(cell);
-        self.stack.push(cell);
+        self.ip.stack.push(cell);
+    }
+
+    fn reflect(&mut self) {
+        self.turn(self.ip.cursor.dir.reverse());
+    }
+
+    /// Pops from the second on stack stack (SOSS), which must exist
+    fn pop_under(&mut self) -> StackCell {
+        let soss = self.ip.stacks_below.last_mut().expect("SOSS exists");
+        match soss.pop() {
+            Some(top) => {
+                self.recorder.pop_under(top);
+                top
+            }
+            None => {
+                self.recorder.pop_under_bottom();
+                self.step_underflows += 1;
+                StackCell(0)
+            }
+        }
+    }
+
+    /// Pushes onto the second on stack stack (SOSS), which must exist
+    fn push_under(&mut self, cell: StackCell) {
+        let soss = self.ip.stacks_below.last_mut().expect("SOSS exists");
+        self.recorder.push_under(cell);
+        soss.push(cell);
+    }
+
+    /// Pushes a new empty stack onto the stack stack
+    fn begin_stack(&mut self) {
+        self.recorder.begin_stack();
+        let old = std::mem::take(&mut self.ip.stack);
+        self.ip.stacks_below.push(old);
+    }
+
+    /// Pops the top of the stack stack (TOSS), which must have a stack below it
+    fn end_stack(&mut self) {
+        let soss = self.ip.stacks_below.pop().expect("SOSS exists");
+        let old = std::mem::replace(&mut self.ip.stack, soss);
+        self.recorder.end_stack(&old);
+    }
+
+    fn set_storage_offset(&mut self, offset: Position) {
+        self.recorder.storage_offset(self.ip.storage_offset, offset);
+        self.ip.storage_offset = offset;
+    }
+
+    /// Pops a vector and adds the storage offset to it
+    fn pop_offset_position(&mut self) -> Position {
+        let y = self.pop().0;
+        let x = self.pop().0;
+        let offset = self.ip.storage_offset;
+        Position {
+            x: x.wrapping_add(offset.x),
+            y: y.wrapping_add(offset.y),
+        }
+    }
+
+    /// Makes a recorded change to the state happen again, without recording it.
+    /// Input events change nothing, since the values read are pushed by the events after them,
+    /// and output events are written to the IO again.
+    pub(crate) fn apply(&mut self, event: &Event) {
+        match event {
+            Event::Replace { at, old: _, new } => self.set_cell(*at, *new),
+            Event::Pop { old: _ } => _ = self.ip.stack.pop(),
+            Event::Push { new } => self.ip.stack.push(*new),
+            Event::PopBottom
+            | Event::PopUnderBottom
+            | Event::ReadByte { input: _ }
+            | Event::ReadNumber { input: _ } => {}
+            Event::EnterQuote => self.ip.cursor.mode = Mode::Quote,
+            Event::ExitQuote => self.ip.cursor.mode = Mode::Normal,
+            Event::BeginStack => {
+                let old = std::mem::take(&mut self.ip.stack);
+                self.ip.stacks_below.push(old);
+            }
+            Event::EndStack { old: _ } => {
+                self.ip.stack = self.ip.stacks_below.pop().expect("SOSS exists");
+            }
+            Event::PopUnder { old: _ } => {
+                let soss = self.ip.stacks_below.last_mut().expect("SOSS exists");
+                soss.pop();
+            }
+            Event::PushUnder { new } => {
+                let soss = self.ip.stacks_below.last_mut().expect("SOSS exists");
+                soss.push(*new);
+            }
+            Event::StorageOffset { old: _, new } => self.ip.storage_offset = *new,
+            Event::Move { old: _, new } => self.ip.cursor.pos = *new,
+            Event::Turn { old: _, new } => self.ip.cursor.dir = *new,
+            Event::Output { bytes } => self.io.write(bytes),
+            // Keeps the generator in step with the run, in case execution carries on from here
+            Event::Random { dir: _ } => _ = self.random.direction(),
+            Event::ExitCode { old: _, new } => self.exit_code = *new,
+            Event::DivisionPrompted { prompted } => self.division_prompted = *prompted,
+            Event::Semantics { old: _, new } => self.ip.semantics = (**new).clone(),
+            Event::Split { child } => {
+                self.next_ip_id = child.id + 1;
+                self.queue.push_back((**child).clone());
+            }
+            Event::NextIp => {
+                let next = self.queue.pop_front().expect("another IP is queued");
+                let current = std::mem::replace(&mut self.ip, next);
+                self.queue.push_back(current);
+            }
+            Event::EndIp { old: _ } => {
+                self.ip = self.queue.pop_front().expect("another IP is queued");
+            }
+            Event::ClearQueue { old: _ } => self.queue.clear(),
+        }
+    }
+
+    /// Takes back a recorded change to the state, which must be the last change that was made.
+    /// Input events change nothing, so input that was read stays read.
+    pub(crate) fn revert(&mut self, event: &Event) {
+        match event {
+            Event::Replace { at, old, new: _ } => self.set_cell(*at, *old),
+            Event::Pop { old } => self.ip.stack.push(*old),
+            Event::Push { new: _ } => _ = self.ip.stack.pop(),
+            Event::PopBottom
+            | Event::PopUnderBottom
+            | Event::ReadByte { input: _ }
+            | Event::ReadNumber { input: _ } => {}
+            Event::EnterQuote => self.ip.cursor.mode = Mode::Normal,
+            Event::ExitQuote => self.ip.cursor.mode = Mode::Quote,
+            Event::BeginStack => {
+                self.ip.stack = self.ip.stacks_below.pop().expect("SOSS exists");
+            }
+            Event::EndStack { old } => {
+                let soss = std::mem::replace(&mut self.ip.stack, old.clone());
+                self.ip.stacks_below.push(soss);
+            }
+            Event::PopUnder { old } => {
+                let soss = self.ip.stacks_below.last_mut().expect("SOSS exists");
+                soss.push(*old);
+            }
+            Event::PushUnder { new: _ } => {
+                let soss = self.ip.stacks_below.last_mut().expect("SOSS exists");
+                soss.pop();
+            }
+            Event::StorageOffset { old, new: _ } => self.ip.storage_offset = *old,
+            Event::Move { old, new: _ } => self.ip.cursor.pos = *old,
+            Event::Turn { old, new: _ } => self.ip.cursor.dir = *old,
+            Event::Output { bytes } => self.io.unwrite(bytes),
+            Event::Random { dir } => self.random.rewind(*dir),
+            Event::ExitCode { old, new: _ } => self.exit_code = *old,
+            Event::DivisionPrompted { prompted } => self.division_prompted = !*prompted,
+            Event::Semantics { old, new: _ } => self.ip.semantics = (**old).clone(),
+            Event::Split { child } => {
+                self.queue.pop_back();
+                self.next_ip_id = child.id;
+            }
+            Event::NextIp => {
+                let previous = self.queue.pop_back().expect("the previous IP is queued");
+                let current = std::mem::replace(&mut self.ip, previous);
+                self.queue.push_front(current);
+            }
+            Event::EndIp { old } => {
+                let current = std::mem::replace(&mut self.ip, (**old).clone());
+                self.queue.push_front(current);
+            }
+            Event::ClearQueue { old } => self.queue = old.iter().cloned().collect(),
+        }
+    }
+
+    /// Captures the state a timeline can't rebuild from events alone
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            space: self.space.clone(),
+            ips: self.ips().cloned().collect(),
+            next_ip_id: self.next_ip_id,
+            exit_code: self.exit_code,
+            division_prompted: self.division_prompted,
+            steps: self.steps,
+        }
+    }
+
+    /// Goes back to the state captured by a checkpoint, leaving the IO and generator as they are
+    fn restore(&mut self, checkpoint: &Checkpoint) {
+        self.space = checkpoint.space.clone();
+        let mut ips = checkpoint.ips.iter().cloned();
+        self.ip = ips.next().expect("checkpoints have at least one IP");
+        self.queue = ips.collect();
+        self.next_ip_id = checkpoint.next_ip_id;
+        self.exit_code = checkpoint.exit_code;
+        self.division_prompted = checkpoint.division_prompted;
+        self.steps = checkpoint.steps;
+        self.forget_loops();
+    }
+
+    /// Interprets commands until the program terminates, fails or waits for input
+    pub fn run(&mut self) -> Status {
+        loop {
+            match self.step() {
+                Status::Completed => {}
+                status => return status,
+            }
+        }
     }
 
     /// Interprets the next command
     pub fn step(&mut self) -> Status {
-        let cell = self.space.get_cell(self.cursor.pos);
-        self.recorder.start_step(self.cursor.pos, cell);
+        let executed = self.ip.cursor.pos;
+        let cell = self.space.get_cell(executed);
+        if self.recorder.wants_checkpoint() {
+            self.recorder.checkpoint(self.checkpoint());
+        }
+        self.recorder.start_step(executed, cell);
 
-        let status = match self.cursor.mode {
+        let status = match self.ip.cursor.mode {
             Mode::Quote => self.step_quoted(cell),
             Mode::Normal => self.step_unquoted(cell),
         };
 
-        if self.cursor.mode == Mode::Normal {
-            if let Some(status) = self.skip_spaces() {
-                return status;
-            }
+        let status = self.count_underflows(executed, status);
+
+        if self.ip.cursor.mode == Mode::Normal
+            && let Some(status) = self.skip_spaces()
+        {
+            self.recorder.commit_step();
+            return status;
         }
 
-        if status == Status::Waiting {
+        let waiting = status == Status::Waiting;
+        // Switching IPs is part of the step, so it's recorded before the step is committed
+        let status = self.schedule(status);
+        if waiting {
             self.recorder.rollback_step();
         } else {
+            self.steps += 1;
             self.recorder.commit_step();
         }
-
+        if status == Status::Completed
+            && let Some(loops) = &mut self.loops
+        {
+            let ips = std::iter::once(&self.ip).chain(&self.queue);
+            if let Some(kind) = loops.observe(executed, ips) {
+                log::error!("{}", kind);
+                return Status::Error(self.error_at(kind, executed));
+            }
+        }
         status
     }
 
+    /// Remembers where the step just executed popped from an empty stack,
+    /// replacing its status with an error if the settings deny it
+    fn count_underflows(&mut self, executed: Position, status: Status) -> Status {
+        let underflows = std::mem::take(&mut self.step_underflows);
+        if underflows == 0 || status == Status::Waiting {
+            return status;
+        }
+        self.underflows.add(executed, underflows);
+        match self.settings.underflow {
+            Underflow::Allow => status,
+            Underflow::Warn => {
+                log::warn!("Popped from an empty stack at {}", executed);
+                status
+            }
+            Underflow::Deny => Status::Error(self.error_at(ErrorKind::StackUnderflow, executed)),
+        }
+    }
+
+    /// Moves on to the next IP after the current one has executed an instruction.
+    /// The program only terminates once every IP has.
+    fn schedule(&mut self, status: Status) -> Status {
+        match status {
+            Status::Completed => {
+                if let Some(next) = self.queue.pop_front() {
+                    self.recorder.next_ip();
+                    let current = std::mem::replace(&mut self.ip, next);
+                    self.queue.push_back(current);
+                }
+                Status::Completed
+            }
+            Status::Terminated => match self.queue.pop_front() {
+                Some(next) => {
+                    self.recorder.end_ip(&self.ip);
+                    self.ip = next;
+                    Status::Completed
+                }
+                None => Status::Terminated,
+            },
+            // Waiting for input blocks every IP
+            Status::Waiting | Status::Error(_) => status,
+        }
+    }
+
     fn step_quoted(&mut self, cell: GridCell) -> Status {
-        match cell {
-            GridCell(b'"') => {
-                self.cursor.mode = Mode::Normal;
+        match cell.opcode() {
+            Some(b'"') => {
+                self.ip.cursor.mode = Mode::Normal;
                 self.recorder.exit_quote();
             }
-            _ => self.stack.push(cell.into()),
+            Some(b' ') if self.settings.dialect == Dialect::Funge98 => {
+                // Funge-98 treats a run of spaces as a single space in string mode
+                self.push(cell.into());
+                let start = self.ip.cursor.pos;
+                loop {
+                    let next = self.space.move_pos(self.ip.cursor.pos, self.ip.cursor.dir);
+                    if next == start || self.space.get_cell(next).opcode() != Some(b' ') {
+                        break;
+                    }
+                    self.move_to(next);
+                }
+            }
+            _ => self.push(cell.into()),
         }
         self.move_auto();
         Status::Completed
     }
 
     fn step_unquoted(&mut self, cell: GridCell) -> Status {
+        let status = self.execute(cell);
+        if status == Status::Completed {
+            self.move_auto()
+        }
+        status
+    }
+
+    /// Handles an input instruction when input has ended.
+    /// Returns the value to push or the status to stop executing the instruction with.
+    fn end_of_input(&mut self) -> Result<StackCell, Status> {
+        match self.settings.end_of_input() {
+            EndOfInput::Wait => Err(Status::Waiting),
+            EndOfInput::PushNegative => Ok(StackCell(-1)),
+            EndOfInput::Reflect => {
+                self.reflect();
+                Err(Status::Completed)
+            }
+            EndOfInput::Error => Err(Status::Error(self.error(ErrorKind::EndOfInput))),
+        }
+    }
+
+    /// Executes "/" or "%" with a divisor of zero on top of the stack
+    fn divide_by_zero(&mut self, op: u8) -> Status {
+        let result = match self.settings.division_by_zero() {
+            DivisionByZero::Zero => 0,
+            DivisionByZero::Error => {
+                return Status::Error(self.error(ErrorKind::DivisionByZero));
+            }
+            DivisionByZero::Prompt => {
+                // The operands stay on the stack until the user has answered
+                if !self.division_prompted {
+                    let lower = self.ip.stack.iter().rev().nth(1).copied();
+                    let lower = lower.map_or(0, |cell| cell.0);
+                    let op = op as char;
+                    let prompt = format!("What do you want {lower}{op}0 to be? ");
+                    self.write(prompt.as_bytes());
+                    self.set_division_prompted(true);
+                }
+                let result = match self.read_number() {
+                    Input::Value(result) => result,
+                    Input::Pending => return Status::Waiting,
+                    Input::Closed => {
+                        self.set_division_prompted(false);
+                        match self.end_of_input() {
+                            Ok(cell) => cell.0,
+                            Err(status) => return status,
+                        }
+                    }
+                };
+                self.set_division_prompted(false);
+                result
+            }
+        };
+        self.pop();
+        self.pop();
+        self.push(StackCell(result));
+        Status::Completed
+    }
+
+    /// Executes an instruction without moving the cursor past it
+    fn execute(&mut self, cell: GridCell) -> Status {
         use std::num::Wrapping;
 
-        let status = match cell.0 {
+        let Some(op) = cell.opcode() else {
+            return self.execute_unknown(cell);
+        };
+        match op {
             b'+' => {
                 let (e1, e2) = (self.pop(), self.pop());
                 let result = Wrapping(e2.0) + Wrapping(e1.0);
@@ -188,6 +940,9 @@ impl<IOImpl: IO, R: Record> Interpreter<IOImpl, R> {
                 self.push(StackCell(result.0));
                 Status::Completed
             }
+            b'/' | b'%' if self.ip.stack.last().is_none_or(|top| top.0 == 0) => {
+                self.divide_by_zero(op)
+            }
             b'/' => {
                 let upper = self.pop();
                 let lower = self.pop();
@@ -216,51 +971,48 @@ impl<IOImpl: IO, R: Record> Interpreter<IOImpl, R> {
                 Status::Completed
             }
             b'>' => {
-                self.cursor.dir = Direction::Right;
+                self.turn(Direction::Right);
                 Status::Completed
             }
             b'<' => {
-                self.cursor.dir = Direction::Left;
+                self.turn(Direction::Left);
                 Status::Completed
             }
             b'^' => {
-                self.cursor.dir = Direction::Up;
+                self.turn(Direction::Up);
                 Status::Completed
             }
             b'v' => {
-                self.cursor.dir = Direction::Down;
+                self.turn(Direction::Down);
                 Status::Completed
             }
             b'?' => {
-                use rand::seq::IndexedRandom;
-                let dir = [
-                    Direction::Right,
-                    Direction::Left,
-                    Direction::Up,
-                    Direction::Down,
-                ]
-                .choose(&mut rand::rng());
-                self.cursor.dir = *(dir.unwrap());
+                self.forget_loops();
+                let dir = self.random.direction();
+                self.recorder.random(dir);
+                self.turn(dir);
                 Status::Completed
             }
             b'_' => {
-                self.cursor.dir = if self.pop().0 == 0 {
+                let dir = if self.pop().0 == 0 {
                     Direction::Right
                 } else {
                     Direction::Left
                 };
+                self.turn(dir);
                 Status::Completed
             }
             b'|' => {
-                self.cursor.dir = if self.pop().0 == 0 {
+                let dir = if self.pop().0 == 0 {
                     Direction::Down
                 } else {
                     Direction::Up
                 };
+                self.turn(dir);
                 Status::Completed
             }
             b'"' => {
-                self.cursor.mode = Mode::Quote;
+                self.ip.cursor.mode = Mode::Quote;
                 self.recorder.enter_quote();
                 Status::Completed
             }
@@ -284,12 +1036,20 @@ impl<IOImpl: IO, R: Record> Interpreter<IOImpl, R> {
             b'.' => {
                 let number_string = format!("{} ", self.pop().0);
                 let buf = number_string.as_bytes();
-                self.io.write(buf);
+                self.write(buf);
                 Status::Completed
             }
             b',' => {
-                let buf = &[self.pop().0 as u8];
-                self.io.write(buf);
+                let value = self.pop().0;
+                let c = char::from_u32(value as u32).filter(|_| !self.settings.strict_93);
+                match c {
+                    Some(c) => {
+                        let mut buf = [0; 4];
+                        self.write(c.encode_utf8(&mut buf).as_bytes());
+                    }
+                    // Values that aren't code points are written as a single byte
+                    None => self.write(&[value as u8]),
+                }
                 Status::Completed
             }
             b'#' => {
@@ -297,40 +1057,56 @@ impl<IOImpl: IO, R: Record> Interpreter<IOImpl, R> {
                 Status::Completed
             }
             b'g' => {
-                let upper = self.pop().0 as u8;
-                let lower = self.pop().0 as u8;
-                let value = self.space.get_cell(Position { x: lower, y: upper });
-                self.push(value.into());
+                let Position { x, y } = self.pop_offset_position();
+                if self.settings.strict_93 && !in_torus_93(x, y) {
+                    log::warn!("g out of bounds at ({}, {})", x, y);
+                    self.push(StackCell(0));
+                    return Status::Completed;
+                }
+                let value = self.space.get_cell(Position { x, y });
+                if self.settings.strict_93 {
+                    // The reference implementation stores cells as signed chars
+                    self.push(StackCell(value.0 as i8 as i32));
+                } else {
+                    self.push(value.into());
+                }
                 Status::Completed
             }
             b'p' => {
-                let upper = self.pop();
-                let middle = self.pop();
-                let lower = self.pop();
-                self.put(
-                    Position {
-                        x: middle.0 as u8,
-                        y: upper.0 as u8,
-                    },
-                    lower.into(),
-                );
+                let Position { x, y } = self.pop_offset_position();
+                let value = self.pop();
+                if !self.settings.strict_93 {
+                    self.put(Position { x, y }, value.into());
+                } else if in_torus_93(x, y) {
+                    self.put(Position { x, y }, GridCell::from(value.0 as u8));
+                } else {
+                    log::warn!("p out of bounds at ({}, {})", x, y);
+                }
                 Status::Completed
             }
             b'&' => {
-                if let Some(input_number) = self.io.read_number() {
-                    self.push(StackCell(input_number as i32));
-                    Status::Completed
-                } else {
-                    Status::Waiting
-                }
+                let input = match self.read_number() {
+                    Input::Value(input) => StackCell(input),
+                    Input::Pending => return Status::Waiting,
+                    Input::Closed => match self.end_of_input() {
+                        Ok(cell) => cell,
+                        Err(status) => return status,
+                    },
+                };
+                self.push(input);
+                Status::Completed
             }
             b'~' => {
-                if let Some(input) = self.io.read_byte() {
-                    self.push(StackCell(input as i32));
-                    Status::Completed
-                } else {
-                    Status::Waiting
-                }
+                let input = match self.read_byte() {
+                    Input::Value(input) => StackCell(input as i32),
+                    Input::Pending => return Status::Waiting,
+                    Input::Closed => match self.end_of_input() {
+                        Ok(cell) => cell,
+                        Err(status) => return status,
+                    },
+                };
+                self.push(input);
+                Status::Completed
             }
             b'@' => Status::Terminated,
             b'0' => {
@@ -374,30 +1150,438 @@ impl<IOImpl: IO, R: Record> Interpreter<IOImpl, R> {
                 Status::Completed
             }
             b' ' => Status::Completed,
-            op => {
-                log::error!("Invalid opcode: {}", op);
-                return Status::Error(InterpreterError::InvalidOpcode(op));
+            _ => match self.settings.dialect {
+                Dialect::Befunge93 => self.execute_unknown(cell),
+                Dialect::Funge98 => self.execute_98(op, cell),
+            },
+        }
+    }
+
+    /// Executes an instruction that only exists in Funge-98
+    fn execute_98(&mut self, op: u8, cell: GridCell) -> Status {
+        match op {
+            b'\'' => {
+                self.move_auto();
+                let value = self.space.get_cell(self.ip.cursor.pos);
+                self.push(value.into());
+                Status::Completed
+            }
+            b's' => {
+                let value = self.pop();
+                self.move_auto();
+                self.put(self.ip.cursor.pos, value.into());
+                Status::Completed
+            }
+            b';' => {
+                let start = self.ip.cursor.pos;
+                loop {
+                    self.move_auto();
+                    if self.ip.cursor.pos == start
+                        || self.space.get_cell(self.ip.cursor.pos).opcode() == Some(b';')
+                    {
+                        break;
+                    }
+                }
+                Status::Completed
+            }
+            b'j' => {
+                let n = self.pop().0;
+                let dir = if n < 0 {
+                    self.ip.cursor.dir.reverse()
+                } else {
+                    self.ip.cursor.dir
+                };
+                let pos = self.ip.cursor.pos;
+                self.move_to(self.space.move_pos_by(pos, dir, n.unsigned_abs().into()));
+                Status::Completed
+            }
+            b'k' => {
+                let n = self.pop().0;
+                let Cursor { pos, dir, mode: _ } = self.ip.cursor;
+                let next = self.space.move_pos(pos, dir);
+                let target = match self.find_instruction(next, dir) {
+                    Ok(target) => target,
+                    Err(error) => return Status::Error(error),
+                };
+                // A count of zero (or less) skips the next instruction entirely
+                if n <= 0 {
+                    self.move_to(target);
+                    return Status::Completed;
+                }
+                let instruction = self.space.get_cell(target);
+                for _ in 0..n {
+                    let status = self.execute(instruction);
+                    if status != Status::Completed {
+                        return status;
+                    }
+                }
+                // Continue after the iterated instruction unless it moved the cursor itself
+                if self.ip.cursor.pos == pos {
+                    self.move_to(target);
+                }
+                Status::Completed
+            }
+            b'x' => {
+                let dy = self.pop().0;
+                let dx = self.pop().0;
+                // A zero delta would keep the IP on this cell forever
+                let dir = match (dx, dy) {
+                    (0, 0) => self.ip.cursor.dir.reverse(),
+                    _ => Direction::from_delta(dx, dy),
+                };
+                self.turn(dir);
+                Status::Completed
+            }
+            b'[' => {
+                self.turn(self.ip.cursor.dir.turn_left());
+                Status::Completed
+            }
+            b']' => {
+                self.turn(self.ip.cursor.dir.turn_right());
+                Status::Completed
+            }
+            b'w' => {
+                let upper = self.pop();
+                let lower = self.pop();
+                if lower.0 < upper.0 {
+                    self.turn(self.ip.cursor.dir.turn_left());
+                } else if lower.0 > upper.0 {
+                    self.turn(self.ip.cursor.dir.turn_right());
+                }
+                Status::Completed
+            }
+            b'r' => {
+                self.reflect();
+                Status::Completed
+            }
+            b'n' => {
+                while !self.ip.stack.is_empty() {
+                    self.pop();
+                }
+                Status::Completed
+            }
+            b'z' => Status::Completed,
+            b'a'..=b'f' => {
+                self.push(StackCell((op - b'a' + 10) as i32));
+                Status::Completed
+            }
+            b'q' => {
+                let exit_code = self.pop().0;
+                self.recorder.exit_code(self.exit_code, exit_code);
+                self.exit_code = exit_code;
+                // Stops every IP instead of just this one
+                if !self.queue.is_empty() {
+                    let old: Vec<_> = self.queue.drain(..).collect();
+                    self.recorder.clear_queue(&old);
+                }
+                Status::Terminated
+            }
+            b'(' | b')' => {
+                let count = self.pop().0.max(0) as usize;
+                let cells = count.min(self.ip.stack.len());
+                let mut id: i32 = 0;
+                for _ in 0..cells {
+                    id = id.wrapping_mul(256).wrapping_add(self.pop().0);
+                }
+                // The rest of the pops find an empty stack and only shift zeros into the id,
+                // so they are taken all at once
+                let empty = count - cells;
+                if empty > 0 {
+                    self.recorder.pop_bottom();
+                    self.step_underflows += empty as u64;
+                    id = if empty >= 4 { 0 } else { id << (8 * empty) };
+                }
+                let Some(fingerprint) = self.fingerprints.get(id).cloned() else {
+                    log::warn!("Fingerprint {:#x} is not available, reflecting", id);
+                    self.reflect();
+                    return Status::Completed;
+                };
+                let old = self.ip.semantics.clone();
+                if op == b'(' {
+                    self.ip.semantics.load(&fingerprint);
+                } else {
+                    self.ip.semantics.unload(&fingerprint);
+                }
+                self.recorder.semantics(&old, &self.ip.semantics);
+                if op == b'(' {
+                    self.push(StackCell(id));
+                    self.push(StackCell(1));
+                }
+                Status::Completed
+            }
+            b'A'..=b'Z' => match self.ip.semantics.get(op).cloned() {
+                Some(fingerprint) => fingerprint.execute(op, self),
+                None => self.execute_unknown(cell),
+            },
+            b'{' => {
+                let n = self.pop().0;
+                // Cells moved onto the new stack, topmost first
+                let mut moved = Vec::new();
+                for _ in 0..n.max(0) {
+                    moved.push(self.pop());
+                }
+                for _ in n..0 {
+                    self.push(StackCell(0));
+                }
+                let offset = self.ip.storage_offset;
+                self.push(StackCell(offset.x));
+                self.push(StackCell(offset.y));
+                self.begin_stack();
+                for cell in moved.into_iter().rev() {
+                    self.push(cell);
+                }
+                let Cursor { pos, dir, mode: _ } = self.ip.cursor;
+                let (dx, dy) = dir.delta();
+                self.set_storage_offset(Position {
+                    x: pos.x.wrapping_add(dx),
+                    y: pos.y.wrapping_add(dy),
+                });
+                Status::Completed
+            }
+            // Without a stack underneath there's nothing to end or transfer from
+            b'}' | b'u' if self.ip.stacks_below.is_empty() => {
+                self.reflect();
+                Status::Completed
+            }
+            b'}' => {
+                let n = self.pop().0;
+                let mut moved = Vec::new();
+                for _ in 0..n.max(0) {
+                    moved.push(self.pop());
+                }
+                self.end_stack();
+                let y = self.pop().0;
+                let x = self.pop().0;
+                self.set_storage_offset(Position { x, y });
+                for cell in moved.into_iter().rev() {
+                    self.push(cell);
+                }
+                for _ in n..0 {
+                    self.pop();
+                }
+                Status::Completed
+            }
+            b'u' => {
+                let count = self.pop().0;
+                for _ in 0..count.max(0) {
+                    let cell = self.pop_under();
+                    self.push(cell);
+                }
+                for _ in count..0 {
+                    let cell = self.pop();
+                    self.push_under(cell);
+                }
+                Status::Completed
+            }
+            b't' => {
+                let mut child = self.ip.split(self.next_ip_id);
+                let Cursor { pos, dir, mode: _ } = child.cursor;
+                let next = self.space.move_pos(pos, dir);
+                child.cursor.pos = match self.find_instruction(next, dir) {
+                    Ok(pos) => pos,
+                    Err(error) => return Status::Error(error),
+                };
+                self.next_ip_id += 1;
+                self.recorder.split(&child);
+                // The child executes before its parent does again
+                self.queue.push_back(child);
+                Status::Completed
+            }
+            _ => self.execute_unknown(cell),
+        }
+    }
+
+    /// Handles a cell that isn't an instruction in the current dialect
+    fn execute_unknown(&mut self, cell: GridCell) -> Status {
+        match self.settings.dialect {
+            Dialect::Befunge93 => {
+                log::error!("Invalid opcode: {}", cell.0);
+                Status::Error(self.error(ErrorKind::InvalidOpcode(cell.0)))
+            }
+            Dialect::Funge98 => {
+                log::warn!("Unknown opcode: {}, reflecting", cell.0);
+                self.reflect();
+                Status::Completed
             }
-        };
-        if status == Status::Completed {
-            self.move_auto()
         }
-        status
     }
 
     fn skip_spaces(&mut self) -> Option<Status> {
-        let start = self.cursor.pos;
+        match self.find_instruction(self.ip.cursor.pos, self.ip.cursor.dir) {
+            Ok(pos) => {
+                self.move_to(pos);
+                None
+            }
+            Err(error) => Some(Status::Error(error)),
+        }
+    }
+
+    /// Finds the first cell starting at a position that is not
+    /// a space or (in Funge-98) part of a ";" comment.
+    fn find_instruction(
+        &self,
+        start: Position,
+        dir: Direction,
+    ) -> Result<Position, InterpreterError> {
+        let mut pos = start;
+        let mut in_comment = false;
         loop {
-            if self.space.get_cell(self.cursor.pos).0 != b' ' {
-                return None;
+            let op = self.space.get_cell(pos).opcode();
+            if op == Some(b';') && self.settings.dialect == Dialect::Funge98 {
+                in_comment = !in_comment;
+            } else if op != Some(b' ') && !in_comment {
+                return Ok(pos);
             }
 
-            self.move_auto();
+            pos = self.space.move_pos(pos, dir);
 
-            if self.cursor.pos == start {
+            if pos == start && !in_comment {
                 log::error!("Infinite loop detected at {:?}", start);
-                return Some(Status::Error(InterpreterError::InfiniteLoop));
+                let mut cells = vec![start];
+                let mut pos = self.space.move_pos(start, dir);
+                while pos != start {
+                    cells.push(pos);
+                    pos = self.space.move_pos(pos, dir);
+                }
+                let length = cells.len();
+                let kind = ErrorKind::InfiniteLoop { cells, length };
+                return Err(self.error_at(kind, start));
+            }
+        }
+    }
+}
+
+impl<IOImpl: IO, G: Random> Interpreter<IOImpl, Timeline, G> {
+    /// Undoes the last step recorded in the timeline, returning whether there was one to undo
+    pub fn step_back(&mut self) -> bool {
+        let Some((step, events)) = self.recorder.back() else {
+            return false;
+        };
+        for event in events.to_vec().iter().rev() {
+            self.revert(event);
+        }
+        if !step.waited {
+            self.steps -= 1;
+        }
+        // The states seen since then are in a future that might not happen again
+        self.forget_loops();
+        true
+    }
+
+    /// Redoes the next step that was undone, returning whether there was one to redo
+    pub fn step_forward(&mut self) -> bool {
+        let Some((step, events)) = self.recorder.forward() else {
+            return false;
+        };
+        for event in events.to_vec().iter() {
+            self.apply(event);
+        }
+        if !step.waited {
+            self.steps += 1;
+        }
+        self.forget_loops();
+        true
+    }
+
+    /// Goes through a step that was recorded elsewhere, like in a trace file,
+    /// adding it to the end of the timeline
+    pub fn apply_step(&mut self, step: &Step, events: &[Event]) {
+        if self.recorder.wants_checkpoint() {
+            self.recorder.checkpoint(self.checkpoint());
+        }
+        self.recorder.start_step(step.at, step.instruction);
+        for event in events {
+            self.apply(event);
+            self.recorder.record(event.clone());
+        }
+        if step.waited {
+            self.recorder.rollback_step();
+        } else {
+            self.steps += 1;
+            self.recorder.commit_step();
+        }
+        self.forget_loops();
+    }
+
+    /// Goes to a step in the timeline, returning whether it is still kept.
+    /// Steps that are further away than the checkpoint interval are gone to
+    /// by restoring the checkpoint before them and replaying from there,
+    /// with only the output and random events of the steps in between taken back or redone.
+    pub fn seek(&mut self, step: usize) -> bool {
+        let timeline = &self.recorder;
+        if !(timeline.start()..=timeline.end()).contains(&step) {
+            return false;
+        }
+        if step.abs_diff(timeline.position()) <= timeline.checkpoint_interval() {
+            while self.recorder.position() > step {
+                self.step_back();
             }
+            while self.recorder.position() < step {
+                self.step_forward();
+            }
+            return true;
+        }
+
+        let mut timeline = std::mem::take(&mut self.recorder);
+        let Some(seek) = timeline.seek(step) else {
+            self.recorder = timeline;
+            return false;
+        };
+        let not_checkpointed =
+            |event: &Event| matches!(event, Event::Output { .. } | Event::Random { .. });
+        if seek.backwards {
+            for event in seek.skipped.iter().rev().filter(|e| not_checkpointed(e)) {
+                self.revert(event);
+            }
+        } else {
+            for event in seek.skipped.iter().filter(|e| not_checkpointed(e)) {
+                self.apply(event);
+            }
+        }
+        self.restore(seek.checkpoint);
+        for event in seek.replayed.iter().filter(|e| !not_checkpointed(e)) {
+            self.apply(event);
         }
+        self.steps += seek.steps;
+        self.recorder = timeline;
+        true
+    }
+}
+
+impl<IOImpl: IO, R: Record, G: Random> Context for Interpreter<IOImpl, R, G> {
+    fn pop(&mut self) -> StackCell {
+        Interpreter::pop(self)
+    }
+
+    fn push(&mut self, cell: StackCell) {
+        Interpreter::push(self, cell)
+    }
+
+    fn reflect(&mut self) {
+        Interpreter::reflect(self)
+    }
+
+    fn position(&self) -> Position {
+        self.ip.cursor.pos
+    }
+
+    fn direction(&self) -> Direction {
+        self.ip.cursor.dir
+    }
+
+    fn set_direction(&mut self, dir: Direction) {
+        self.turn(dir);
+    }
+
+    fn get(&self, pos: Position) -> GridCell {
+        self.space.get_cell(pos)
+    }
+
+    fn put(&mut self, pos: Position, cell: GridCell) {
+        Interpreter::put(self, pos, cell)
+    }
+
+    fn write(&mut self, buf: &[u8]) {
+        Interpreter::write(self, buf);
     }
 }
@@ -0,0 +1,120 @@
+use crate::{
+    core::{Dialect, GridCell},
+    fingerprint::{Fingerprint, Fingerprints},
+    io::{IO, StdIO},
+    random::{Random, SeededRandom},
+    record::Record,
+    space::Space,
+};
+
+use super::{Interpreter, Settings};
+
+/// Configures an [`Interpreter`] before it is created.
+///
+/// Starts out with the default settings, standard IO, no recorder
+/// and a randomly seeded generator for "?".
+///
+/// ```
+/// use bft::{Interpreter, Dialect, VecIO};
+///
+/// let mut interpreter = Interpreter::builder()
+///     .with_dialect(Dialect::Funge98)
+///     .with_io(VecIO::default())
+///     .with_seed(7)
+///     .build(b"'a,@");
+/// interpreter.run();
+/// assert_eq!(b"a", interpreter.io().output());
+/// ```
+pub struct InterpreterBuilder<IOImpl = StdIO, R = (), G = SeededRandom> {
+    settings: Settings,
+    io: IOImpl,
+    recorder: R,
+    random: G,
+    fingerprints: Fingerprints,
+}
+
+impl Default for InterpreterBuilder {
+    fn default() -> Self {
+        Self {
+            settings: Settings::default(),
+            io: StdIO::default(),
+            recorder: (),
+            random: SeededRandom::from_entropy(),
+            fingerprints: Fingerprints::default(),
+        }
+    }
+}
+
+impl<IOImpl: IO, R: Record, G: Random> InterpreterBuilder<IOImpl, R, G> {
+    /// Replaces every setting used to interpret the program
+    pub fn with_settings(mut self, settings: Settings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// Sets the dialect the program is written in, keeping the other settings
+    pub fn with_dialect(mut self, dialect: Dialect) -> Self {
+        self.settings.dialect = dialect;
+        self
+    }
+
+    /// Replaces where the program reads input from and writes output to
+    pub fn with_io<IO2: IO>(self, io: IO2) -> InterpreterBuilder<IO2, R, G> {
+        InterpreterBuilder {
+            settings: self.settings,
+            io,
+            recorder: self.recorder,
+            random: self.random,
+            fingerprints: self.fingerprints,
+        }
+    }
+
+    /// Replaces what the events of every step are recorded to
+    pub fn with_recorder<R2: Record>(self, recorder: R2) -> InterpreterBuilder<IOImpl, R2, G> {
+        InterpreterBuilder {
+            settings: self.settings,
+            io: self.io,
+            recorder,
+            random: self.random,
+            fingerprints: self.fingerprints,
+        }
+    }
+
+    /// Replaces the source of randomness used by the "?" instruction
+    pub fn with_random<G2: Random>(self, random: G2) -> InterpreterBuilder<IOImpl, R, G2> {
+        InterpreterBuilder {
+            settings: self.settings,
+            io: self.io,
+            recorder: self.recorder,
+            random,
+            fingerprints: self.fingerprints,
+        }
+    }
+
+    /// Makes "?" reproducible by seeding its generator
+    pub fn with_seed(self, seed: u64) -> InterpreterBuilder<IOImpl, R, SeededRandom> {
+        self.with_random(SeededRandom::new(seed))
+    }
+
+    /// Makes a fingerprint available for Funge-98 programs to load,
+    /// replacing any standard fingerprint with the same ID
+    pub fn with_fingerprint(mut self, fingerprint: impl Fingerprint + 'static) -> Self {
+        self.fingerprints.register(fingerprint);
+        self
+    }
+
+    /// Creates an interpreter for a program, laid out in a space suited to the settings
+    pub fn build(self, program: &[u8]) -> Interpreter<IOImpl, R, G> {
+        let space = self.settings.space(program);
+        self.build_space(space)
+    }
+
+    /// Creates an interpreter for a program that is already laid out in a space
+    pub fn build_space(self, space: Space<GridCell>) -> Interpreter<IOImpl, R, G> {
+        let mut interpreter = Interpreter::new(space, self.io, self.recorder)
+            .with_random(self.random)
+            .with_settings(self.settings);
+        interpreter.fingerprints = self.fingerprints;
+        interpreter
+    }
+}
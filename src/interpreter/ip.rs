@@ -0,0 +1,50 @@
+use crate::{
+    core::{Cursor, Position, StackCell},
+    fingerprint::Semantics,
+};
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+/// An instruction pointer (IP) with its own cursor and stacks.
+/// Funge-98 programs can create more IPs with "t" which all run concurrently.
+pub struct InstructionPointer {
+    /// Uniquely identifies the IP for the rest of the program's execution
+    pub id: u32,
+    /// Where the IP is and where it is going
+    pub cursor: Cursor,
+    /// The top of the stack stack (TOSS)
+    pub stack: Vec<StackCell>,
+    /// The stacks underneath the TOSS, with the second on stack stack (SOSS) last
+    pub stacks_below: Vec<Vec<StackCell>>,
+    /// Added to the coordinates used by "g" and "p", set by "{" and "}"
+    pub storage_offset: Position,
+    /// The fingerprint semantics loaded for "A" to "Z"
+    pub semantics: Semantics,
+}
+
+impl InstructionPointer {
+    /// Creates an IP at the origin with an empty stack
+    pub fn new(id: u32) -> Self {
+        Self {
+            id,
+            cursor: Cursor::default(),
+            stack: Vec::new(),
+            stacks_below: Vec::new(),
+            storage_offset: Position::ORIGIN,
+            semantics: Semantics::default(),
+        }
+    }
+
+    /// The number of cells on all of the IP's stacks
+    pub fn stack_size(&self) -> usize {
+        let below: usize = self.stacks_below.iter().map(Vec::len).sum();
+        self.stack.len() + below
+    }
+
+    /// Creates a copy of the IP moving in the opposite direction, like "t"
+    pub fn split(&self, id: u32) -> Self {
+        let mut child = self.clone();
+        child.id = id;
+        child.cursor.dir = child.cursor.dir.reverse();
+        child
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::HashSet;
+
+use crate::{
+    core::{GridCell, Position},
+    space::Space,
+};
+
+use super::{ErrorKind, InstructionPointer};
+
+/// The longest loop, in steps, that can be detected.
+/// Bounds the memory used to remember which cells the loop went through.
+const MAX_LOOP_LENGTH: usize = 1 << 16;
+
+/// Detects when a program returns to a state it has already been in,
+/// which means it will repeat the same steps forever.
+///
+/// Uses Brent's algorithm: the state is saved at checkpoints that get further apart
+/// and every step is compared against the last checkpoint.
+/// Comparing the program space cell by cell would be too slow,
+/// so it is compared by a hash that is updated whenever a cell is written.
+/// Input and randomness make the steps after them unpredictable,
+/// so the detector has to start over after them.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct LoopDetector {
+    /// Hash of the contents of the program space
+    space_hash: u64,
+    checkpoint: Option<Checkpoint>,
+    /// How many steps to take before moving the checkpoint
+    interval: usize,
+    /// The positions of the instructions executed since the checkpoint
+    trail: Vec<Position>,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+struct Checkpoint {
+    space_hash: u64,
+    ips: Vec<InstructionPointer>,
+}
+
+impl LoopDetector {
+    pub fn new(space: &Space<GridCell>) -> Self {
+        let grid = space.grid().indexed_iter().map(|((y, x), cell)| {
+            let pos = Position {
+                x: x as i32,
+                y: y as i32,
+            };
+            (pos, cell)
+        });
+        let space_hash = grid
+            .chain(space.sparse_cells())
+            .fold(0u64, |hash, (pos, cell)| {
+                hash.wrapping_add(cell_hash(pos, *cell))
+            });
+        Self {
+            space_hash,
+            checkpoint: None,
+            interval: 1,
+            trail: Vec::new(),
+        }
+    }
+
+    /// Keeps the hash of the space up to date with a cell being written
+    pub fn put(&mut self, pos: Position, old: GridCell, new: GridCell) {
+        self.space_hash = self
+            .space_hash
+            .wrapping_sub(cell_hash(pos, old))
+            .wrapping_add(cell_hash(pos, new));
+    }
+
+    /// Starts over because the program did something that can't be predicted
+    pub fn forget(&mut self) {
+        self.checkpoint = None;
+        self.interval = 1;
+        self.trail.clear();
+    }
+
+    /// Checks the state after executing the instruction at a position.
+    /// Describes the loop if the state has been seen before.
+    pub fn observe<'a>(
+        &mut self,
+        executed: Position,
+        ips: impl Iterator<Item = &'a InstructionPointer> + Clone,
+    ) -> Option<ErrorKind> {
+        self.trail.push(executed);
+        if let Some(checkpoint) = &self.checkpoint
+            && checkpoint.space_hash == self.space_hash
+            && ips.clone().eq(checkpoint.ips.iter())
+        {
+            let error = ErrorKind::InfiniteLoop {
+                cells: unique(&self.trail),
+                length: self.trail.len(),
+            };
+            self.forget();
+            return Some(error);
+        }
+        if self.checkpoint.is_none() || self.trail.len() >= self.interval {
+            self.checkpoint = Some(Checkpoint {
+                space_hash: self.space_hash,
+                ips: ips.cloned().collect(),
+            });
+            self.trail.clear();
+            self.interval = (self.interval * 2).min(MAX_LOOP_LENGTH);
+        }
+        None
+    }
+}
+
+/// The contribution of one cell to the hash of the space.
+/// Empty cells contribute nothing so that the unbounded space has a finite hash.
+fn cell_hash(pos: Position, cell: GridCell) -> u64 {
+    if cell == GridCell::default() {
+        return 0;
+    }
+    let mut z = (pos.x as u32 as u64) << 32 | pos.y as u32 as u64;
+    z ^= (cell.0 as u32 as u64).wrapping_mul(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+/// The positions in the order they were first visited
+fn unique(trail: &[Position]) -> Vec<Position> {
+    let mut seen = HashSet::new();
+    trail
+        .iter()
+        .copied()
+        .filter(|pos| seen.insert(*pos))
+        .collect()
+}
@@ -0,0 +1,31 @@
+use std::collections::HashMap;
+
+use crate::core::Position;
+
+/// Counts the pops from an empty stack that produced an implicit zero,
+/// by the position of the instruction that popped
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub struct Underflows {
+    counts: HashMap<Position, u64>,
+    total: u64,
+}
+
+impl Underflows {
+    pub fn add(&mut self, pos: Position, count: u64) {
+        *self.counts.entry(pos).or_default() += count;
+        self.total += count;
+    }
+
+    /// The number of implicit zeros popped by the whole program
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Every position that popped an implicit zero and how many times it did,
+    /// in reading order
+    pub fn locations(&self) -> Vec<(Position, u64)> {
+        let mut locations: Vec<_> = self.counts.iter().map(|(pos, n)| (*pos, *n)).collect();
+        locations.sort_by_key(|(pos, _)| (pos.y, pos.x));
+        locations
+    }
+}
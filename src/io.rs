@@ -4,9 +4,34 @@ use std::{
 };
 
 pub trait IO {
-    fn read_byte(&mut self) -> Option<u8>;
-    fn read_number(&mut self) -> Option<u8>;
+    fn read_byte(&mut self) -> Input<u8>;
+    fn read_number(&mut self) -> Input<i32>;
     fn write(&mut self, buf: &[u8]);
+    /// Takes back output that was the last to be written, when stepping backwards.
+    /// Output that has already left the program, like standard output, stays written.
+    fn unwrite(&mut self, buf: &[u8]);
+    /// Input that has arrived but hasn't been read by the program yet
+    fn pending_input(&self) -> PendingInput;
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Default)]
+/// Input that has been received but not yet read
+pub struct PendingInput {
+    /// The bytes waiting to be read, in order
+    pub bytes: Vec<u8>,
+    /// Whether the input stream has ended after these bytes
+    pub closed: bool,
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+/// The result of trying to read input
+pub enum Input<T> {
+    /// A value was read
+    Value(T),
+    /// No input is available yet but more may arrive
+    Pending,
+    /// The input stream has ended and nothing more will arrive
+    Closed,
 }
 
 pub struct StdIO {
@@ -23,41 +48,53 @@ impl Default for StdIO {
     }
 }
 
-pub struct InputBuffer {
-    stdin: Stdin,
-    buffer: [u8; 32],
+/// The number of bytes of input buffered at first, which grows to fit longer numbers
+const INPUT_BUFFER_SIZE: usize = 32;
+
+pub(crate) struct InputBuffer<R = Stdin> {
+    stdin: R,
+    buffer: Vec<u8>,
     offset: usize,
     length: usize,
+    /// Whether standard input has ended
+    closed: bool,
 }
 
 impl Default for InputBuffer {
     fn default() -> Self {
-        Self {
-            stdin: stdin(),
-            buffer: [0; 32],
-            offset: 0,
-            length: 0,
-        }
+        Self::new(stdin())
     }
 }
 
-impl std::fmt::Debug for InputBuffer {
+impl<R: std::fmt::Debug> std::fmt::Debug for InputBuffer<R> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("InputBuffer")
             .field("stdin", &self.stdin)
             .field("buffer", &format!("{:x?}", &self.buffer))
             .field("offset", &self.offset)
             .field("length", &self.length)
+            .field("closed", &self.closed)
             .finish()
     }
 }
 
-impl InputBuffer {
-    fn read_byte(&mut self) -> Option<u8> {
+impl<R: Read> InputBuffer<R> {
+    fn new(stdin: R) -> Self {
+        Self {
+            stdin,
+            buffer: vec![0; INPUT_BUFFER_SIZE],
+            offset: 0,
+            length: 0,
+            closed: false,
+        }
+    }
+
+    fn read_byte(&mut self) -> Input<u8> {
         if self.is_empty() {
             let n = self.stdin.read(&mut self.buffer).unwrap();
             if n == 0 {
-                return None;
+                self.closed = true;
+                return Input::Closed;
             }
             self.offset = 0;
             self.length = n;
@@ -66,7 +103,7 @@ impl InputBuffer {
         let value = self.buffer[self.offset];
         self.offset += 1;
         self.length -= 1;
-        Some(value)
+        Input::Value(value)
     }
 
     fn is_empty(&self) -> bool {
@@ -90,99 +127,360 @@ impl InputBuffer {
         self.length -= skip;
     }
 
-    /// Returns the amount more that was able to be read
+    /// Returns the amount more that was able to be read, which is only 0 once the input has ended
     fn read_more(&mut self) -> usize {
-        let buf = match self.length {
-            0 => &mut self.buffer,
-            _ => {
-                let start = self.offset;
-                let end = self.offset + self.length;
-                &mut self.buffer[start..end]
-            }
-        };
-        let n = self.stdin.read(buf).unwrap();
-        self.length += n;
-        n
-    }
-
-    fn bytes(&self) -> impl Iterator<Item = u8> {
+        // Move the unread bytes to the front to make room after them
         let start = self.offset;
         let end = self.offset + self.length;
-        let buf = &self.buffer[start..end];
-        buf.iter().copied()
-    }
-}
-
-impl IO for StdIO {
-    fn read_byte(&mut self) -> Option<u8> {
-        self.input.read_byte()
+        self.buffer.copy_within(start..end, 0);
+        self.offset = 0;
+        // A number longer than the buffer needs more room to be read whole
+        if self.length == self.buffer.len() {
+            self.buffer.resize(self.buffer.len() * 2, 0);
+        }
+        let n = self.stdin.read(&mut self.buffer[self.length..]).unwrap();
+        self.length += n;
+        if n == 0 {
+            self.closed = true;
+        }
+        n
     }
 
-    fn read_number(&mut self) -> Option<u8> {
-        if self.input.is_empty() {
-            let n = self.input.read_more();
+    fn read_number(&mut self) -> Input<i32> {
+        if self.is_empty() {
+            let n = self.read_more();
             if n == 0 {
-                return None;
+                return Input::Closed;
             }
         }
         loop {
-            let iter = self.input.bytes();
+            let iter = self.bytes();
             match try_read_number(iter) {
                 Ok((offset, num)) => {
-                    self.input.skip_and_shift(offset);
-                    return Some(num);
+                    self.skip_and_shift(offset);
+                    return Input::Value(num);
                 }
                 Err(skippable) => {
-                    self.input.skip_and_shift(skippable);
-                    if self.input.read_more() == 0 {
-                        return None;
+                    self.skip_and_shift(skippable);
+                    if self.read_more() == 0 {
+                        // The end of input finishes a number that was being read
+                        let iter = self.bytes().chain([b'\n']);
+                        return match try_read_number(iter) {
+                            Ok((offset, num)) => {
+                                self.skip_and_shift(offset);
+                                Input::Value(num)
+                            }
+                            Err(_) => Input::Closed,
+                        };
                     }
                 }
             }
         }
     }
 
+    fn bytes(&self) -> impl Iterator<Item = u8> + Clone {
+        let start = self.offset;
+        let end = self.offset + self.length;
+        let buf = &self.buffer[start..end];
+        buf.iter().copied()
+    }
+}
+
+impl IO for StdIO {
+    fn read_byte(&mut self) -> Input<u8> {
+        self.input.read_byte()
+    }
+
+    fn read_number(&mut self) -> Input<i32> {
+        self.input.read_number()
+    }
+
     fn write(&mut self, buf: &[u8]) {
         self.stdout.write_all(buf).unwrap();
     }
+
+    fn unwrite(&mut self, _buf: &[u8]) {}
+
+    fn pending_input(&self) -> PendingInput {
+        PendingInput {
+            bytes: self.input.bytes().collect(),
+            closed: self.input.closed,
+        }
+    }
 }
 
-#[derive(Default, Debug)]
+#[derive(PartialEq, Eq, Default, Debug)]
 pub struct VecIO {
-    input_buffer: VecDeque<u8>,
+    input_buffer: BufferedInput,
     output_buffer: Vec<u8>,
 }
 
+impl VecIO {
+    /// Creates an IO whose input is already buffered
+    pub fn with_input(input: &[u8]) -> Self {
+        let mut input_buffer = BufferedInput::default();
+        input_buffer.push(input);
+        Self {
+            input_buffer,
+            output_buffer: Vec::new(),
+        }
+    }
+
+    /// Ends the input once the buffered input has been read
+    pub fn close_input(mut self) -> Self {
+        self.input_buffer.close();
+        self
+    }
+
+    pub fn output(&self) -> &[u8] {
+        &self.output_buffer
+    }
+}
+
 impl IO for VecIO {
-    fn read_byte(&mut self) -> Option<u8> {
-        self.input_buffer.pop_front()
+    fn read_byte(&mut self) -> Input<u8> {
+        self.input_buffer.read_byte()
     }
 
-    fn read_number(&mut self) -> Option<u8> {
-        let iter = self.input_buffer.iter().copied();
-        let (offset, byte) = try_read_number(iter).ok()?;
-        for _ in 0..offset {
-            self.input_buffer.pop_front();
-        }
-        Some(byte)
+    fn read_number(&mut self) -> Input<i32> {
+        self.input_buffer.read_number()
     }
 
     fn write(&mut self, buf: &[u8]) {
         self.output_buffer.extend_from_slice(buf);
     }
+
+    fn unwrite(&mut self, buf: &[u8]) {
+        if self.output_buffer.ends_with(buf) {
+            self.output_buffer
+                .truncate(self.output_buffer.len() - buf.len());
+        }
+    }
+
+    fn pending_input(&self) -> PendingInput {
+        self.input_buffer.pending()
+    }
+}
+
+/// Answers reads with the input a run read earlier, in the order it was read,
+/// and writes output to another IO.
+/// Once it runs out, or the program reads differently, reads wait for input that never arrives.
+pub struct ScriptedIO<IOImpl> {
+    inputs: VecDeque<ScriptedInput>,
+    io: IOImpl,
+}
+
+/// What a read by "~" or "&" got
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum ScriptedInput {
+    Byte(Input<u8>),
+    Number(Input<i32>),
+}
+
+impl<IOImpl: IO> ScriptedIO<IOImpl> {
+    pub fn new(inputs: impl IntoIterator<Item = ScriptedInput>, io: IOImpl) -> Self {
+        Self {
+            inputs: inputs.into_iter().collect(),
+            io,
+        }
+    }
+
+    /// The IO output is written to
+    pub fn inner(&self) -> &IOImpl {
+        &self.io
+    }
+}
+
+impl<IOImpl: IO> IO for ScriptedIO<IOImpl> {
+    fn read_byte(&mut self) -> Input<u8> {
+        match self.inputs.front() {
+            Some(&ScriptedInput::Byte(input)) => {
+                self.inputs.pop_front();
+                input
+            }
+            _ => Input::Pending,
+        }
+    }
+
+    fn read_number(&mut self) -> Input<i32> {
+        match self.inputs.front() {
+            Some(&ScriptedInput::Number(input)) => {
+                self.inputs.pop_front();
+                input
+            }
+            _ => Input::Pending,
+        }
+    }
+
+    fn write(&mut self, buf: &[u8]) {
+        self.io.write(buf);
+    }
+
+    fn unwrite(&mut self, buf: &[u8]) {
+        self.io.unwrite(buf);
+    }
+
+    /// Scripted input isn't pending since it can only be read in order
+    fn pending_input(&self) -> PendingInput {
+        PendingInput::default()
+    }
+}
+
+/// Input that has arrived in memory, read the way "~" and "&" read standard input.
+/// For IO implementations that receive input themselves, like from a user typing it in.
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Default)]
+pub struct BufferedInput {
+    bytes: VecDeque<u8>,
+    /// Whether the input has ended after the buffered bytes
+    closed: bool,
+}
+
+impl BufferedInput {
+    /// Creates an empty buffer with room for some input before it has to grow
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            bytes: VecDeque::with_capacity(capacity),
+            closed: false,
+        }
+    }
+
+    /// Adds input that has arrived after what is already buffered
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.bytes.extend(bytes);
+    }
+
+    /// Ends the input once the buffered input has been read
+    pub fn close(&mut self) {
+        self.closed = true;
+    }
+
+    /// Whether the input has ended
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Reads a byte, or says whether more input could still arrive
+    pub fn read_byte(&mut self) -> Input<u8> {
+        match self.bytes.pop_front() {
+            Some(byte) => Input::Value(byte),
+            None if self.closed => Input::Closed,
+            None => Input::Pending,
+        }
+    }
+
+    /// Reads a number, skipping anything before it that can't start one.
+    /// Once the input is closed a number at the very end of it is complete.
+    pub fn read_number(&mut self) -> Input<i32> {
+        let iter = self.bytes.iter().copied();
+        let result = if self.closed {
+            try_read_number(iter.chain([b'\n']))
+        } else {
+            try_read_number(iter)
+        };
+        match result {
+            Ok((offset, num)) => {
+                self.bytes.drain(..offset);
+                Input::Value(num)
+            }
+            Err(skippable) => {
+                self.bytes.drain(..skippable.min(self.bytes.len()));
+                if self.closed {
+                    Input::Closed
+                } else {
+                    Input::Pending
+                }
+            }
+        }
+    }
+
+    /// The input that hasn't been read yet
+    pub fn pending(&self) -> PendingInput {
+        PendingInput {
+            bytes: self.bytes.iter().copied().collect(),
+            closed: self.closed,
+        }
+    }
+}
+
+impl From<PendingInput> for BufferedInput {
+    fn from(input: PendingInput) -> Self {
+        Self {
+            bytes: input.bytes.into(),
+            closed: input.closed,
+        }
+    }
+}
+
+/// An integer type that numbers typed as input are parsed into
+pub(crate) trait InputNumber: Copy {
+    /// Whether a "-" directly before the digits makes the number negative
+    const SIGNED: bool;
+
+    /// The number made of a single digit
+    fn from_digit(digit: u8, negative: bool) -> Self;
+
+    /// Appends a digit to the number or returns None if it would overflow
+    fn push_digit(self, digit: u8, negative: bool) -> Option<Self>;
+}
+
+/// Unsigned bytes, which are what "&" used to read
+impl InputNumber for u8 {
+    const SIGNED: bool = false;
+
+    fn from_digit(digit: u8, _negative: bool) -> Self {
+        digit
+    }
+
+    fn push_digit(self, digit: u8, _negative: bool) -> Option<Self> {
+        try_combine(self, digit)
+    }
+}
+
+impl InputNumber for i32 {
+    const SIGNED: bool = true;
+
+    fn from_digit(digit: u8, negative: bool) -> Self {
+        if negative {
+            -(digit as i32)
+        } else {
+            digit as i32
+        }
+    }
+
+    fn push_digit(self, digit: u8, negative: bool) -> Option<Self> {
+        let shifted = self.checked_mul(10)?;
+        if negative {
+            shifted.checked_sub(digit as i32)
+        } else {
+            shifted.checked_add(digit as i32)
+        }
+    }
 }
 
 // Either reads a number from the iterator successfully
 // returning the number of bytes read and the value of the number
 // or returns that a number could not be read and how many bytes can be skipped.
-pub fn try_read_number(iter: impl Iterator<Item = u8>) -> Result<(usize, u8), usize> {
-    let mut iter = iter.enumerate();
-    let (mut offset, mut num) = base_number(&mut iter)?;
-    let skippable = offset - 1;
-    for (i, byte) in iter {
+// Digits that would overflow the number are left to be read as the next number.
+pub(crate) fn try_read_number<N: InputNumber>(
+    iter: impl Iterator<Item = u8> + Clone,
+) -> Result<(usize, N), usize> {
+    let is_minus = |index: usize| iter.clone().nth(index) == Some(b'-');
+    let mut enumerated = iter.clone().enumerate();
+    let (mut offset, digit) = match base_number(&mut enumerated) {
+        Ok(found) => found,
+        // Keep a trailing "-" since the digits may not have arrived yet
+        Err(skippable) if N::SIGNED && skippable > 0 && is_minus(skippable - 1) => {
+            return Err(skippable - 1);
+        }
+        Err(skippable) => return Err(skippable),
+    };
+    let negative = N::SIGNED && offset >= 2 && is_minus(offset - 2);
+    let skippable = offset - 1 - negative as usize;
+    let mut num = N::from_digit(digit, negative);
+    for (i, byte) in enumerated {
         if byte.is_ascii_digit() {
             let value = byte - b'0';
-            if let Some(new_num) = try_combine(num, value) {
+            if let Some(new_num) = num.push_digit(value, negative) {
                 offset = i + 1;
                 num = new_num;
             } else {
@@ -250,11 +548,61 @@ mod tests {
         ];
         for (input, expected) in cases.into_iter() {
             let iter = input.as_bytes().iter().copied();
-            let actual = try_read_number(iter);
+            let actual = try_read_number::<u8>(iter);
             assert_eq!(actual, expected);
         }
     }
 
+    #[test]
+    fn test_try_read_signed_number() {
+        let cases = [
+            ("1000 ", Ok((4, 1000))),
+            ("-5\n", Ok((2, -5))),
+            ("abc-42x", Ok((6, -42))),
+            ("- 3 ", Ok((3, 3))),
+            ("-05 ", Ok((3, -5))),
+            ("2147483647 ", Ok((10, i32::MAX))),
+            ("-2147483648 ", Ok((11, i32::MIN))),
+            // Digits that would overflow are left for the next number
+            ("2147483648 ", Ok((9, 214748364))),
+            ("-21474836480 ", Ok((11, i32::MIN))),
+            // Incomplete numbers keep their sign
+            ("ab-12", Err(2)),
+            ("ab-", Err(2)),
+            ("ab", Err(2)),
+        ];
+        for (input, expected) in cases.into_iter() {
+            let iter = input.as_bytes().iter().copied();
+            let actual = try_read_number::<i32>(iter);
+            assert_eq!(actual, expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_read_number_longer_than_buffer() {
+        let zeros = "0".repeat(3 * INPUT_BUFFER_SIZE);
+        let input = format!("{zeros}123 {zeros}45");
+        let mut buffer = InputBuffer::new(input.as_bytes());
+        assert_eq!(Input::Value(123), buffer.read_number());
+        assert_eq!(Input::Value(45), buffer.read_number());
+        assert_eq!(Input::Closed, buffer.read_number());
+    }
+
+    #[test]
+    fn test_buffered_input() {
+        let mut input = BufferedInput::default();
+        input.push(b"x12");
+        // The number could still go on
+        assert_eq!(Input::Pending, input.read_number());
+        input.push(b"3 a");
+        assert_eq!(Input::Value(123), input.read_number());
+        assert_eq!(Input::Value(b' '), input.read_byte());
+        input.push(b"4");
+        input.close();
+        assert_eq!(Input::Value(4), input.read_number());
+        assert_eq!(Input::Closed, input.read_byte());
+    }
+
     #[test]
     fn test_base_num() {
         let cases = [
@@ -0,0 +1,953 @@
+//! Befunge runtime and development tools.
+//!
+//! The [`Interpreter`] executes Befunge-93 and Funge-98 programs one step at a time,
+//! reading input from and writing output to an [`IO`] and reporting what every step
+//! did to a [`Record`]. [`Interpreter::builder`] configures one:
+//!
+//! ```
+//! use bft::{Interpreter, Status, VecIO};
+//!
+//! let mut interpreter = Interpreter::builder()
+//!     .with_io(VecIO::with_input(b"20\n").close_input())
+//!     .build(b"&2*.@");
+//! assert_eq!(Status::Terminated, interpreter.run());
+//! assert_eq!(b"40 ", interpreter.io().output());
+//! ```
+//!
+//! [`analyze_path`] finds the cells a program can reach without running it,
+//! and [`compile`] turns Befunge-93 programs into Rust or C.
+
+mod analyze;
+mod compile;
+mod core;
+mod diagnostic;
+mod fingerprint;
+mod interpreter;
+mod io;
+mod random;
+mod record;
+mod snapshot;
+mod space;
+mod trace;
+
+pub use analyze::{CellState, Directions, Modes, PathAnalysis, analyze_path};
+pub use compile::{CompileError, Target, compile};
+pub use core::{
+    Cursor, Dialect, Direction, DivisionByZero, EndOfInput, GridCell, Mode, Position, StackCell,
+    Underflow,
+};
+pub use diagnostic::{render_error, render_underflows};
+pub use fingerprint::{Context, Fingerprint, Fingerprints, Semantics, fingerprint_id};
+pub use interpreter::{
+    ErrorKind, InstructionPointer, Interpreter, InterpreterBuilder, InterpreterError, Settings,
+    Status, Underflows,
+};
+pub use io::{BufferedInput, IO, Input, PendingInput, ScriptedIO, ScriptedInput, StdIO, VecIO};
+pub use random::{Random, ScriptedRandom, SeededRandom};
+pub use record::{Event, EventLog, Record, StdOutEventLog, Step, Timeline};
+pub use snapshot::{Snapshot, SnapshotError};
+pub use space::Space;
+pub use trace::{Trace, TraceError, TraceWriter, jsonl::JsonLinesWriter, text::TextTrace};
+
+#[cfg(test)]
+mod tests {
+    use super::core::{
+        Dialect, Direction, DivisionByZero, EndOfInput, Position, StackCell, Underflow,
+    };
+    use super::interpreter::{ErrorKind, Interpreter, Settings, Status};
+    use crate::core::GridCell;
+    use crate::fingerprint::{self, Context, Fingerprint, Fingerprints};
+    use crate::io::{IO, VecIO};
+    use crate::random::{Random, ScriptedRandom, SeededRandom};
+    use crate::record::{StdOutEventLog, Timeline};
+    use crate::snapshot::Snapshot;
+    use crate::space::Space;
+    use std::time::Duration;
+
+    type DebugInterpreter<'src> = Interpreter<VecIO, StdOutEventLog>;
+
+    const EMPTY_STACK: &[StackCell] = &[];
+
+    fn one_liner(line: &[u8]) -> DebugInterpreter<'_> {
+        let program = Vec::from(line);
+        let space = Space::new(&program);
+        let io = VecIO::default();
+        Interpreter::new(space, io, StdOutEventLog)
+    }
+
+    fn one_liner_98(line: &[u8]) -> DebugInterpreter<'_> {
+        let settings = Settings {
+            dialect: Dialect::Funge98,
+            ..Default::default()
+        };
+        one_liner(line).with_settings(settings)
+    }
+
+    fn one_liner_93(line: &[u8]) -> DebugInterpreter<'_> {
+        let settings = Settings {
+            strict_93: true,
+            ..Default::default()
+        };
+        let program = Vec::from(line);
+        let space = settings.space(&program);
+        Interpreter::new(space, VecIO::default(), StdOutEventLog).with_settings(settings)
+    }
+
+    fn run_to_end<G: Random>(interpreter: &mut Interpreter<VecIO, StdOutEventLog, G>) {
+        loop {
+            match interpreter.step() {
+                Status::Completed => {}
+                Status::Terminated => return,
+                status => panic!("Unexpected status {:?}", status),
+            }
+        }
+    }
+
+    #[test]
+    fn test_initial_settings() {
+        let interpreter = one_liner(&[]);
+        assert_eq!(Direction::Right, interpreter.current_direction());
+        assert_eq!(Position::ORIGIN, interpreter.current_position());
+        assert_eq!(EMPTY_STACK, interpreter.stack());
+    }
+
+    #[test]
+    fn test_push_num() {
+        let cases: [(u8, u8); 10] = [
+            (b'0', 0),
+            (b'1', 1),
+            (b'2', 2),
+            (b'3', 3),
+            (b'4', 4),
+            (b'5', 5),
+            (b'6', 6),
+            (b'7', 7),
+            (b'8', 8),
+            (b'9', 9),
+        ];
+        for (opcode, number) in cases.iter() {
+            test_push_num_recipe(*opcode, *number);
+        }
+    }
+
+    fn test_push_num_recipe(opcode: u8, number: u8) {
+        let mut interpreter = one_liner(&[opcode]);
+
+        let status = interpreter.step();
+        assert_eq!(Status::Completed, status);
+
+        assert_eq!(Direction::Right, interpreter.current_direction());
+        assert_eq!(Position { x: 0, y: 0 }, interpreter.current_position());
+        assert_eq!(&[StackCell(number as i32)], interpreter.stack());
+    }
+
+    #[test]
+    fn test_left_arrow() {
+        let mut interpreter = one_liner(b"1<");
+
+        let status = interpreter.step();
+        assert_eq!(Status::Completed, status);
+
+        assert_eq!(Direction::Right, interpreter.current_direction());
+        assert_eq!(Position { x: 1, y: 0 }, interpreter.current_position());
+        assert_eq!(&[StackCell(1)], interpreter.stack());
+
+        let status = interpreter.step();
+        assert_eq!(Status::Completed, status);
+
+        assert_eq!(Direction::Left, interpreter.current_direction());
+        assert_eq!(Position::ORIGIN, interpreter.current_position());
+        assert_eq!(&[StackCell(1)], interpreter.stack());
+    }
+
+    #[test]
+    fn test_arrow_loop() {
+        let program = vec![b'v', b'<', b'\n', b'>', b'^'];
+        let io = VecIO::default();
+        let space = Space::new(&program);
+        let mut interpreter = Interpreter::new(space, io, StdOutEventLog);
+
+        let sequence = [
+            (0, 1, Direction::Down),
+            (1, 1, Direction::Right),
+            (1, 0, Direction::Up),
+            (0, 0, Direction::Left),
+        ];
+
+        for _ in 0..255 {
+            for (x, y, direction) in sequence.iter().copied() {
+                let status = interpreter.step();
+                assert_eq!(Status::Completed, status);
+
+                assert_eq!(direction, interpreter.current_direction());
+                assert_eq!(Position { x, y }, interpreter.current_position());
+                assert_eq!(EMPTY_STACK, interpreter.stack());
+            }
+        }
+    }
+
+    #[test]
+    fn test_put() {
+        let mut interpreter = one_liner(b"211p3");
+
+        // Push the three numbers
+        assert_eq!(Status::Completed, interpreter.step());
+        assert_eq!(Status::Completed, interpreter.step());
+        assert_eq!(Status::Completed, interpreter.step());
+        // Verify that the numbers are on the stack
+        assert_eq!(Position { x: 3, y: 0 }, interpreter.current_position());
+        assert_eq!(
+            &[StackCell(2), StackCell(1), StackCell(1)],
+            interpreter.stack()
+        );
+        // Step over the p command
+        assert_eq!(Status::Completed, interpreter.step());
+        // Verify that the position and direction are correct
+        assert_eq!(Position { x: 4, y: 0 }, interpreter.current_position());
+        // Verify that the stack is now empty
+        assert_eq!(EMPTY_STACK, interpreter.stack());
+        // Verify that the value 2 was placed into the specified position
+        assert_eq!(
+            GridCell(2),
+            interpreter.space().get_cell(Position { x: 1, y: 1 })
+        );
+    }
+
+    #[test]
+    fn test_get() {
+        let mut interpreter = one_liner(b"70g    4");
+
+        assert_eq!(Direction::Right, interpreter.current_direction());
+        assert_eq!(Position::ORIGIN, interpreter.current_position());
+        assert_eq!(EMPTY_STACK, interpreter.stack());
+
+        let status = interpreter.step();
+        assert_eq!(Status::Completed, status);
+        let status = interpreter.step();
+        assert_eq!(Status::Completed, status);
+
+        assert_eq!(&[StackCell(7), StackCell(0)], interpreter.stack());
+
+        let status = interpreter.step();
+        assert_eq!(Status::Completed, status);
+
+        assert_eq!(Direction::Right, interpreter.current_direction());
+        assert_eq!(Position { x: 7, y: 0 }, interpreter.current_position());
+        assert_eq!(&[StackCell(b'4' as i32)], interpreter.stack());
+    }
+
+    #[test]
+    fn test_93_rejects_98_opcodes() {
+        let mut interpreter = one_liner(b"a");
+        assert_eq!(
+            Some(ErrorKind::InvalidOpcode(b'a' as i32)),
+            error_kind(interpreter.step())
+        );
+    }
+
+    #[test]
+    fn test_invalid_opcode_message() {
+        let message = |value| ErrorKind::InvalidOpcode(value).to_string();
+        assert_eq!("Invalid opcode 'X' (88) found", message(88));
+        assert_eq!("Invalid opcode '\\n' (10) found", message(10));
+        assert_eq!("Invalid opcode -1 found", message(-1));
+        assert_eq!("Invalid opcode 55296 found", message(0xD800));
+    }
+
+    #[test]
+    fn test_98_hex_digits() {
+        let mut interpreter = one_liner_98(b"af");
+        assert_eq!(Status::Completed, interpreter.step());
+        assert_eq!(Status::Completed, interpreter.step());
+        assert_eq!(&[StackCell(10), StackCell(15)], interpreter.stack());
+    }
+
+    #[test]
+    fn test_98_reflect_unknown() {
+        let mut interpreter = one_liner_98(b"1X");
+        assert_eq!(Status::Completed, interpreter.step());
+        assert_eq!(Status::Completed, interpreter.step());
+        assert_eq!(Direction::Left, interpreter.current_direction());
+        assert_eq!(Position::ORIGIN, interpreter.current_position());
+    }
+
+    #[test]
+    fn test_98_fetch_character() {
+        let mut interpreter = one_liner_98(b"'A1");
+        assert_eq!(Status::Completed, interpreter.step());
+        assert_eq!(Position { x: 2, y: 0 }, interpreter.current_position());
+        assert_eq!(&[StackCell(b'A' as i32)], interpreter.stack());
+    }
+
+    #[test]
+    fn test_98_comment() {
+        let mut interpreter = one_liner_98(b"1;23;4");
+        assert_eq!(Status::Completed, interpreter.step());
+        assert_eq!(Position { x: 5, y: 0 }, interpreter.current_position());
+        assert_eq!(Status::Completed, interpreter.step());
+        assert_eq!(&[StackCell(1), StackCell(4)], interpreter.stack());
+    }
+
+    #[test]
+    fn test_98_jump() {
+        let mut interpreter = one_liner_98(b"2j12345");
+        assert_eq!(Status::Completed, interpreter.step());
+        assert_eq!(Status::Completed, interpreter.step());
+        assert_eq!(Position { x: 4, y: 0 }, interpreter.current_position());
+        assert_eq!(EMPTY_STACK, interpreter.stack());
+    }
+
+    #[test]
+    fn test_98_iterate() {
+        let mut interpreter = one_liner_98(b"3k1@");
+        assert_eq!(Status::Completed, interpreter.step());
+        assert_eq!(Status::Completed, interpreter.step());
+        assert_eq!(Position { x: 3, y: 0 }, interpreter.current_position());
+        assert_eq!(
+            &[StackCell(1), StackCell(1), StackCell(1)],
+            interpreter.stack()
+        );
+    }
+
+    #[test]
+    fn test_98_flying() {
+        let program = b"11x\n   5\n    .\n     @";
+        let mut interpreter =
+            Interpreter::new(Space::new(program), VecIO::default(), StdOutEventLog).with_settings(
+                Settings {
+                    dialect: Dialect::Funge98,
+                    ..Default::default()
+                },
+            );
+        for _ in 0..3 {
+            assert_eq!(Status::Completed, interpreter.step());
+        }
+        assert_eq!(Direction::Delta(1, 1), interpreter.current_direction());
+        assert_eq!(Position { x: 3, y: 1 }, interpreter.current_position());
+        run_to_end(&mut interpreter);
+        assert_eq!(b"5 ", interpreter.io().output());
+        // Turning keeps the delta's length
+        let mut interpreter = one_liner_98(b"201-x");
+        run_for(&mut interpreter, 5);
+        assert_eq!(Direction::Delta(2, -1), interpreter.current_direction());
+        assert_eq!(
+            Direction::Delta(-1, -2),
+            interpreter.current_direction().turn_left()
+        );
+    }
+
+    #[test]
+    fn test_98_quit() {
+        let mut interpreter = one_liner_98(b"7q");
+        assert_eq!(Status::Completed, interpreter.step());
+        assert_eq!(Status::Terminated, interpreter.step());
+        assert_eq!(7, interpreter.exit_code());
+    }
+
+    #[test]
+    fn test_strict_93_signed_cells() {
+        let program = b"55*8*00p00g@";
+        let mut interpreter = one_liner(program);
+        run_to_end(&mut interpreter);
+        assert_eq!(&[StackCell(200)], interpreter.stack());
+
+        let mut interpreter = one_liner_93(program);
+        run_to_end(&mut interpreter);
+        assert_eq!(&[StackCell(-56)], interpreter.stack());
+    }
+
+    #[test]
+    fn test_strict_93_out_of_bounds() {
+        let mut interpreter = one_liner_93(b"1999*p99*0g@");
+        run_to_end(&mut interpreter);
+        assert_eq!(&[StackCell(0)], interpreter.stack());
+        assert_eq!(80, interpreter.space().cols());
+        assert_eq!(25, interpreter.space().rows());
+    }
+
+    #[test]
+    fn test_put_get_negative() {
+        let mut interpreter = one_liner(b"501-03-p01-03-g@");
+        run_to_end(&mut interpreter);
+        assert_eq!(&[StackCell(5)], interpreter.stack());
+        assert_eq!(Position { x: -1, y: -3 }, interpreter.space().least());
+    }
+
+    #[test]
+    fn test_put_large_value() {
+        let mut interpreter = one_liner(b"88*88**00p00g@");
+        run_to_end(&mut interpreter);
+        assert_eq!(&[StackCell(4096)], interpreter.stack());
+    }
+
+    #[test]
+    fn test_unicode_output() {
+        let mut interpreter = one_liner("\"é\",@".as_bytes());
+        run_to_end(&mut interpreter);
+        assert_eq!("é".as_bytes(), interpreter.io().output());
+    }
+
+    #[test]
+    fn test_scripted_random() {
+        let cases = [(Direction::Right, &b"2 "[..]), (Direction::Left, b"")];
+        for (dir, output) in cases {
+            let random = ScriptedRandom::new([dir]);
+            let mut interpreter = one_liner(b"?2.@").with_random(random);
+            run_to_end(&mut interpreter);
+            assert_eq!(output, interpreter.io().output());
+        }
+    }
+
+    #[test]
+    fn test_divide_by_zero_98() {
+        for program in [b"50/.@", b"50%.@"] {
+            let mut interpreter = one_liner_98(program);
+            run_to_end(&mut interpreter);
+            assert_eq!(b"0 ", interpreter.io().output());
+        }
+    }
+
+    #[test]
+    fn test_divide_by_zero_error() {
+        let settings = Settings {
+            division_by_zero: Some(DivisionByZero::Error),
+            ..Default::default()
+        };
+        let mut interpreter = one_liner(b"50/.@").with_settings(settings);
+        interpreter.step();
+        interpreter.step();
+        let Status::Error(error) = interpreter.step() else {
+            panic!("division by zero did not stop the program");
+        };
+        assert_eq!(ErrorKind::DivisionByZero, error.kind);
+        assert_eq!(Position { x: 2, y: 0 }, error.position);
+        assert_eq!(Direction::Right, error.direction);
+        assert_eq!(2, error.step);
+        assert_eq!(vec![StackCell(5), StackCell(0)], error.stack_top);
+        assert_eq!(2, error.stack_size);
+    }
+
+    #[test]
+    fn test_divide_by_zero_prompt() {
+        let space = Space::new(b"50/.@");
+        let io = VecIO::with_input(b"7\n");
+        let mut interpreter = Interpreter::new(space, io, StdOutEventLog);
+        run_to_end(&mut interpreter);
+        let expected = b"What do you want 5/0 to be? 7 ";
+        assert_eq!(expected, interpreter.io().output());
+    }
+
+    #[test]
+    fn test_divide_by_zero_prompt_waits() {
+        let mut interpreter = one_liner(b"50%.@");
+        interpreter.step();
+        interpreter.step();
+        assert_eq!(Status::Waiting, interpreter.step());
+        assert_eq!(Status::Waiting, interpreter.step());
+        assert_eq!(&[StackCell(5), StackCell(0)], interpreter.stack());
+        let expected = b"What do you want 5%0 to be? ";
+        assert_eq!(expected, interpreter.io().output());
+    }
+
+    #[test]
+    fn test_read_signed_number() {
+        let space = Space::new(b"&&+.@");
+        let io = VecIO::with_input(b"-1000 7\n");
+        let mut interpreter = Interpreter::new(space, io, StdOutEventLog);
+        run_to_end(&mut interpreter);
+        assert_eq!(b"-993 ", interpreter.io().output());
+    }
+
+    fn closed_input(
+        line: &[u8],
+        input: &[u8],
+        end_of_input: Option<EndOfInput>,
+    ) -> DebugInterpreter<'static> {
+        let space = Space::new(line);
+        let io = VecIO::with_input(input).close_input();
+        let settings = Settings {
+            end_of_input,
+            ..Default::default()
+        };
+        Interpreter::new(space, io, StdOutEventLog).with_settings(settings)
+    }
+
+    #[test]
+    fn test_end_of_input_push_negative() {
+        let mut interpreter = closed_input(b"~.~.&.@", b"a", None);
+        run_to_end(&mut interpreter);
+        assert_eq!(b"97 -1 -1 ", interpreter.io().output());
+    }
+
+    #[test]
+    fn test_end_of_input_finishes_number() {
+        let mut interpreter = closed_input(b"&.&.@", b"12", None);
+        run_to_end(&mut interpreter);
+        assert_eq!(b"12 -1 ", interpreter.io().output());
+    }
+
+    #[test]
+    fn test_end_of_input_reflect() {
+        // Reflecting wraps around to the "@"
+        let mut interpreter = closed_input(b"~2.@", b"", Some(EndOfInput::Reflect));
+        run_to_end(&mut interpreter);
+        assert_eq!(b"", interpreter.io().output());
+        assert_eq!(EMPTY_STACK, interpreter.stack());
+    }
+
+    #[test]
+    fn test_end_of_input_error_and_wait() {
+        let mut interpreter = closed_input(b"&", b"", Some(EndOfInput::Error));
+        let expected = Some(ErrorKind::EndOfInput);
+        assert_eq!(expected, error_kind(interpreter.step()));
+        let mut interpreter = closed_input(b"&", b"", Some(EndOfInput::Wait));
+        assert_eq!(Status::Waiting, interpreter.step());
+    }
+
+    #[test]
+    fn test_split() {
+        let mut interpreter = one_liner_98(b"7t.@");
+        interpreter.step();
+        interpreter.step();
+        // The child runs next, moving left from the "t"
+        let ips: Vec<_> = interpreter.ips().map(|ip| (ip.id, ip.cursor.pos)).collect();
+        assert_eq!(
+            vec![(1, Position::ORIGIN), (0, Position { x: 2, y: 0 })],
+            ips
+        );
+        assert_eq!(&[StackCell(7)], interpreter.stack());
+        run_to_end(&mut interpreter);
+        assert_eq!(b"7 ", interpreter.io().output());
+        assert_eq!(1, interpreter.ips().count());
+    }
+
+    #[test]
+    fn test_split_quit_stops_every_ip() {
+        let mut interpreter = one_liner_98(b"t.q");
+        run_to_end(&mut interpreter);
+        assert_eq!(b"", interpreter.io().output());
+    }
+
+    fn output_98(line: &[u8]) -> Vec<u8> {
+        let mut interpreter = one_liner_98(line);
+        run_to_end(&mut interpreter);
+        interpreter.io().output().to_vec()
+    }
+
+    #[test]
+    fn test_jump_far() {
+        // Jumps 1732076671 cells around the 15 cell line, which skips just the "@" after the "j"
+        assert_eq!(b"7 ", &output_98(br"ff*:*:*0\-j@7.@")[..]);
+    }
+
+    #[test]
+    fn test_fingerprint_roma() {
+        assert_eq!(b"1110 ", &output_98(br#""AMOR"4($$MCX++.@"#)[..]);
+    }
+
+    #[test]
+    fn test_fingerprint_modu() {
+        let program = br#""UDOM"4($$07-3M.07-3R.07-3U.@"#;
+        assert_eq!(b"2 -1 1 ", &output_98(program)[..]);
+    }
+
+    #[test]
+    fn test_fingerprint_bool() {
+        let program = br#""LOOB"4($$65A.65O.65X.0N.@"#;
+        assert_eq!(b"4 7 3 -1 ", &output_98(program)[..]);
+    }
+
+    #[test]
+    fn test_fingerprint_unload() {
+        // NULL hides ROMA's "M" until it is unloaded again
+        let program = br#""AMOR"4($$"LLUN"4($$"LLUN"4)M.@"#;
+        assert_eq!(b"1000 ", &output_98(program)[..]);
+    }
+
+    #[test]
+    fn test_fingerprint_unavailable() {
+        // Reflects back onto the "@"
+        let mut interpreter = one_liner_98(b"#@0(.@");
+        run_to_end(&mut interpreter);
+        assert_eq!(b"", interpreter.io().output());
+        assert_eq!(&[StackCell(0)], interpreter.stack());
+    }
+
+    #[test]
+    fn test_begin_end_stack() {
+        let mut interpreter = one_liner_98(b"1232{2}...@");
+        for _ in 0..5 {
+            interpreter.step();
+        }
+        // The top two cells move to the new stack above the old storage offset
+        let ip = interpreter.ips().next().unwrap();
+        assert_eq!(&[StackCell(2), StackCell(3)], &ip.stack[..]);
+        assert_eq!(
+            vec![vec![StackCell(1), StackCell(0), StackCell(0)]],
+            ip.stacks_below
+        );
+        assert_eq!(Position { x: 5, y: 0 }, ip.storage_offset);
+        run_to_end(&mut interpreter);
+        assert_eq!(b"3 2 1 ", interpreter.io().output());
+        assert_eq!(EMPTY_STACK, interpreter.stack());
+    }
+
+    #[test]
+    fn test_stack_under_stack() {
+        assert_eq!(b"7 0 0 ", &output_98(b"70{3u...@")[..]);
+    }
+
+    #[test]
+    fn test_no_second_stack_reflects() {
+        assert_eq!(b"", &output_98(b"#@}1.@")[..]);
+        assert_eq!(b"", &output_98(b"#@u1.@")[..]);
+    }
+
+    #[test]
+    fn test_storage_offset() {
+        // "g" reads relative to the offset set by "{"
+        assert_eq!(b"0", &output_98(b"1{00g,@")[..]);
+    }
+
+    #[test]
+    fn test_fingerprint_long_id() {
+        // Popping 1732076671 cells from an empty stack gives id 0, which isn't available
+        let mut interpreter = one_liner_98(br"#@ff*:*:*0\-(");
+        run_to_end(&mut interpreter);
+        assert_eq!(b"", interpreter.io().output());
+        assert!(interpreter.underflows().total() > 1_732_076_671);
+    }
+
+    #[test]
+    fn test_custom_fingerprint() {
+        struct Answer;
+
+        impl Fingerprint for Answer {
+            fn id(&self) -> i32 {
+                fingerprint::fingerprint_id(b"ANSR")
+            }
+
+            fn instructions(&self) -> &[u8] {
+                b"A"
+            }
+
+            fn execute(&self, _instruction: u8, context: &mut dyn Context) -> Status {
+                context.push(StackCell(42));
+                Status::Completed
+            }
+        }
+
+        let mut interpreter = one_liner_98(br#""RSNA"4(.A.@"#).with_fingerprint(Answer);
+        run_to_end(&mut interpreter);
+        assert_eq!(b"1 42 ", interpreter.io().output());
+    }
+
+    #[test]
+    fn test_builder() {
+        let settings = Settings {
+            strict_93: true,
+            ..Default::default()
+        };
+        let mut interpreter = Interpreter::builder()
+            .with_settings(settings)
+            .with_io(VecIO::default())
+            .with_recorder(StdOutEventLog)
+            .with_seed(3)
+            .build(b"1.@");
+        assert_eq!(settings, interpreter.settings());
+        // The space is laid out for the settings
+        assert_eq!(
+            (25, 80),
+            (interpreter.space().rows(), interpreter.space().cols())
+        );
+        assert_eq!(SeededRandom::new(3), interpreter.snapshot().random);
+        run_to_end(&mut interpreter);
+        assert_eq!(b"1 ", interpreter.io().output());
+
+        let mut interpreter = Interpreter::builder()
+            .with_dialect(Dialect::Funge98)
+            .with_io(VecIO::default())
+            .build(b"'a,@");
+        assert_eq!(Status::Terminated, interpreter.run());
+        assert_eq!(b"a", interpreter.io().output());
+    }
+
+    #[test]
+    fn test_resume_from_snapshot() {
+        let program = br#""AMOR"4($$122{1u&.~,M....@"#;
+        let settings = Settings {
+            dialect: Dialect::Funge98,
+            ..Default::default()
+        };
+        let mut interpreter = Interpreter::new(
+            Space::new(program),
+            VecIO::with_input(b"42 x"),
+            StdOutEventLog,
+        )
+        .with_settings(settings)
+        .with_random(SeededRandom::new(1));
+        for _ in 0..18 {
+            interpreter.step();
+        }
+        assert_eq!(b"42 ", interpreter.io().output());
+
+        let snapshot = interpreter.snapshot();
+        let decoded = Snapshot::decode(&snapshot.encode(), &Fingerprints::default()).unwrap();
+        assert_eq!(snapshot, decoded);
+        assert_eq!(b" x", &decoded.input.bytes[..]);
+
+        let io = VecIO::with_input(&decoded.input.bytes);
+        let mut resumed = Interpreter::from_snapshot(decoded, io, StdOutEventLog);
+        run_to_end(&mut resumed);
+        assert_eq!(b" 1000 0 2 1 ", resumed.io().output());
+    }
+
+    /// Builds an interpreter recording a Funge-98 program that uses quotes with spaces, input,
+    /// "#", "?", stacks, fingerprints, "t", "p" and "q", and that has already read some input
+    fn recording(unread: &[u8], timeline: Timeline) -> Interpreter<VecIO, Timeline> {
+        let program = concat!(
+            " v\n",
+            ">?<\n",
+            r#" >"a  b"~&+#@2{1u0}"AMOR"4($$X."AMOR"4)#@t"Z"51p3q"#,
+        );
+        let settings = Settings {
+            dialect: Dialect::Funge98,
+            ..Default::default()
+        };
+        Interpreter::builder()
+            .with_settings(settings)
+            .with_io(VecIO::with_input(unread).close_input())
+            .with_recorder(timeline)
+            .with_seed(5)
+            .build(program.as_bytes())
+    }
+
+    #[test]
+    fn test_replay_timeline() {
+        let mut interpreter = recording(b"x12\n", Timeline::default());
+        let mut status = Status::Completed;
+        while status == Status::Completed {
+            status = interpreter.step();
+            // The input the program hasn't read isn't part of the recording
+            let mut replayed =
+                recording(&interpreter.io().pending_input().bytes, Timeline::default());
+            interpreter.recorder().replay(&mut replayed);
+            // Replaying the events doesn't count the steps they came from
+            let mut snapshot = interpreter.snapshot();
+            snapshot.steps = 0;
+            assert_eq!(snapshot, replayed.snapshot());
+            assert_eq!(interpreter.io().output(), replayed.io().output());
+        }
+        assert_eq!(Status::Terminated, status);
+        assert_eq!(b"10 ", interpreter.io().output());
+        assert_eq!(3, interpreter.exit_code());
+        let timeline = interpreter.recorder();
+        let events: usize = timeline.steps().iter().map(|step| step.events).sum();
+        assert_eq!(timeline.events().len(), events);
+    }
+
+    /// The state of a recording interpreter that going back in time restores.
+    /// Input that has been read stays read.
+    fn recorded_state(interpreter: &Interpreter<VecIO, Timeline>) -> (Snapshot, Vec<u8>, u64) {
+        let mut snapshot = interpreter.snapshot();
+        snapshot.input = Default::default();
+        let output = interpreter.io().output().to_vec();
+        (snapshot, output, interpreter.steps())
+    }
+
+    #[test]
+    fn test_step_back_and_forward() {
+        let mut interpreter = recording(b"x12\n", Timeline::default());
+        let mut states = vec![recorded_state(&interpreter)];
+        while interpreter.step() == Status::Completed {
+            states.push(recorded_state(&interpreter));
+        }
+        states.push(recorded_state(&interpreter));
+
+        assert!(!interpreter.step_forward());
+        for expected in states.iter().rev().skip(1) {
+            assert!(interpreter.step_back());
+            assert_eq!(*expected, recorded_state(&interpreter));
+        }
+        assert!(!interpreter.step_back());
+        assert_eq!(0, interpreter.recorder().position());
+        for expected in states.iter().skip(1) {
+            assert!(interpreter.step_forward());
+            assert_eq!(*expected, recorded_state(&interpreter));
+        }
+        assert!(!interpreter.step_forward());
+
+        // Executing a step after stepping back forgets the steps after it
+        let recorded = interpreter.recorder().steps().len();
+        interpreter.step_back();
+        interpreter.step_back();
+        assert_eq!(Status::Completed, interpreter.step());
+        assert_eq!(recorded - 1, interpreter.recorder().steps().len());
+        assert!(!interpreter.recorder().can_step_forward());
+    }
+
+    #[test]
+    fn test_seek_checkpoints() {
+        let timeline = Timeline::default()
+            .with_checkpoint_interval(4)
+            .with_capacity(20);
+        let mut interpreter = recording(b"x12\n", timeline);
+        let mut states = vec![recorded_state(&interpreter)];
+        while interpreter.step() == Status::Completed {
+            states.push(recorded_state(&interpreter));
+        }
+        states.push(recorded_state(&interpreter));
+
+        // Old steps are dropped a checkpoint interval at a time
+        let timeline = interpreter.recorder();
+        let (start, end) = (timeline.start(), timeline.end());
+        assert_eq!(states.len() - 1, end);
+        assert!(start > 0 && start.is_multiple_of(4));
+        assert!((17..=20).contains(&timeline.steps().len()));
+
+        assert!(!interpreter.seek(start - 1));
+        assert!(!interpreter.seek(end + 1));
+        for step in (start..=end).rev().step_by(7).chain(start..=end) {
+            assert!(interpreter.seek(step));
+            assert_eq!(step, interpreter.recorder().position());
+            assert_eq!(states[step], recorded_state(&interpreter));
+        }
+    }
+
+    #[test]
+    fn test_limit_exit_codes() {
+        let codes = [
+            ErrorKind::DivisionByZero.exit_code(),
+            ErrorKind::StepLimit(1).exit_code(),
+            ErrorKind::Timeout(Duration::ZERO).exit_code(),
+            ErrorKind::StackLimit(1).exit_code(),
+        ];
+        assert_eq!([1, 3, 4, 5], codes);
+    }
+
+    fn with_underflow(line: &[u8], underflow: Underflow) -> DebugInterpreter<'_> {
+        let settings = Settings {
+            underflow,
+            ..Default::default()
+        };
+        one_liner(line).with_settings(settings)
+    }
+
+    #[test]
+    fn test_count_underflows() {
+        // "$" pops one implicit zero and "\" pops two, while "." pops a real zero
+        let mut interpreter = with_underflow(b"$\\.@", Underflow::Warn);
+        run_to_end(&mut interpreter);
+        let underflows = interpreter.underflows();
+        assert_eq!(3, underflows.total());
+        let expected = vec![(Position::ORIGIN, 1), (Position { x: 1, y: 0 }, 2)];
+        assert_eq!(expected, underflows.locations());
+        // Underflows are counted even when they are allowed
+        let mut interpreter = with_underflow(b"$@", Underflow::Allow);
+        run_to_end(&mut interpreter);
+        assert_eq!(1, interpreter.underflows().total());
+    }
+
+    #[test]
+    fn test_deny_underflow() {
+        let mut interpreter = with_underflow(b"1$$.@", Underflow::Deny);
+        let status = run_for(&mut interpreter, 10);
+        let Status::Error(error) = status else {
+            panic!("underflow did not stop the program");
+        };
+        assert_eq!(ErrorKind::StackUnderflow, error.kind);
+        assert_eq!(Position { x: 2, y: 0 }, error.position);
+        assert_eq!(2, error.step);
+    }
+
+    fn detecting_loops(line: &[u8], input: &[u8]) -> DebugInterpreter<'static> {
+        let space = Space::new(line);
+        let io = VecIO::with_input(input).close_input();
+        let settings = Settings {
+            detect_loops: true,
+            ..Default::default()
+        };
+        Interpreter::new(space, io, StdOutEventLog).with_settings(settings)
+    }
+
+    /// What went wrong, if the program stopped with an error
+    fn error_kind(status: Status) -> Option<ErrorKind> {
+        match status {
+            Status::Error(error) => Some(error.kind),
+            _ => None,
+        }
+    }
+
+    /// Steps until the program stops or the step limit is reached
+    fn run_for(interpreter: &mut DebugInterpreter, steps: usize) -> Status {
+        for _ in 0..steps {
+            let status = interpreter.step();
+            if status != Status::Completed {
+                return status;
+            }
+        }
+        Status::Completed
+    }
+
+    #[test]
+    fn test_detect_loop() {
+        let mut interpreter = detecting_loops(b"1$", b"");
+        let Some(ErrorKind::InfiniteLoop { mut cells, length }) =
+            error_kind(run_for(&mut interpreter, 100))
+        else {
+            panic!("loop was not detected");
+        };
+        assert_eq!(2, length);
+        cells.sort_by_key(|pos| pos.x);
+        assert_eq!(vec![Position::ORIGIN, Position { x: 1, y: 0 }], cells);
+    }
+
+    #[test]
+    fn test_detect_loop_ignores_progress() {
+        // Counts up in the cell below the program forever
+        let mut interpreter = detecting_loops(b"01g1+01p", b"");
+        assert_eq!(Status::Completed, run_for(&mut interpreter, 10000));
+        // Pushes one more cell each time around
+        let mut interpreter = detecting_loops(b"1", b"");
+        assert_eq!(Status::Completed, run_for(&mut interpreter, 10000));
+    }
+
+    #[test]
+    fn test_detect_loop_after_input_and_random() {
+        let mut interpreter = detecting_loops(b"?", b"");
+        assert_eq!(Status::Completed, run_for(&mut interpreter, 10000));
+        // Input can change what happens next, but once it has ended it can't
+        let mut interpreter = detecting_loops(b"~$", b"abcdef");
+        let status = run_for(&mut interpreter, 100);
+        assert!(matches!(
+            error_kind(status),
+            Some(ErrorKind::InfiniteLoop { length: 2, .. })
+        ));
+        assert!(interpreter.io().pending_input().bytes.is_empty());
+    }
+
+    #[test]
+    fn test_put_ahead_of_ip() {
+        // Writes "@" over a space further along the row
+        let mut interpreter = one_liner(b"\"@\"90p 1. 2.@");
+        assert_eq!(Status::Terminated, run_for(&mut interpreter, 100));
+        assert_eq!(b"1 ", interpreter.io().output());
+        // Writes "@" just past the end of the row, so the IP stops there instead of wrapping
+        let mut interpreter = one_liner(b"\"@\"80p1.");
+        assert_eq!(Status::Terminated, run_for(&mut interpreter, 100));
+        assert_eq!(b"1 ", interpreter.io().output());
+    }
+
+    #[test]
+    fn test_eq_ignores_underflows() {
+        // Pops from an empty stack every time around
+        let mut interpreter = Interpreter::new(Space::new(b"$1+"), VecIO::default(), ());
+        for _ in 0..30 {
+            interpreter.step();
+        }
+        assert!(interpreter.underflows().total() > 0);
+        let resumed = Interpreter::from_snapshot(interpreter.snapshot(), VecIO::default(), ());
+        assert_eq!(0, resumed.underflows().total());
+        assert_eq!(interpreter, resumed);
+    }
+}
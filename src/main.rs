@@ -1,26 +1,27 @@
 #![allow(clippy::collapsible_else_if)]
-mod analyze;
-mod core;
 mod debugger;
-mod interpreter;
-mod io;
-mod record;
-mod space;
 mod terminal;
 mod tui;
 
-use std::path::PathBuf;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::{cmp::min, fs};
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use ftail::Ftail;
 use log::LevelFilter;
-use space::Space;
 use thiserror::Error;
 
-use crate::interpreter::{Interpreter, InterpreterError, Status};
+use bft::{
+    CompileError, Dialect, DivisionByZero, EndOfInput, ErrorKind, IO, Interpreter,
+    InterpreterError, JsonLinesWriter, Position, Random, Record, SeededRandom, Settings, Snapshot,
+    SnapshotError, Status, StdIO, Target, TextTrace, Timeline, Trace, TraceError, TraceWriter,
+    Underflow, render_error, render_underflows,
+};
+
+use crate::debugger::Debugger;
 
 /// Befunge runtime and development tools.
 #[derive(Parser)]
@@ -32,15 +33,246 @@ struct Cli {
 #[derive(Subcommand)]
 enum Command {
     /// Run a Befunge program.
-    Run { path: PathBuf },
+    Run {
+        /// Path of program to run.
+        path: PathBuf,
+        #[command(flatten)]
+        options: ProgramOptions,
+        #[command(flatten)]
+        snapshots: SnapshotOptions,
+        #[command(flatten)]
+        limits: RunLimits,
+        /// Write every step the program takes to this file, for `replay` or `debug --trace`.
+        #[arg(long, value_name = "FILE")]
+        trace: Option<PathBuf>,
+        /// Write every step the program takes to this file as one JSON object per line, for scripts to read.
+        #[arg(long, value_name = "FILE")]
+        events_jsonl: Option<PathBuf>,
+    },
+    /// Run a program again from a trace written by `run --trace`, reading the input it read.
+    Replay {
+        /// Path of trace to replay.
+        path: PathBuf,
+    },
+    /// Print a trace written by `run --trace`, one line per step.
+    Trace {
+        /// Path of trace to print.
+        path: PathBuf,
+        /// Only print steps executed inside this rectangle, given by two opposite corners.
+        #[arg(long, value_name = "X1,Y1,X2,Y2", value_parser = parse_region)]
+        region: Option<Region>,
+        /// Only print steps that execute one of these instructions.
+        #[arg(long, value_name = "CHARS")]
+        opcode: Option<String>,
+    },
     /// Run the specified program in an interactive debugger.
     Debug {
         /// Path of program to run.
-        path: PathBuf,
+        #[arg(required_unless_present_any = ["from_snapshot", "trace"])]
+        path: Option<PathBuf>,
+        /// Resume from a snapshot saved by `run` instead of starting a program.
+        /// The settings and random state are restored from the snapshot.
+        #[arg(long, value_name = "FILE", conflicts_with = "path")]
+        from_snapshot: Option<PathBuf>,
+        /// Step back and forth through a trace written by `run --trace` instead of running a program.
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["path", "from_snapshot"])]
+        trace: Option<PathBuf>,
+        #[command(flatten)]
+        options: ProgramOptions,
+        /// The most steps kept for stepping back before the oldest are dropped.
+        #[arg(long, value_name = "STEPS", default_value_t = 100_000)]
+        history: usize,
         /// Log level
         #[arg(long)]
-        log_level: Option<LevelFilter>
+        log_level: Option<LevelFilter>,
     },
+    /// Compile a Befunge-93 program to Rust or C source code.
+    Compile {
+        /// Path of program to compile.
+        path: PathBuf,
+        /// Write the source code to this file instead of stdout.
+        #[arg(long, short, value_name = "FILE")]
+        output: Option<PathBuf>,
+        /// Language to compile to, C if the output file ends in ".c" and Rust otherwise.
+        #[arg(long)]
+        target: Option<Target>,
+        #[command(flatten)]
+        options: ProgramOptions,
+    },
+}
+
+/// Options that control how a program is interpreted.
+#[derive(Args)]
+struct ProgramOptions {
+    /// Dialect of the program, guessed from the file extension by default.
+    #[arg(long)]
+    dialect: Option<Dialect>,
+    /// Match the reference Befunge-93 implementation, including its 80x25 size limit.
+    #[arg(long, conflicts_with = "dialect")]
+    strict_93: bool,
+    /// What "/" and "%" do when dividing by zero, chosen by the dialect by default.
+    #[arg(long)]
+    division_by_zero: Option<DivisionByZero>,
+    /// What "~" and "&" do once input has ended, chosen by the dialect by default.
+    #[arg(long)]
+    end_of_input: Option<EndOfInput>,
+    /// Seed for the random number generator, chosen randomly by default.
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Stop with an error when the program repeats a state it has already been in.
+    /// Only detects loops that don't use input or "?".
+    #[arg(long)]
+    detect_loops: bool,
+    /// Count pops from an empty stack and list where they happened when the program stops.
+    #[arg(long, conflicts_with = "deny_underflow")]
+    warn_underflow: bool,
+    /// Stop with an error when the program pops from an empty stack.
+    #[arg(long)]
+    deny_underflow: bool,
+}
+
+impl ProgramOptions {
+    fn settings(&self, path: &Path) -> Settings {
+        let dialect = if self.strict_93 {
+            Dialect::Befunge93
+        } else {
+            self.dialect
+                .or_else(|| Dialect::from_path(path))
+                .unwrap_or_default()
+        };
+        Settings {
+            dialect,
+            strict_93: self.strict_93,
+            division_by_zero: self.division_by_zero,
+            end_of_input: self.end_of_input,
+            detect_loops: self.detect_loops,
+            underflow: self.underflow(),
+        }
+    }
+
+    fn underflow(&self) -> Underflow {
+        if self.deny_underflow {
+            Underflow::Deny
+        } else if self.warn_underflow {
+            Underflow::Warn
+        } else {
+            Underflow::Allow
+        }
+    }
+
+    fn random(&self) -> SeededRandom {
+        let random = match self.seed {
+            Some(seed) => SeededRandom::new(seed),
+            None => SeededRandom::from_entropy(),
+        };
+        log::info!("Random seed: {}", random.seed());
+        random
+    }
+}
+
+/// Where to save snapshots of the interpreter when a run stops.
+#[derive(Args)]
+struct SnapshotOptions {
+    /// Save a snapshot of the interpreter to this file when the program stops for any reason.
+    #[arg(long, value_name = "FILE")]
+    snapshot_on_exit: Option<PathBuf>,
+    /// Save a snapshot of the interpreter to this file if the program stops with an error.
+    #[arg(long, value_name = "FILE")]
+    snapshot_on_error: Option<PathBuf>,
+}
+
+impl SnapshotOptions {
+    fn save<R: Record>(
+        &self,
+        interpreter: &Interpreter<StdIO, R>,
+        error: bool,
+    ) -> Result<(), SnapshotError> {
+        let on_error = self.snapshot_on_error.iter().filter(|_| error);
+        for path in self.snapshot_on_exit.iter().chain(on_error) {
+            interpreter.snapshot().save(path)?;
+            log::info!("Saved snapshot to {}", path.display());
+        }
+        Ok(())
+    }
+}
+
+/// Limits that stop a run which goes on for too long.
+#[derive(Args, Default)]
+struct RunLimits {
+    /// Stop with an error after executing this many instructions.
+    #[arg(long, value_name = "STEPS")]
+    max_steps: Option<u64>,
+    /// Stop with an error after running for this many seconds, including time spent waiting for input.
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+    timeout: Option<Duration>,
+    /// Stop with an error once an IP holds more than this many cells across its stacks.
+    #[arg(long, value_name = "CELLS")]
+    max_stack: Option<usize>,
+}
+
+impl RunLimits {
+    /// The limit the run has reached, if any
+    fn check<IOImpl: IO, R: Record>(
+        &self,
+        interpreter: &Interpreter<IOImpl, R>,
+        elapsed: Duration,
+    ) -> Option<ErrorKind> {
+        if let Some(max_steps) = self.max_steps
+            && interpreter.steps() >= max_steps
+        {
+            return Some(ErrorKind::StepLimit(max_steps));
+        }
+        if let Some(timeout) = self.timeout
+            && elapsed >= timeout
+        {
+            return Some(ErrorKind::Timeout(timeout));
+        }
+        if let Some(max_stack) = self.max_stack
+            && interpreter.ips().any(|ip| ip.stack_size() > max_stack)
+        {
+            return Some(ErrorKind::StackLimit(max_stack));
+        }
+        None
+    }
+}
+
+fn parse_seconds(arg: &str) -> Result<Duration, String> {
+    let seconds: f64 = arg.parse().map_err(|_| format!("{arg} is not a number"))?;
+    Duration::try_from_secs_f64(seconds).map_err(|error| error.to_string())
+}
+
+/// A rectangle of the program space, including its edges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Region {
+    min: Position,
+    max: Position,
+}
+
+impl Region {
+    fn contains(&self, pos: Position) -> bool {
+        (self.min.x..=self.max.x).contains(&pos.x) && (self.min.y..=self.max.y).contains(&pos.y)
+    }
+}
+
+fn parse_region(arg: &str) -> Result<Region, String> {
+    let coords = arg
+        .split(',')
+        .map(|coord| coord.trim().parse::<i32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("{arg} is not a list of numbers"))?;
+    let [x1, y1, x2, y2] = coords[..] else {
+        return Err(format!("{arg} is not four numbers, x1,y1,x2,y2"));
+    };
+    Ok(Region {
+        min: Position {
+            x: x1.min(x2),
+            y: y1.min(y2),
+        },
+        max: Position {
+            x: x1.max(x2),
+            y: y1.max(y2),
+        },
+    })
 }
 
 #[derive(Debug, Error)]
@@ -49,24 +281,72 @@ enum Error {
     IO(#[from] std::io::Error),
     #[error("Interpreter Error")]
     Interpreter(#[from] InterpreterError),
+    #[error("Snapshot Error")]
+    Snapshot(#[from] SnapshotError),
+    #[error("Compile Error")]
+    Compile(#[from] CompileError),
+    #[error("Trace Error")]
+    Trace(#[from] TraceError),
+}
+
+impl Error {
+    fn exit_code(&self) -> i32 {
+        match self {
+            Error::Interpreter(error) => error.exit_code(),
+            _ => 1,
+        }
+    }
 }
 
 fn main() {
     let cli = Cli::parse();
     let result = match cli.command {
-        Command::Run { path } => run(path),
-        Command::Debug { path, log_level } => {
+        Command::Run {
+            path,
+            options,
+            snapshots,
+            limits,
+            trace,
+            events_jsonl,
+        } => run(
+            path,
+            &options,
+            &snapshots,
+            &limits,
+            trace.as_deref(),
+            events_jsonl.as_deref(),
+        ),
+        Command::Replay { path } => replay(&path),
+        Command::Trace {
+            path,
+            region,
+            opcode,
+        } => print_trace(&path, region, opcode.as_deref()).map(|()| 0),
+        Command::Debug {
+            path,
+            from_snapshot,
+            trace,
+            options,
+            history,
+            log_level,
+        } => {
             init_logging(log_level);
-            let name = path.file_name().unwrap().to_string_lossy().into_owned();
-            let program = fs::read(path).unwrap();
-            tui::run_tui(name, program)
+            debug(path, from_snapshot, trace, &options, history).map(|()| 0)
         }
+        Command::Compile {
+            path,
+            output,
+            target,
+            options,
+        } => compile(path, output, target, &options).map(|()| 0),
     };
-    if let Err(error) = result {
-        log::error!("{:?}", error);
-        std::process::exit(1);
+    match result {
+        Ok(code) => std::process::exit(code),
+        Err(error) => {
+            log::error!("{:?}", error);
+            std::process::exit(error.exit_code());
+        }
     }
-    std::process::exit(0)
 }
 
 fn init_logging(log_level: Option<LevelFilter>) {
@@ -101,18 +381,122 @@ fn init_logging(log_level: Option<LevelFilter>) {
     }
 }
 
-fn run(path: PathBuf) -> Result<(), Error> {
-    let program = fs::read(path)?;
-    let space = Space::new(&program);
-    let mut interpreter = Interpreter::new_std(space);
+fn debug(
+    path: Option<PathBuf>,
+    from_snapshot: Option<PathBuf>,
+    trace: Option<PathBuf>,
+    options: &ProgramOptions,
+    history: usize,
+) -> Result<(), Error> {
+    let timeline = Timeline::default().with_capacity(history);
+    let (name, debugger) = match (path, from_snapshot) {
+        _ if let Some(trace_path) = trace => {
+            let trace = Trace::load(&trace_path)?;
+            let debugger = Debugger::from_trace(&trace, timeline);
+            (file_name(&trace_path), debugger)
+        }
+        (_, Some(snapshot_path)) => {
+            let snapshot = Snapshot::load(&snapshot_path)?;
+            let debugger = Debugger::from_snapshot(snapshot, timeline);
+            (file_name(&snapshot_path), debugger)
+        }
+        (Some(path), None) => {
+            let settings = options.settings(&path);
+            let program = fs::read(&path)?;
+            let debugger = Debugger::new(&program, settings, options.random(), timeline);
+            (file_name(&path), debugger)
+        }
+        (None, None) => unreachable!("clap requires a path, a snapshot or a trace"),
+    };
+    tui::run_tui(name, debugger)
+}
+
+fn compile(
+    path: PathBuf,
+    output: Option<PathBuf>,
+    target: Option<Target>,
+    options: &ProgramOptions,
+) -> Result<(), Error> {
+    let target =
+        target.unwrap_or_else(
+            || match output.as_ref().and_then(|output| output.extension()) {
+                Some(extension) if extension == "c" => Target::C,
+                _ => Target::Rust,
+            },
+        );
+    let settings = options.settings(&path);
+    let program = fs::read(&path)?;
+    let source = bft::compile(&settings.space(&program), settings, options.seed, target)
+        .inspect_err(|error| eprintln!("error: {error}"))?;
+    match output {
+        Some(output) => fs::write(output, source)?,
+        None => print!("{source}"),
+    }
+    Ok(())
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap().to_string_lossy().into_owned()
+}
+
+fn run(
+    path: PathBuf,
+    options: &ProgramOptions,
+    snapshots: &SnapshotOptions,
+    limits: &RunLimits,
+    trace: Option<&Path>,
+    events_jsonl: Option<&Path>,
+) -> Result<i32, Error> {
+    let program = fs::read(&path)?;
+    let mut interpreter = Interpreter::builder()
+        .with_settings(options.settings(&path))
+        .with_random(options.random())
+        .build(&program);
+    let show_seed = options.seed.is_none();
+    if trace.is_none() && events_jsonl.is_none() {
+        return run_interpreter(&mut interpreter, &path, show_seed, snapshots, limits);
+    }
+
+    let start = interpreter.snapshot();
+    let trace = trace
+        .map(|trace| TraceWriter::create(trace, &start))
+        .transpose()?;
+    let events = events_jsonl
+        .map(|events| JsonLinesWriter::create(events, &start))
+        .transpose()?;
+    let mut interpreter = Interpreter::from_snapshot(start, StdIO::default(), (trace, events));
+    let result = run_interpreter(&mut interpreter, &path, show_seed, snapshots, limits);
+    let (trace, events) = interpreter.recorder_mut();
+    if let Some(trace) = trace {
+        trace.finish()?;
+    }
+    if let Some(events) = events {
+        events.finish()?;
+    }
+    result
+}
 
+fn run_interpreter<R: Record>(
+    interpreter: &mut Interpreter<StdIO, R>,
+    path: &Path,
+    show_seed: bool,
+    snapshots: &SnapshotOptions,
+    limits: &RunLimits,
+) -> Result<i32, Error> {
+    let started = Instant::now();
     let mut wait_count = 0;
     loop {
+        if let Some(kind) = limits.check(interpreter, started.elapsed()) {
+            let error = interpreter.error(kind);
+            eprint!("{}", render_error(&error, interpreter.space(), path));
+            print_underflows(interpreter, path);
+            print_seed(interpreter, show_seed, true);
+            snapshots.save(interpreter, true)?;
+            return Err(error.into());
+        }
         let status = interpreter.step();
         match status {
-            Status::Completed => {
-                wait_count = 0;
-            }
+            Status::Completed => wait_count = 0,
             Status::Waiting => {
                 wait_count += 1;
                 let wait = min(wait_count, 500);
@@ -120,165 +504,155 @@ fn run(path: PathBuf) -> Result<(), Error> {
                 sleep(wait);
             }
             Status::Terminated => {
-                return Ok(());
+                print_underflows(interpreter, path);
+                print_seed(interpreter, show_seed, false);
+                snapshots.save(interpreter, false)?;
+                return Ok(interpreter.exit_code());
             }
             Status::Error(error) => {
+                eprint!("{}", render_error(&error, interpreter.space(), path));
+                print_underflows(interpreter, path);
+                print_seed(interpreter, show_seed, true);
+                snapshots.save(interpreter, true)?;
                 return Err(error.into());
             }
         }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::core::{Direction, Position, StackCell};
-    use super::interpreter::{Interpreter, Status};
-    use crate::core::GridCell;
-    use crate::io::VecIO;
-    use crate::record::StdOutEventLog;
-    use crate::space::Space;
-
-    type DebugInterpreter<'src> = Interpreter<VecIO, StdOutEventLog>;
-
-    const EMPTY_STACK: &[StackCell] = &[];
-
-    fn one_liner(line: &[u8]) -> DebugInterpreter {
-        let program = Vec::from(line);
-        let space = Space::new(&program);
-        let io = VecIO::default();
-        Interpreter::new(space, io, StdOutEventLog)
+/// Runs a traced program again, checking that every step goes where the trace says it went
+fn replay(path: &Path) -> Result<i32, Error> {
+    let trace = Trace::load(path)?;
+    let mut interpreter = trace.interpreter(StdIO::default());
+    let mut status = Status::Completed;
+    // Steps that waited for input are taken in one go, since the input is already there
+    for (step, traced) in trace.steps.iter().filter(|step| !step.waited).enumerate() {
+        let found = interpreter.current_position();
+        if status != Status::Completed || found != traced.at {
+            let expected = traced.at;
+            return Err(TraceError::Diverged {
+                step,
+                expected,
+                found,
+            }
+            .into());
+        }
+        status = interpreter.step();
     }
-
-    #[test]
-    fn test_initial_settings() {
-        let interpreter = one_liner(&[]);
-        assert_eq!(Direction::Right, interpreter.current_direction());
-        assert_eq!(Position::ORIGIN, interpreter.current_position());
-        assert_eq!(EMPTY_STACK, interpreter.stack());
+    match status {
+        Status::Completed | Status::Waiting => {
+            eprintln!("The trace ends before the program did");
+            Ok(0)
+        }
+        Status::Terminated => Ok(interpreter.exit_code()),
+        Status::Error(error) => {
+            eprint!("{}", render_error(&error, interpreter.space(), path));
+            Err(error.into())
+        }
     }
+}
 
-    #[test]
-    fn test_push_num() {
-        let cases: [(u8, u8); 10] = [
-            (b'0', 0),
-            (b'1', 1),
-            (b'2', 2),
-            (b'3', 3),
-            (b'4', 4),
-            (b'5', 5),
-            (b'6', 6),
-            (b'7', 7),
-            (b'8', 8),
-            (b'9', 9),
-        ];
-        for (opcode, number) in cases.iter() {
-            test_push_num_recipe(*opcode, *number);
+/// Prints the steps of a trace that match the filters, skipping steps that only waited for input
+fn print_trace(path: &Path, region: Option<Region>, opcodes: Option<&str>) -> Result<(), Error> {
+    let trace = Trace::load(path)?;
+    let mut text = TextTrace::new(&trace.start);
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    for (step, events) in trace.step_events() {
+        // Every step is formatted, so that IPs are followed through the steps that aren't printed
+        let line = text.line(step, events);
+        let in_region = region.is_none_or(|region| region.contains(step.at));
+        let instruction = char::from_u32(step.instruction.0 as u32);
+        let is_opcode =
+            opcodes.is_none_or(|opcodes| instruction.is_some_and(|c| opcodes.contains(c)));
+        if !step.waited && in_region && is_opcode {
+            writeln!(out, "{line}")?;
         }
     }
+    out.flush()?;
+    Ok(())
+}
 
-    fn test_push_num_recipe(opcode: u8, number: u8) {
-        let mut interpreter = one_liner(&[opcode]);
-
-        let status = interpreter.step();
-        assert_eq!(Status::Completed, status);
-
-        assert_eq!(Direction::Right, interpreter.current_direction());
-        assert_eq!(Position { x: 0, y: 0 }, interpreter.current_position());
-        assert_eq!(&[StackCell(number as i32)], interpreter.stack());
+/// Lists where the program popped from an empty stack, if it was asked for
+fn print_underflows<IOImpl: IO, R: Record, G: Random>(
+    interpreter: &Interpreter<IOImpl, R, G>,
+    path: &Path,
+) {
+    let underflows = interpreter.underflows();
+    if interpreter.settings().underflow == Underflow::Warn && underflows.total() > 0 {
+        eprint!("{}", render_underflows(underflows, path));
     }
+}
 
-    #[test]
-    fn test_left_arrow() {
-        let mut interpreter = one_liner(b"1<");
-
-        let status = interpreter.step();
-        assert_eq!(Status::Completed, status);
-
-        assert_eq!(Direction::Right, interpreter.current_direction());
-        assert_eq!(Position { x: 1, y: 0 }, interpreter.current_position());
-        assert_eq!(&[StackCell(1)], interpreter.stack());
+/// Shows the seed `run` picked when the run depended on it or failed,
+/// so that it can be reproduced with `--seed`
+fn print_seed<IOImpl: IO, R: Record>(
+    interpreter: &Interpreter<IOImpl, R>,
+    show_seed: bool,
+    failed: bool,
+) {
+    let random = interpreter.random();
+    // The state only moves away from the seed once "?" has run
+    if show_seed && (failed || random.state() != random.seed()) {
+        eprintln!("Random seed: {}", random.seed());
+    }
+}
 
-        let status = interpreter.step();
-        assert_eq!(Status::Completed, status);
+#[cfg(test)]
+mod tests {
+    use bft::VecIO;
+    use std::time::Duration;
 
-        assert_eq!(Direction::Left, interpreter.current_direction());
-        assert_eq!(Position::ORIGIN, interpreter.current_position());
-        assert_eq!(&[StackCell(1)], interpreter.stack());
-    }
+    use super::*;
 
     #[test]
-    fn test_arrow_loop() {
-        let program = vec![b'v', b'<', b'\n', b'>', b'^'];
-        let io = VecIO::default();
-        let space = Space::new(&program);
-        let mut interpreter = Interpreter::new(space, io, StdOutEventLog);
-
-        let sequence = [
-            (0, 1, Direction::Down),
-            (1, 1, Direction::Right),
-            (1, 0, Direction::Up),
-            (0, 0, Direction::Left),
-        ];
-
-        for _ in 0..255 {
-            for (x, y, direction) in sequence.iter().copied() {
-                let status = interpreter.step();
-                assert_eq!(Status::Completed, status);
-
-                assert_eq!(direction, interpreter.current_direction());
-                assert_eq!(Position { x, y }, interpreter.current_position());
-                assert_eq!(EMPTY_STACK, interpreter.stack());
-            }
+    fn test_limits() {
+        let mut interpreter = Interpreter::builder().with_io(VecIO::default()).build(b"1");
+        for _ in 0..9 {
+            interpreter.step();
         }
-    }
-
-    #[test]
-    fn test_put() {
-        let mut interpreter = one_liner(b"211p3");
-
-        // Push the three numbers
-        assert_eq!(Status::Completed, interpreter.step());
-        assert_eq!(Status::Completed, interpreter.step());
-        assert_eq!(Status::Completed, interpreter.step());
-        // Verify that the numbers are on the stack
-        assert_eq!(Position { x: 3, y: 0 }, interpreter.current_position());
+        let second = Duration::from_secs(1);
+        let limits = RunLimits {
+            max_steps: Some(10),
+            timeout: Some(second),
+            max_stack: Some(10),
+        };
+        assert_eq!(None, limits.check(&interpreter, Duration::ZERO));
         assert_eq!(
-            &[StackCell(2), StackCell(1), StackCell(1)],
-            interpreter.stack()
+            Some(ErrorKind::Timeout(second)),
+            limits.check(&interpreter, second)
         );
-        // Step over the p command
-        assert_eq!(Status::Completed, interpreter.step());
-        // Verify that the position and direction are correct
-        assert_eq!(Position { x: 4, y: 0 }, interpreter.current_position());
-        // Verify that the stack is now empty
-        assert_eq!(EMPTY_STACK, interpreter.stack());
-        // Verify that the value 2 was placed into the specified position
+        interpreter.step();
         assert_eq!(
-            GridCell(2),
-            interpreter.space().get_cell(Position { x: 1, y: 1 })
+            Some(ErrorKind::StepLimit(10)),
+            limits.check(&interpreter, Duration::ZERO)
         );
+        interpreter.step();
+        let stack_limit = RunLimits {
+            max_stack: Some(10),
+            ..Default::default()
+        };
+        assert_eq!(
+            Some(ErrorKind::StackLimit(10)),
+            stack_limit.check(&interpreter, Duration::ZERO)
+        );
+        assert_eq!(None, RunLimits::default().check(&interpreter, second));
     }
 
     #[test]
-    fn test_get() {
-        let mut interpreter = one_liner(b"70g    4");
-
-        assert_eq!(Direction::Right, interpreter.current_direction());
-        assert_eq!(Position::ORIGIN, interpreter.current_position());
-        assert_eq!(EMPTY_STACK, interpreter.stack());
-
-        let status = interpreter.step();
-        assert_eq!(Status::Completed, status);
-        let status = interpreter.step();
-        assert_eq!(Status::Completed, status);
-
-        assert_eq!(&[StackCell(7), StackCell(0)], interpreter.stack());
-
-        let status = interpreter.step();
-        assert_eq!(Status::Completed, status);
+    fn test_parse_seconds() {
+        assert_eq!(Ok(Duration::from_millis(1500)), parse_seconds("1.5"));
+        assert!(parse_seconds("-1").is_err());
+        assert!(parse_seconds("soon").is_err());
+    }
 
-        assert_eq!(Direction::Right, interpreter.current_direction());
-        assert_eq!(Position { x: 7, y: 0 }, interpreter.current_position());
-        assert_eq!(&[StackCell(b'4' as i32)], interpreter.stack());
+    #[test]
+    fn test_parse_region() {
+        let region = parse_region("3,4,1,0").unwrap();
+        assert_eq!(Position { x: 1, y: 0 }, region.min);
+        assert_eq!(Position { x: 3, y: 4 }, region.max);
+        assert!(region.contains(Position { x: 3, y: 0 }));
+        assert!(!region.contains(Position { x: 0, y: 2 }));
+        assert!(parse_region("1,2,3").is_err());
+        assert!(parse_region("a,b,c,d").is_err());
     }
 }
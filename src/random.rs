@@ -0,0 +1,161 @@
+use std::collections::VecDeque;
+
+use crate::core::Direction;
+
+/// A source of randomness for the "?" instruction
+pub trait Random {
+    fn direction(&mut self) -> Direction;
+    /// Goes back to before the last direction was generated, when stepping backwards
+    fn rewind(&mut self, dir: Direction);
+}
+
+const DIRECTIONS: [Direction; 4] = [
+    Direction::Right,
+    Direction::Left,
+    Direction::Up,
+    Direction::Down,
+];
+
+/// The amount the state of [`SeededRandom`] advances by for every number it generates
+const GAMMA: u64 = 0x9E3779B97F4A7C15;
+
+/// A small deterministic generator (SplitMix64) so that runs
+/// with the same seed are reproducible across platforms and versions.
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub struct SeededRandom {
+    seed: u64,
+    state: u64,
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, state: seed }
+    }
+
+    /// Creates a generator with a seed chosen by the operating system
+    pub fn from_entropy() -> Self {
+        Self::new(rand::random())
+    }
+
+    /// The seed the generator was created with
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The internal state, which changes every time a direction is generated
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    /// Recreates a generator that has already generated some directions
+    pub fn resume(seed: u64, state: u64) -> Self {
+        Self { seed, state }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(GAMMA);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+}
+
+impl Random for SeededRandom {
+    fn direction(&mut self) -> Direction {
+        DIRECTIONS[(self.next_u64() >> 62) as usize]
+    }
+
+    fn rewind(&mut self, _dir: Direction) {
+        self.state = self.state.wrapping_sub(GAMMA);
+    }
+}
+
+/// Replays a fixed sequence of directions, repeating the last one when it runs out.
+/// Useful for testing programs that use "?".
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Default)]
+pub struct ScriptedRandom {
+    directions: VecDeque<Direction>,
+    last: Option<Direction>,
+}
+
+impl ScriptedRandom {
+    pub fn new(directions: impl IntoIterator<Item = Direction>) -> Self {
+        Self {
+            directions: directions.into_iter().collect(),
+            last: None,
+        }
+    }
+}
+
+impl Random for ScriptedRandom {
+    fn direction(&mut self) -> Direction {
+        if let Some(dir) = self.directions.pop_front() {
+            self.last = Some(dir);
+        }
+        self.last.unwrap_or(Direction::Right)
+    }
+
+    fn rewind(&mut self, dir: Direction) {
+        self.directions.push_front(dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded_is_deterministic() {
+        let mut a = SeededRandom::new(42);
+        let mut b = SeededRandom::new(42);
+        for _ in 0..100 {
+            assert_eq!(a.direction(), b.direction());
+        }
+    }
+
+    #[test]
+    fn test_rewind() {
+        let mut random = SeededRandom::new(42);
+        let dirs: Vec<_> = (0..3).map(|_| random.direction()).collect();
+        random.rewind(dirs[2]);
+        random.rewind(dirs[1]);
+        assert_eq!(dirs[1..], [random.direction(), random.direction()]);
+
+        let mut scripted = ScriptedRandom::new([Direction::Up]);
+        let up = scripted.direction();
+        scripted.rewind(up);
+        assert_eq!(Direction::Up, scripted.direction());
+    }
+
+    #[test]
+    fn test_seeded_uses_every_direction() {
+        let mut random = SeededRandom::new(0);
+        let mut seen = Vec::new();
+        for _ in 0..100 {
+            let dir = random.direction();
+            if !seen.contains(&dir) {
+                seen.push(dir);
+            }
+        }
+        assert_eq!(seen.len(), 4);
+    }
+
+    #[test]
+    fn test_resume() {
+        let mut random = SeededRandom::new(7);
+        random.direction();
+        let mut resumed = SeededRandom::resume(random.seed(), random.state());
+        for _ in 0..10 {
+            assert_eq!(random.direction(), resumed.direction());
+        }
+    }
+
+    #[test]
+    fn test_scripted() {
+        let mut random = ScriptedRandom::new([Direction::Up, Direction::Left]);
+        assert_eq!(random.direction(), Direction::Up);
+        assert_eq!(random.direction(), Direction::Left);
+        assert_eq!(random.direction(), Direction::Left);
+    }
+}
@@ -1,5 +1,21 @@
-use crate::core::{GridCell, Position, StackCell};
+use std::collections::VecDeque;
 
+use crate::{
+    core::{Direction, GridCell, Position, StackCell},
+    fingerprint::Semantics,
+    interpreter::{InstructionPointer, Interpreter},
+    io::{IO, Input},
+    random::Random,
+    space::Space,
+};
+
+/// The number of steps between checkpoints a timeline takes by default
+const CHECKPOINT_INTERVAL: usize = 1_000;
+/// The number of steps a timeline keeps by default before dropping the oldest
+const CAPACITY: usize = 100_000;
+
+/// Receives every change the interpreter makes to its state, one step at a time.
+/// Stack, cursor and storage offset events are for the IP executing the step.
 pub trait Record {
     fn start_step(&mut self, at: Position, instruction: GridCell);
     fn rollback_step(&mut self);
@@ -11,6 +27,36 @@ pub trait Record {
     fn push(&mut self, new: StackCell);
     fn enter_quote(&mut self);
     fn exit_quote(&mut self);
+
+    fn begin_stack(&mut self);
+    fn end_stack(&mut self, old: &[StackCell]);
+    fn pop_under(&mut self, old: StackCell);
+    fn pop_under_bottom(&mut self);
+    fn push_under(&mut self, new: StackCell);
+    fn storage_offset(&mut self, old: Position, new: Position);
+
+    fn move_cursor(&mut self, old: Position, new: Position);
+    fn turn(&mut self, old: Direction, new: Direction);
+    fn output(&mut self, bytes: &[u8]);
+    fn read_byte(&mut self, input: Input<u8>);
+    fn read_number(&mut self, input: Input<i32>);
+    fn random(&mut self, dir: Direction);
+    fn exit_code(&mut self, old: i32, new: i32);
+    fn division_prompted(&mut self, prompted: bool);
+    fn semantics(&mut self, old: &Semantics, new: &Semantics);
+
+    fn split(&mut self, child: &InstructionPointer);
+    fn next_ip(&mut self);
+    fn end_ip(&mut self, old: &InstructionPointer);
+    fn clear_queue(&mut self, old: &[InstructionPointer]);
+
+    /// Whether a checkpoint of the state should be taken before the next step starts.
+    /// Only recorders that can go back in time need them.
+    fn wants_checkpoint(&self) -> bool {
+        false
+    }
+
+    fn checkpoint(&mut self, _checkpoint: Checkpoint) {}
 }
 
 impl Record for () {
@@ -24,6 +70,28 @@ impl Record for () {
     fn push(&mut self, _new: StackCell) {}
     fn enter_quote(&mut self) {}
     fn exit_quote(&mut self) {}
+
+    fn begin_stack(&mut self) {}
+    fn end_stack(&mut self, _old: &[StackCell]) {}
+    fn pop_under(&mut self, _old: StackCell) {}
+    fn pop_under_bottom(&mut self) {}
+    fn push_under(&mut self, _new: StackCell) {}
+    fn storage_offset(&mut self, _old: Position, _new: Position) {}
+
+    fn move_cursor(&mut self, _old: Position, _new: Position) {}
+    fn turn(&mut self, _old: Direction, _new: Direction) {}
+    fn output(&mut self, _bytes: &[u8]) {}
+    fn read_byte(&mut self, _input: Input<u8>) {}
+    fn read_number(&mut self, _input: Input<i32>) {}
+    fn random(&mut self, _dir: Direction) {}
+    fn exit_code(&mut self, _old: i32, _new: i32) {}
+    fn division_prompted(&mut self, _prompted: bool) {}
+    fn semantics(&mut self, _old: &Semantics, _new: &Semantics) {}
+
+    fn split(&mut self, _child: &InstructionPointer) {}
+    fn next_ip(&mut self) {}
+    fn end_ip(&mut self, _old: &InstructionPointer) {}
+    fn clear_queue(&mut self, _old: &[InstructionPointer]) {}
 }
 
 impl<T1, T2> Record for (T1, T2)
@@ -75,8 +143,301 @@ where
         self.0.exit_quote();
         self.1.exit_quote();
     }
+
+    fn begin_stack(&mut self) {
+        self.0.begin_stack();
+        self.1.begin_stack();
+    }
+
+    fn end_stack(&mut self, old: &[StackCell]) {
+        self.0.end_stack(old);
+        self.1.end_stack(old);
+    }
+
+    fn pop_under(&mut self, old: StackCell) {
+        self.0.pop_under(old);
+        self.1.pop_under(old);
+    }
+
+    fn pop_under_bottom(&mut self) {
+        self.0.pop_under_bottom();
+        self.1.pop_under_bottom();
+    }
+
+    fn push_under(&mut self, new: StackCell) {
+        self.0.push_under(new);
+        self.1.push_under(new);
+    }
+
+    fn storage_offset(&mut self, old: Position, new: Position) {
+        self.0.storage_offset(old, new);
+        self.1.storage_offset(old, new);
+    }
+
+    fn move_cursor(&mut self, old: Position, new: Position) {
+        self.0.move_cursor(old, new);
+        self.1.move_cursor(old, new);
+    }
+
+    fn turn(&mut self, old: Direction, new: Direction) {
+        self.0.turn(old, new);
+        self.1.turn(old, new);
+    }
+
+    fn output(&mut self, bytes: &[u8]) {
+        self.0.output(bytes);
+        self.1.output(bytes);
+    }
+
+    fn read_byte(&mut self, input: Input<u8>) {
+        self.0.read_byte(input);
+        self.1.read_byte(input);
+    }
+
+    fn read_number(&mut self, input: Input<i32>) {
+        self.0.read_number(input);
+        self.1.read_number(input);
+    }
+
+    fn random(&mut self, dir: Direction) {
+        self.0.random(dir);
+        self.1.random(dir);
+    }
+
+    fn exit_code(&mut self, old: i32, new: i32) {
+        self.0.exit_code(old, new);
+        self.1.exit_code(old, new);
+    }
+
+    fn division_prompted(&mut self, prompted: bool) {
+        self.0.division_prompted(prompted);
+        self.1.division_prompted(prompted);
+    }
+
+    fn semantics(&mut self, old: &Semantics, new: &Semantics) {
+        self.0.semantics(old, new);
+        self.1.semantics(old, new);
+    }
+
+    fn split(&mut self, child: &InstructionPointer) {
+        self.0.split(child);
+        self.1.split(child);
+    }
+
+    fn next_ip(&mut self) {
+        self.0.next_ip();
+        self.1.next_ip();
+    }
+
+    fn end_ip(&mut self, old: &InstructionPointer) {
+        self.0.end_ip(old);
+        self.1.end_ip(old);
+    }
+
+    fn clear_queue(&mut self, old: &[InstructionPointer]) {
+        self.0.clear_queue(old);
+        self.1.clear_queue(old);
+    }
+
+    fn wants_checkpoint(&self) -> bool {
+        self.0.wants_checkpoint() || self.1.wants_checkpoint()
+    }
+
+    fn checkpoint(&mut self, checkpoint: Checkpoint) {
+        match (self.0.wants_checkpoint(), self.1.wants_checkpoint()) {
+            (true, true) => {
+                self.0.checkpoint(checkpoint.clone());
+                self.1.checkpoint(checkpoint);
+            }
+            (true, false) => self.0.checkpoint(checkpoint),
+            (false, true) => self.1.checkpoint(checkpoint),
+            (false, false) => {}
+        }
+    }
+}
+
+/// Records nothing when there is no recorder
+impl<R: Record> Record for Option<R> {
+    fn start_step(&mut self, at: Position, instruction: GridCell) {
+        if let Some(recorder) = self {
+            recorder.start_step(at, instruction);
+        }
+    }
+
+    fn rollback_step(&mut self) {
+        if let Some(recorder) = self {
+            recorder.rollback_step();
+        }
+    }
+
+    fn commit_step(&mut self) {
+        if let Some(recorder) = self {
+            recorder.commit_step();
+        }
+    }
+
+    fn replace(&mut self, at: Position, old: GridCell, new: GridCell) {
+        if let Some(recorder) = self {
+            recorder.replace(at, old, new);
+        }
+    }
+
+    fn pop(&mut self, old: StackCell) {
+        if let Some(recorder) = self {
+            recorder.pop(old);
+        }
+    }
+
+    fn pop_bottom(&mut self) {
+        if let Some(recorder) = self {
+            recorder.pop_bottom();
+        }
+    }
+
+    fn push(&mut self, new: StackCell) {
+        if let Some(recorder) = self {
+            recorder.push(new);
+        }
+    }
+
+    fn enter_quote(&mut self) {
+        if let Some(recorder) = self {
+            recorder.enter_quote();
+        }
+    }
+
+    fn exit_quote(&mut self) {
+        if let Some(recorder) = self {
+            recorder.exit_quote();
+        }
+    }
+
+    fn begin_stack(&mut self) {
+        if let Some(recorder) = self {
+            recorder.begin_stack();
+        }
+    }
+
+    fn end_stack(&mut self, old: &[StackCell]) {
+        if let Some(recorder) = self {
+            recorder.end_stack(old);
+        }
+    }
+
+    fn pop_under(&mut self, old: StackCell) {
+        if let Some(recorder) = self {
+            recorder.pop_under(old);
+        }
+    }
+
+    fn pop_under_bottom(&mut self) {
+        if let Some(recorder) = self {
+            recorder.pop_under_bottom();
+        }
+    }
+
+    fn push_under(&mut self, new: StackCell) {
+        if let Some(recorder) = self {
+            recorder.push_under(new);
+        }
+    }
+
+    fn storage_offset(&mut self, old: Position, new: Position) {
+        if let Some(recorder) = self {
+            recorder.storage_offset(old, new);
+        }
+    }
+
+    fn move_cursor(&mut self, old: Position, new: Position) {
+        if let Some(recorder) = self {
+            recorder.move_cursor(old, new);
+        }
+    }
+
+    fn turn(&mut self, old: Direction, new: Direction) {
+        if let Some(recorder) = self {
+            recorder.turn(old, new);
+        }
+    }
+
+    fn output(&mut self, bytes: &[u8]) {
+        if let Some(recorder) = self {
+            recorder.output(bytes);
+        }
+    }
+
+    fn read_byte(&mut self, input: Input<u8>) {
+        if let Some(recorder) = self {
+            recorder.read_byte(input);
+        }
+    }
+
+    fn read_number(&mut self, input: Input<i32>) {
+        if let Some(recorder) = self {
+            recorder.read_number(input);
+        }
+    }
+
+    fn random(&mut self, dir: Direction) {
+        if let Some(recorder) = self {
+            recorder.random(dir);
+        }
+    }
+
+    fn exit_code(&mut self, old: i32, new: i32) {
+        if let Some(recorder) = self {
+            recorder.exit_code(old, new);
+        }
+    }
+
+    fn division_prompted(&mut self, prompted: bool) {
+        if let Some(recorder) = self {
+            recorder.division_prompted(prompted);
+        }
+    }
+
+    fn semantics(&mut self, old: &Semantics, new: &Semantics) {
+        if let Some(recorder) = self {
+            recorder.semantics(old, new);
+        }
+    }
+
+    fn split(&mut self, child: &InstructionPointer) {
+        if let Some(recorder) = self {
+            recorder.split(child);
+        }
+    }
+
+    fn next_ip(&mut self) {
+        if let Some(recorder) = self {
+            recorder.next_ip();
+        }
+    }
+
+    fn end_ip(&mut self, old: &InstructionPointer) {
+        if let Some(recorder) = self {
+            recorder.end_ip(old);
+        }
+    }
+
+    fn clear_queue(&mut self, old: &[InstructionPointer]) {
+        if let Some(recorder) = self {
+            recorder.clear_queue(old);
+        }
+    }
+
+    fn wants_checkpoint(&self) -> bool {
+        self.as_ref().is_some_and(R::wants_checkpoint)
+    }
+
+    fn checkpoint(&mut self, checkpoint: Checkpoint) {
+        if let Some(recorder) = self {
+            recorder.checkpoint(checkpoint);
+        }
+    }
 }
 
+/// Prints every event to stdout, which is handy when debugging the interpreter itself
 pub struct StdOutEventLog;
 
 impl Record for StdOutEventLog {
@@ -115,8 +476,94 @@ impl Record for StdOutEventLog {
     fn exit_quote(&mut self) {
         println!("Exit quote mode")
     }
+
+    fn begin_stack(&mut self) {
+        println!("Began a new stack");
+    }
+
+    fn end_stack(&mut self, old: &[StackCell]) {
+        println!("Ended a stack containing {} values", old.len());
+    }
+
+    fn pop_under(&mut self, old: StackCell) {
+        println!("Popped '{}' from the second stack", old.0);
+    }
+
+    fn pop_under_bottom(&mut self) {
+        println!("Popped while at bottom of the second stack")
+    }
+
+    fn push_under(&mut self, new: StackCell) {
+        println!("Pushed '{}' onto the second stack", new.0);
+    }
+
+    fn storage_offset(&mut self, old: Position, new: Position) {
+        println!("Moved storage offset from {} to {}", old, new);
+    }
+
+    fn move_cursor(&mut self, old: Position, new: Position) {
+        println!("Moved from {} to {}", old, new);
+    }
+
+    fn turn(&mut self, old: Direction, new: Direction) {
+        println!("Turned from {} to {}", old, new);
+    }
+
+    fn output(&mut self, bytes: &[u8]) {
+        println!("Wrote {:?}", String::from_utf8_lossy(bytes));
+    }
+
+    fn read_byte(&mut self, input: Input<u8>) {
+        println!("Read byte {:?}", input);
+    }
+
+    fn read_number(&mut self, input: Input<i32>) {
+        println!("Read number {:?}", input);
+    }
+
+    fn random(&mut self, dir: Direction) {
+        println!("Randomly chose {}", dir);
+    }
+
+    fn exit_code(&mut self, old: i32, new: i32) {
+        println!("Changed exit code from {} to {}", old, new);
+    }
+
+    fn division_prompted(&mut self, prompted: bool) {
+        if prompted {
+            println!("Prompted for the result of a division by zero");
+        } else {
+            println!("Answered the prompt for a division by zero");
+        }
+    }
+
+    fn semantics(&mut self, old: &Semantics, new: &Semantics) {
+        let count = |semantics: &Semantics| semantics.ids().map(|ids| ids.len()).sum::<usize>();
+        println!(
+            "Changed loaded fingerprint semantics from {} to {}",
+            count(old),
+            count(new)
+        );
+    }
+
+    fn split(&mut self, child: &InstructionPointer) {
+        println!("Split off IP {} at {}", child.id, child.cursor.pos);
+    }
+
+    fn next_ip(&mut self) {
+        println!("Switched to the next IP");
+    }
+
+    fn end_ip(&mut self, old: &InstructionPointer) {
+        println!("Ended IP {}", old.id);
+    }
+
+    fn clear_queue(&mut self, old: &[InstructionPointer]) {
+        println!("Ended {} other IPs", old.len());
+    }
 }
 
+/// Logs every event at the info level
 pub struct EventLog;
 
 impl Record for EventLog {
@@ -155,19 +602,158 @@ impl Record for EventLog {
     fn exit_quote(&mut self) {
         log::info!("Exit quote mode")
     }
+
+    fn begin_stack(&mut self) {
+        log::info!("Began a new stack");
+    }
+
+    fn end_stack(&mut self, old: &[StackCell]) {
+        log::info!("Ended a stack containing {} values", old.len());
+    }
+
+    fn pop_under(&mut self, old: StackCell) {
+        log::info!("Popped '{}' from the second stack", old.0);
+    }
+
+    fn pop_under_bottom(&mut self) {
+        log::info!("Popped while at bottom of the second stack")
+    }
+
+    fn push_under(&mut self, new: StackCell) {
+        log::info!("Pushed '{}' onto the second stack", new.0);
+    }
+
+    fn storage_offset(&mut self, old: Position, new: Position) {
+        log::info!("Moved storage offset from {} to {}", old, new);
+    }
+
+    fn move_cursor(&mut self, old: Position, new: Position) {
+        log::info!("Moved from {} to {}", old, new);
+    }
+
+    fn turn(&mut self, old: Direction, new: Direction) {
+        log::info!("Turned from {} to {}", old, new);
+    }
+
+    fn output(&mut self, bytes: &[u8]) {
+        log::info!("Wrote {:?}", String::from_utf8_lossy(bytes));
+    }
+
+    fn read_byte(&mut self, input: Input<u8>) {
+        log::info!("Read byte {:?}", input);
+    }
+
+    fn read_number(&mut self, input: Input<i32>) {
+        log::info!("Read number {:?}", input);
+    }
+
+    fn random(&mut self, dir: Direction) {
+        log::info!("Randomly chose {}", dir);
+    }
+
+    fn exit_code(&mut self, old: i32, new: i32) {
+        log::info!("Changed exit code from {} to {}", old, new);
+    }
+
+    fn division_prompted(&mut self, prompted: bool) {
+        if prompted {
+            log::info!("Prompted for the result of a division by zero");
+        } else {
+            log::info!("Answered the prompt for a division by zero");
+        }
+    }
+
+    fn semantics(&mut self, old: &Semantics, new: &Semantics) {
+        let count = |semantics: &Semantics| semantics.ids().map(|ids| ids.len()).sum::<usize>();
+        log::info!(
+            "Changed loaded fingerprint semantics from {} to {}",
+            count(old),
+            count(new)
+        );
+    }
+
+    fn split(&mut self, child: &InstructionPointer) {
+        log::info!("Split off IP {} at {}", child.id, child.cursor.pos);
+    }
+
+    fn next_ip(&mut self) {
+        log::info!("Switched to the next IP");
+    }
+
+    fn end_ip(&mut self, old: &InstructionPointer) {
+        log::info!("Ended IP {}", old.id);
+    }
+
+    fn clear_queue(&mut self, old: &[InstructionPointer]) {
+        log::info!("Ended {} other IPs", old.len());
+    }
 }
 
-#[derive(Default)]
+/// Records every step with the events that happened during it,
+/// so that a run can be replayed or inspected afterwards.
+///
+/// A checkpoint of the state is taken every so often, so any step can be gone back to
+/// by restoring the checkpoint before it and replaying the steps after that.
+/// Once more steps are recorded than the timeline's capacity,
+/// the steps before the second oldest checkpoint are dropped.
 pub struct Timeline {
     steps: Vec<Step>,
     events: Vec<Event>,
+    /// The number of steps the interpreter has gone through since the oldest step kept,
+    /// which is less than the number recorded after stepping back
+    position: usize,
+    /// The number of events in the steps the interpreter has gone through
+    event_position: usize,
+
+    /// The number of events recorded since the current step started
+    pending_events: usize,
 
-    pending_events: u8,
+    /// Checkpoints from oldest to newest, the oldest being taken before the oldest step kept
+    checkpoints: VecDeque<Taken>,
+    checkpoint_interval: usize,
+    capacity: usize,
+    /// The number of steps that were dropped to stay within the capacity
+    dropped_steps: usize,
+    /// The number of events in the steps that were dropped
+    dropped_events: usize,
+}
+
+/// The state of the interpreter before a step, apart from its IO and random number generator.
+/// Those aren't needed since they can be moved to any step by going through its output
+/// and random events.
+#[derive(Clone, Debug)]
+pub struct Checkpoint {
+    pub(crate) space: Space<GridCell>,
+    /// Every IP, with the one that executes next first
+    pub(crate) ips: Vec<InstructionPointer>,
+    pub(crate) next_ip_id: u32,
+    pub(crate) exit_code: i32,
+    pub(crate) division_prompted: bool,
+    pub(crate) steps: u64,
+}
+
+/// A checkpoint and where in the timeline it was taken, counting dropped steps and events
+struct Taken {
+    step: usize,
+    event: usize,
+    checkpoint: Checkpoint,
+}
+
+/// How to move an interpreter to a step that is far away in the timeline
+pub(crate) struct Seek<'a> {
+    pub checkpoint: &'a Checkpoint,
+    /// The events from the checkpoint to the step, to be applied after restoring it
+    pub replayed: &'a [Event],
+    /// The events between the step the interpreter was at and the new step
+    pub skipped: &'a [Event],
+    pub backwards: bool,
+    /// The number of steps from the checkpoint to the step that didn't wait for input
+    pub steps: u64,
 }
 
 /// Events contain enough information to apply them to the state either forwards or backwards.
-#[allow(dead_code)]
-enum Event {
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Event {
     Replace {
         at: Position,
         old: GridCell,
@@ -182,55 +768,407 @@ enum Event {
     },
     EnterQuote,
     ExitQuote,
+    BeginStack,
+    EndStack {
+        old: Vec<StackCell>,
+    },
+    PopUnder {
+        old: StackCell,
+    },
+    PopUnderBottom,
+    PushUnder {
+        new: StackCell,
+    },
+    StorageOffset {
+        old: Position,
+        new: Position,
+    },
+    /// The cursor moved, including every jump and skipped cell of the step
+    Move {
+        old: Position,
+        new: Position,
+    },
+    Turn {
+        old: Direction,
+        new: Direction,
+    },
+    Output {
+        bytes: Vec<u8>,
+    },
+    /// What an input instruction got, which is already reflected in the events after it
+    ReadByte {
+        input: Input<u8>,
+    },
+    ReadNumber {
+        input: Input<i32>,
+    },
+    /// The direction "?" chose, which advanced the random number generator
+    Random {
+        dir: Direction,
+    },
+    ExitCode {
+        old: i32,
+        new: i32,
+    },
+    DivisionPrompted {
+        prompted: bool,
+    },
+    Semantics {
+        old: Box<Semantics>,
+        new: Box<Semantics>,
+    },
+    /// A new IP was added to the end of the queue by "t"
+    Split {
+        child: Box<InstructionPointer>,
+    },
+    /// The current IP went to the back of the queue and the next one took over
+    NextIp,
+    /// The current IP terminated and the next one took over
+    EndIp {
+        old: Box<InstructionPointer>,
+    },
+    /// Every IP but the current one terminated
+    ClearQueue {
+        old: Vec<InstructionPointer>,
+    },
 }
 
-#[allow(dead_code)]
-struct Step {
-    at: Position,
-    instruction: GridCell,
-    events: u8,
+/// An instruction the interpreter executed and the number of events it caused
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Step {
+    pub at: Position,
+    pub instruction: GridCell,
+    pub events: usize,
+    /// Whether the step waited for input, so its events are only what happened before it did
+    pub waited: bool,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self {
+            steps: Vec::new(),
+            events: Vec::new(),
+            position: 0,
+            event_position: 0,
+            pending_events: 0,
+            checkpoints: VecDeque::new(),
+            checkpoint_interval: CHECKPOINT_INTERVAL,
+            capacity: CAPACITY,
+            dropped_steps: 0,
+            dropped_events: 0,
+        }
+    }
+}
+
+impl Timeline {
+    /// Sets how many steps apart checkpoints are taken,
+    /// which is the most steps that are replayed to go to any step
+    pub fn with_checkpoint_interval(mut self, steps: usize) -> Self {
+        self.checkpoint_interval = steps.max(1);
+        self
+    }
+
+    /// Sets the most steps that are kept before the oldest are dropped,
+    /// which happens a checkpoint interval at a time
+    pub fn with_capacity(mut self, steps: usize) -> Self {
+        self.capacity = steps;
+        self
+    }
+
+    /// How many steps apart checkpoints are taken
+    pub fn checkpoint_interval(&self) -> usize {
+        self.checkpoint_interval
+    }
+
+    /// The steps that are kept, in the order they were executed,
+    /// including any that have been stepped back over
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// The number of the oldest step kept, which is how far back the timeline reaches
+    pub fn start(&self) -> usize {
+        self.dropped_steps
+    }
+
+    /// The number of steps the interpreter has gone through, including dropped ones
+    pub fn position(&self) -> usize {
+        self.dropped_steps + self.position
+    }
+
+    /// The number of steps recorded, including dropped ones and any that have been stepped back over
+    pub fn end(&self) -> usize {
+        self.dropped_steps + self.steps.len()
+    }
+
+    /// Whether there are steps that have been stepped back over and can be redone
+    pub fn can_step_forward(&self) -> bool {
+        self.position < self.steps.len()
+    }
+
+    /// Moves back over the last step the interpreter went through, returning it and its events
+    pub(crate) fn back(&mut self) -> Option<(Step, &[Event])> {
+        let step = *self.steps[..self.position].last()?;
+        let end = self.event_position;
+        self.position -= 1;
+        self.event_position -= step.events;
+        Some((step, &self.events[self.event_position..end]))
+    }
+
+    /// Moves forward over the next step that was stepped back over, returning it and its events
+    pub(crate) fn forward(&mut self) -> Option<(Step, &[Event])> {
+        let step = *self.steps.get(self.position)?;
+        let start = self.event_position;
+        self.position += 1;
+        self.event_position += step.events;
+        Some((step, &self.events[start..self.event_position]))
+    }
+
+    /// The events of every recorded step, in the order they happened
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Moves to a step between the start and the end, returning how to move an interpreter there
+    /// from the nearest checkpoint before it
+    pub(crate) fn seek(&mut self, step: usize) -> Option<Seek<'_>> {
+        if !(self.start()..=self.end()).contains(&step) {
+            return None;
+        }
+        let index = self
+            .checkpoints
+            .partition_point(|taken| taken.step <= step)
+            .checked_sub(1)?;
+        let taken = &self.checkpoints[index];
+        let from = taken.step - self.dropped_steps;
+        let to = step - self.dropped_steps;
+        let start = taken.event - self.dropped_events;
+        let end = start + self.steps[from..to].iter().map(|s| s.events).sum::<usize>();
+        let steps = self.steps[from..to].iter().filter(|s| !s.waited).count() as u64;
+
+        let backwards = to < self.position;
+        let skipped = if backwards {
+            end..self.event_position
+        } else {
+            self.event_position..end
+        };
+        self.position = to;
+        self.event_position = end;
+        Some(Seek {
+            checkpoint: &taken.checkpoint,
+            replayed: &self.events[start..end],
+            skipped: &self.events[skipped],
+            backwards,
+            steps,
+        })
+    }
+
+    /// Applies every event that is kept to an interpreter,
+    /// which must be in the state the oldest step kept started from
+    pub fn replay<IOImpl: IO, R: Record, G: Random>(
+        &self,
+        interpreter: &mut Interpreter<IOImpl, R, G>,
+    ) {
+        for event in &self.events {
+            interpreter.apply(event);
+        }
+    }
+
+    pub(crate) fn record(&mut self, event: Event) {
+        self.events.push(event);
+        self.pending_events += 1;
+    }
+
+    /// Drops the steps before the second oldest checkpoint while more steps are kept than the capacity
+    fn compact(&mut self) {
+        while self.steps.len() > self.capacity && self.checkpoints.len() > 1 {
+            let oldest = &self.checkpoints[1];
+            let steps = oldest.step - self.dropped_steps;
+            let events = oldest.event - self.dropped_events;
+            self.steps.drain(..steps);
+            self.events.drain(..events);
+            self.position -= steps;
+            self.event_position -= events;
+            self.dropped_steps = oldest.step;
+            self.dropped_events = oldest.event;
+            self.checkpoints.pop_front();
+        }
+    }
 }
 
 impl Record for Timeline {
+    /// Starts a step, forgetting the steps that were stepped back over
+    /// since executing a step makes a different future
     fn start_step(&mut self, at: Position, instruction: GridCell) {
+        self.steps.truncate(self.position);
+        self.events.truncate(self.event_position);
+        let position = self.position();
+        while self
+            .checkpoints
+            .back()
+            .is_some_and(|taken| taken.step > position)
+        {
+            self.checkpoints.pop_back();
+        }
         self.steps.push(Step {
             at,
             instruction,
             events: 0,
+            waited: false,
         });
     }
 
+    /// Forgets a step that waited for input, unless it changed something before it did,
+    /// like writing a prompt, in which case it is kept so those changes aren't lost
     fn rollback_step(&mut self) {
-        self.steps.pop();
-        self.pending_events = 0;
+        if self.pending_events == 0 {
+            self.steps.pop();
+        } else {
+            self.steps.last_mut().unwrap().waited = true;
+            self.commit_step();
+        }
     }
 
     fn commit_step(&mut self) {
         self.steps.last_mut().unwrap().events = self.pending_events;
         self.pending_events = 0;
+        self.position = self.steps.len();
+        self.event_position = self.events.len();
+        self.compact();
     }
 
     fn replace(&mut self, at: Position, old: GridCell, new: GridCell) {
-        self.events.push(Event::Replace { at, old, new });
+        self.record(Event::Replace { at, old, new });
     }
 
     fn pop(&mut self, old: StackCell) {
-        self.events.push(Event::Pop { old });
+        self.record(Event::Pop { old });
     }
 
     fn pop_bottom(&mut self) {
-        self.events.push(Event::PopBottom);
+        self.record(Event::PopBottom);
     }
 
     fn push(&mut self, new: StackCell) {
-        self.events.push(Event::Push { new });
+        self.record(Event::Push { new });
     }
 
     fn enter_quote(&mut self) {
-        self.events.push(Event::EnterQuote);
+        self.record(Event::EnterQuote);
     }
 
     fn exit_quote(&mut self) {
-        self.events.push(Event::ExitQuote);
+        self.record(Event::ExitQuote);
+    }
+
+    fn begin_stack(&mut self) {
+        self.record(Event::BeginStack);
+    }
+
+    fn end_stack(&mut self, old: &[StackCell]) {
+        self.record(Event::EndStack { old: old.to_vec() });
+    }
+
+    fn pop_under(&mut self, old: StackCell) {
+        self.record(Event::PopUnder { old });
+    }
+
+    fn pop_under_bottom(&mut self) {
+        self.record(Event::PopUnderBottom);
+    }
+
+    fn push_under(&mut self, new: StackCell) {
+        self.record(Event::PushUnder { new });
+    }
+
+    fn storage_offset(&mut self, old: Position, new: Position) {
+        self.record(Event::StorageOffset { old, new });
+    }
+
+    fn move_cursor(&mut self, old: Position, new: Position) {
+        // Moves that follow each other within a step are merged into one
+        if self.pending_events > 0
+            && let Some(Event::Move { new: last, .. }) = self.events.last_mut()
+        {
+            *last = new;
+        } else {
+            self.record(Event::Move { old, new });
+        }
+    }
+
+    fn turn(&mut self, old: Direction, new: Direction) {
+        self.record(Event::Turn { old, new });
+    }
+
+    fn output(&mut self, bytes: &[u8]) {
+        self.record(Event::Output {
+            bytes: bytes.to_vec(),
+        });
+    }
+
+    fn read_byte(&mut self, input: Input<u8>) {
+        self.record(Event::ReadByte { input });
+    }
+
+    fn read_number(&mut self, input: Input<i32>) {
+        self.record(Event::ReadNumber { input });
+    }
+
+    fn random(&mut self, dir: Direction) {
+        self.record(Event::Random { dir });
+    }
+
+    fn exit_code(&mut self, old: i32, new: i32) {
+        self.record(Event::ExitCode { old, new });
+    }
+
+    fn division_prompted(&mut self, prompted: bool) {
+        self.record(Event::DivisionPrompted { prompted });
+    }
+
+    fn semantics(&mut self, old: &Semantics, new: &Semantics) {
+        self.record(Event::Semantics {
+            old: Box::new(old.clone()),
+            new: Box::new(new.clone()),
+        });
+    }
+
+    fn split(&mut self, child: &InstructionPointer) {
+        self.record(Event::Split {
+            child: Box::new(child.clone()),
+        });
+    }
+
+    fn next_ip(&mut self) {
+        self.record(Event::NextIp);
+    }
+
+    fn end_ip(&mut self, old: &InstructionPointer) {
+        self.record(Event::EndIp {
+            old: Box::new(old.clone()),
+        });
+    }
+
+    fn clear_queue(&mut self, old: &[InstructionPointer]) {
+        self.record(Event::ClearQueue { old: old.to_vec() });
+    }
+
+    /// Wants a checkpoint every checkpoint interval, unless there already is one
+    /// from before the interpreter stepped back
+    fn wants_checkpoint(&self) -> bool {
+        let position = self.position();
+        position.is_multiple_of(self.checkpoint_interval)
+            && self
+                .checkpoints
+                .back()
+                .is_none_or(|taken| taken.step < position)
+    }
+
+    fn checkpoint(&mut self, checkpoint: Checkpoint) {
+        self.checkpoints.push_back(Taken {
+            step: self.position(),
+            event: self.dropped_events + self.event_position,
+            checkpoint,
+        });
     }
 }
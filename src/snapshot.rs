@@ -0,0 +1,563 @@
+use std::{fs, path::Path};
+
+use grid::Grid;
+use thiserror::Error;
+
+use crate::{
+    core::{
+        Cursor, Dialect, Direction, DivisionByZero, EndOfInput, GridCell, Mode, Position,
+        StackCell, Underflow,
+    },
+    fingerprint::{Fingerprints, Semantics},
+    interpreter::{InstructionPointer, Settings},
+    io::PendingInput,
+    random::SeededRandom,
+    space::Space,
+};
+
+/// The bytes every snapshot file starts with
+const MAGIC: &[u8; 4] = b"BFTS";
+/// The version of the snapshot format written by this build.
+/// It must change whenever the layout below changes.
+pub const VERSION: u32 = 1;
+
+/// The complete state of an interpreter at the moment it was captured,
+/// which is enough to resume it exactly where it left off.
+///
+/// Snapshots are saved in a binary format made of little-endian integers.
+/// Lists are a `u32` length followed by their items.
+/// Directions are a `u8`: 0 up, 1 down, 2 left, 3 right,
+/// or 4 followed by dx `i32` and dy `i32` for any other delta.
+///
+/// ```text
+/// magic                "BFTS"
+/// version              u32
+/// settings             dialect u8, strict_93 u8, division_by_zero u8, end_of_input u8,
+///                      detect_loops u8, underflow u8
+///                      (division_by_zero and end_of_input are 0 for the dialect's default
+///                      or the variant plus one)
+/// random               seed u64, state u64
+/// next_ip_id           u32
+/// exit_code            i32
+/// division_prompted    u8
+/// steps                u64
+/// input                closed u8, list of u8
+/// space                least x i32, least y i32, greatest x i32, greatest y i32,
+///                      rows u32, cols u32, rows * cols cells i32 row by row,
+///                      list of sparse cells (x i32, y i32, value i32)
+/// ips                  list of IPs in the order they execute, each one is
+///                      id u32, x i32, y i32, direction, mode u8,
+///                      storage offset x i32, storage offset y i32,
+///                      list of stacks from the bottom one to the TOSS, each a list of i32,
+///                      26 lists of the fingerprint IDs (i32) loaded for "A" to "Z"
+/// ```
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Snapshot {
+    pub settings: Settings,
+    pub random: SeededRandom,
+    pub next_ip_id: u32,
+    pub exit_code: i32,
+    pub division_prompted: bool,
+    /// The number of steps executed before the snapshot was taken
+    pub steps: u64,
+    pub input: PendingInput,
+    pub space: Space<GridCell>,
+    /// Every IP, with the one that executes next first
+    pub ips: Vec<InstructionPointer>,
+}
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("Could not access snapshot file")]
+    IO(#[from] std::io::Error),
+    #[error("Not a snapshot file")]
+    NotASnapshot,
+    #[error("Snapshot format version {0} is not supported (expected {VERSION})")]
+    UnsupportedVersion(u32),
+    #[error("Snapshot file ended unexpectedly")]
+    Truncated,
+    #[error("Snapshot contains an invalid {0}")]
+    Invalid(&'static str),
+    #[error("Snapshot uses fingerprint {0:#x} which is not available")]
+    UnknownFingerprint(i32),
+}
+
+impl Snapshot {
+    /// Writes the snapshot to a file
+    pub fn save(&self, path: &Path) -> Result<(), SnapshotError> {
+        fs::write(path, self.encode())?;
+        Ok(())
+    }
+
+    /// Reads a snapshot from a file, resolving fingerprints with the standard ones
+    pub fn load(path: &Path) -> Result<Self, SnapshotError> {
+        let bytes = fs::read(path)?;
+        Self::decode(&bytes, &Fingerprints::default())
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.bytes(MAGIC);
+        w.u32(VERSION);
+
+        let settings = self.settings;
+        w.u8(settings.dialect as u8);
+        w.bool(settings.strict_93);
+        w.u8(settings.division_by_zero.map_or(0, |d| d as u8 + 1));
+        w.u8(settings.end_of_input.map_or(0, |e| e as u8 + 1));
+        w.bool(settings.detect_loops);
+        w.u8(settings.underflow as u8);
+
+        w.u64(self.random.seed());
+        w.u64(self.random.state());
+        w.u32(self.next_ip_id);
+        w.i32(self.exit_code);
+        w.bool(self.division_prompted);
+        w.u64(self.steps);
+
+        w.bool(self.input.closed);
+        w.len(self.input.bytes.len());
+        w.bytes(&self.input.bytes);
+
+        let space = &self.space;
+        w.position(space.least());
+        w.position(space.greatest());
+        let grid = space.grid();
+        w.len(grid.rows());
+        w.len(grid.cols());
+        for cell in grid.iter() {
+            w.i32(cell.0);
+        }
+        let sparse: Vec<_> = space.sparse_cells().collect();
+        w.len(sparse.len());
+        for (pos, cell) in sparse {
+            w.position(pos);
+            w.i32(cell.0);
+        }
+
+        w.len(self.ips.len());
+        for ip in self.ips.iter() {
+            w.ip(ip);
+        }
+        w.0
+    }
+
+    /// Reads a snapshot, looking up the fingerprints its IPs have loaded in a registry
+    pub fn decode(bytes: &[u8], fingerprints: &Fingerprints) -> Result<Self, SnapshotError> {
+        let mut r = Reader(bytes);
+        if r.take(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err(SnapshotError::NotASnapshot);
+        }
+        let version = r.u32()?;
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let dialect = match r.u8()? {
+            0 => Dialect::Befunge93,
+            1 => Dialect::Funge98,
+            _ => return Err(SnapshotError::Invalid("dialect")),
+        };
+        let strict_93 = r.bool()?;
+        let division_by_zero = match r.u8()? {
+            0 => None,
+            1 => Some(DivisionByZero::Zero),
+            2 => Some(DivisionByZero::Prompt),
+            3 => Some(DivisionByZero::Error),
+            _ => return Err(SnapshotError::Invalid("division by zero setting")),
+        };
+        let end_of_input = match r.u8()? {
+            0 => None,
+            1 => Some(EndOfInput::Wait),
+            2 => Some(EndOfInput::PushNegative),
+            3 => Some(EndOfInput::Reflect),
+            4 => Some(EndOfInput::Error),
+            _ => return Err(SnapshotError::Invalid("end of input setting")),
+        };
+        let detect_loops = r.bool()?;
+        let underflow = match r.u8()? {
+            0 => Underflow::Allow,
+            1 => Underflow::Warn,
+            2 => Underflow::Deny,
+            _ => return Err(SnapshotError::Invalid("underflow setting")),
+        };
+        let settings = Settings {
+            dialect,
+            strict_93,
+            division_by_zero,
+            end_of_input,
+            detect_loops,
+            underflow,
+        };
+
+        let seed = r.u64()?;
+        let state = r.u64()?;
+        let random = SeededRandom::resume(seed, state);
+        let next_ip_id = r.u32()?;
+        let exit_code = r.i32()?;
+        let division_prompted = r.bool()?;
+        let steps = r.u64()?;
+
+        let closed = r.bool()?;
+        let len = r.len()?;
+        let input = PendingInput {
+            bytes: r.take(len)?.to_vec(),
+            closed,
+        };
+
+        let least = r.position()?;
+        let greatest = r.position()?;
+        if least.x > greatest.x || least.y > greatest.y {
+            return Err(SnapshotError::Invalid("space bounds"));
+        }
+        let in_bounds = |x: i64, y: i64| {
+            (least.x as i64..=greatest.x as i64).contains(&x)
+                && (least.y as i64..=greatest.y as i64).contains(&y)
+        };
+        let rows = r.len()?;
+        let cols = r.len()?;
+        let grid_fits = in_bounds(0, 0) && in_bounds(cols as i64 - 1, rows as i64 - 1);
+        if rows > 0 && cols > 0 && !grid_fits {
+            return Err(SnapshotError::Invalid("grid size"));
+        }
+        let cell_count = rows
+            .checked_mul(cols)
+            .ok_or(SnapshotError::Invalid("grid size"))?;
+        let mut cells = Vec::new();
+        for _ in 0..cell_count {
+            cells.push(GridCell(r.i32()?));
+        }
+        // Empty grids keep their shape, which `from_vec` would lose
+        let grid = if cell_count == 0 {
+            Grid::new(rows, cols)
+        } else {
+            Grid::from_vec(cells, cols)
+        };
+        let mut sparse = Vec::new();
+        for _ in 0..r.len()? {
+            let pos = r.position()?;
+            if !in_bounds(pos.x as i64, pos.y as i64) {
+                return Err(SnapshotError::Invalid("sparse cell position"));
+            }
+            sparse.push((pos, GridCell(r.i32()?)));
+        }
+        let space = Space::from_parts(grid, sparse, least, greatest);
+
+        let mut ips = Vec::new();
+        for _ in 0..r.len()? {
+            ips.push(r.ip(fingerprints)?);
+        }
+        if ips.is_empty() {
+            return Err(SnapshotError::Invalid("list of IPs"));
+        }
+        if !r.0.is_empty() {
+            return Err(SnapshotError::Invalid("trailing data"));
+        }
+
+        Ok(Self {
+            settings,
+            random,
+            next_ip_id,
+            exit_code,
+            division_prompted,
+            steps,
+            input,
+            space,
+            ips,
+        })
+    }
+}
+
+/// Encodes the values snapshots and traces are made of
+#[derive(Default)]
+pub(crate) struct Writer(pub(crate) Vec<u8>);
+
+impl Writer {
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    pub(crate) fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub(crate) fn i32(&mut self, value: i32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub(crate) fn len(&mut self, len: usize) {
+        self.u32(len as u32);
+    }
+
+    pub(crate) fn position(&mut self, pos: Position) {
+        self.i32(pos.x);
+        self.i32(pos.y);
+    }
+
+    pub(crate) fn direction(&mut self, dir: Direction) {
+        match dir {
+            Direction::Up => self.u8(0),
+            Direction::Down => self.u8(1),
+            Direction::Left => self.u8(2),
+            Direction::Right => self.u8(3),
+            Direction::Delta(dx, dy) => {
+                self.u8(4);
+                self.i32(dx);
+                self.i32(dy);
+            }
+        }
+    }
+
+    pub(crate) fn cells(&mut self, cells: &[StackCell]) {
+        self.len(cells.len());
+        for cell in cells {
+            self.i32(cell.0);
+        }
+    }
+
+    pub(crate) fn semantics(&mut self, semantics: &Semantics) {
+        for ids in semantics.ids() {
+            self.len(ids.len());
+            for id in ids {
+                self.i32(id);
+            }
+        }
+    }
+
+    pub(crate) fn ip(&mut self, ip: &InstructionPointer) {
+        self.u32(ip.id);
+        self.position(ip.cursor.pos);
+        self.direction(ip.cursor.dir);
+        self.u8(ip.cursor.mode as u8);
+        self.position(ip.storage_offset);
+        self.len(ip.stacks_below.len() + 1);
+        for stack in ip.stacks_below.iter().chain([&ip.stack]) {
+            self.cells(stack);
+        }
+        self.semantics(&ip.semantics);
+    }
+}
+
+/// Decodes the values snapshots and traces are made of
+pub(crate) struct Reader<'a>(pub(crate) &'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub(crate) fn take(&mut self, n: usize) -> Result<&'a [u8], SnapshotError> {
+        if n > self.0.len() {
+            return Err(SnapshotError::Truncated);
+        }
+        let (taken, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SnapshotError> {
+        let bytes = self.take(N)?;
+        Ok(bytes.try_into().expect("took N bytes"))
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool, SnapshotError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SnapshotError::Invalid("flag")),
+        }
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, SnapshotError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn i32(&mut self) -> Result<i32, SnapshotError> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn len(&mut self) -> Result<usize, SnapshotError> {
+        Ok(self.u32()? as usize)
+    }
+
+    pub(crate) fn position(&mut self) -> Result<Position, SnapshotError> {
+        let x = self.i32()?;
+        let y = self.i32()?;
+        Ok(Position { x, y })
+    }
+
+    pub(crate) fn direction(&mut self) -> Result<Direction, SnapshotError> {
+        match self.u8()? {
+            0 => Ok(Direction::Up),
+            1 => Ok(Direction::Down),
+            2 => Ok(Direction::Left),
+            3 => Ok(Direction::Right),
+            4 => Ok(Direction::from_delta(self.i32()?, self.i32()?)),
+            _ => Err(SnapshotError::Invalid("direction")),
+        }
+    }
+
+    pub(crate) fn cells(&mut self) -> Result<Vec<StackCell>, SnapshotError> {
+        let mut cells = Vec::new();
+        for _ in 0..self.len()? {
+            cells.push(StackCell(self.i32()?));
+        }
+        Ok(cells)
+    }
+
+    pub(crate) fn semantics(
+        &mut self,
+        fingerprints: &Fingerprints,
+    ) -> Result<Semantics, SnapshotError> {
+        let mut ids = Vec::new();
+        for _ in 0..26 {
+            let mut stack = Vec::new();
+            for _ in 0..self.len()? {
+                stack.push(self.i32()?);
+            }
+            ids.push(stack);
+        }
+        Semantics::from_ids(ids, fingerprints).map_err(SnapshotError::UnknownFingerprint)
+    }
+
+    pub(crate) fn ip(
+        &mut self,
+        fingerprints: &Fingerprints,
+    ) -> Result<InstructionPointer, SnapshotError> {
+        let id = self.u32()?;
+        let pos = self.position()?;
+        let dir = self.direction()?;
+        let mode = match self.u8()? {
+            0 => Mode::Quote,
+            1 => Mode::Normal,
+            _ => return Err(SnapshotError::Invalid("mode")),
+        };
+        let storage_offset = self.position()?;
+        let mut stacks = Vec::new();
+        for _ in 0..self.len()? {
+            stacks.push(self.cells()?);
+        }
+        let stack = stacks.pop().ok_or(SnapshotError::Invalid("stack stack"))?;
+        let semantics = self.semantics(fingerprints)?;
+        Ok(InstructionPointer {
+            id,
+            cursor: Cursor { pos, dir, mode },
+            stack,
+            stacks_below: stacks,
+            storage_offset,
+            semantics,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        let mut space = Space::new(b"12\n3");
+        space.set_cell(Position { x: -4, y: 9 }, GridCell(-77));
+        let mut ip = InstructionPointer::new(3);
+        ip.stack = vec![StackCell(5), StackCell(-6)];
+        ip.stacks_below = vec![vec![], vec![StackCell(i32::MIN)]];
+        ip.storage_offset = Position { x: 2, y: -1 };
+        let fingerprints = Fingerprints::default();
+        ip.semantics.load(
+            fingerprints
+                .get(crate::fingerprint::fingerprint_id(b"ROMA"))
+                .unwrap(),
+        );
+        let mut flying = InstructionPointer::new(0);
+        flying.cursor.dir = Direction::Delta(2, -1);
+        Snapshot {
+            settings: Settings {
+                dialect: Dialect::Funge98,
+                strict_93: false,
+                division_by_zero: Some(DivisionByZero::Error),
+                end_of_input: None,
+                detect_loops: true,
+                underflow: Underflow::Warn,
+            },
+            random: SeededRandom::resume(1, 2),
+            next_ip_id: 4,
+            exit_code: 9,
+            division_prompted: true,
+            steps: 1234,
+            input: PendingInput {
+                bytes: b"abc".to_vec(),
+                closed: true,
+            },
+            space,
+            ips: vec![ip, flying],
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let snapshot = snapshot();
+        let decoded = Snapshot::decode(&snapshot.encode(), &Fingerprints::default()).unwrap();
+        assert_eq!(snapshot, decoded);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let fingerprints = Fingerprints::default();
+        let bytes = snapshot().encode();
+        assert!(matches!(
+            Snapshot::decode(b"BFTX", &fingerprints),
+            Err(SnapshotError::NotASnapshot)
+        ));
+        let mut future = bytes.clone();
+        future[4] = 2;
+        assert!(matches!(
+            Snapshot::decode(&future, &fingerprints),
+            Err(SnapshotError::UnsupportedVersion(2))
+        ));
+        assert!(matches!(
+            Snapshot::decode(&bytes[..bytes.len() - 1], &fingerprints),
+            Err(SnapshotError::Truncated)
+        ));
+        assert!(matches!(
+            Snapshot::decode(&bytes, &Fingerprints::empty()),
+            Err(SnapshotError::UnknownFingerprint(_))
+        ));
+
+        let bounds = |least, greatest, sparse: Vec<(Position, GridCell)>| {
+            let mut snapshot = snapshot();
+            let grid = snapshot.space.grid().clone();
+            snapshot.space = Space::from_parts(grid, sparse, least, greatest);
+            Snapshot::decode(&snapshot.encode(), &fingerprints)
+        };
+        let origin = Position::ORIGIN;
+        let corner = Position { x: 1, y: 1 };
+        let inverted = bounds(corner, Position { x: 1, y: -1 }, vec![]);
+        assert!(matches!(
+            inverted,
+            Err(SnapshotError::Invalid("space bounds"))
+        ));
+        let small = bounds(origin, Position { x: 0, y: 1 }, vec![]);
+        assert!(matches!(small, Err(SnapshotError::Invalid("grid size"))));
+        let outside = vec![(Position { x: 5, y: 0 }, GridCell(1))];
+        let outside = bounds(origin, corner, outside);
+        assert!(matches!(
+            outside,
+            Err(SnapshotError::Invalid("sparse cell position"))
+        ));
+    }
+}
@@ -4,79 +4,86 @@ use grid::Grid;
 
 use crate::core::{Direction, Position};
 
+/// The number of columns in the Befunge-93 torus
+pub const TORUS_93_COLS: usize = 80;
+/// The number of rows in the Befunge-93 torus
+pub const TORUS_93_ROWS: usize = 25;
+
+/// Whether a coordinate pair lies within the Befunge-93 torus
+pub(crate) fn in_torus_93(x: i32, y: i32) -> bool {
+    (0..TORUS_93_COLS as i32).contains(&x) && (0..TORUS_93_ROWS as i32).contains(&y)
+}
+
 /// The program space
+///
+/// Cells in the rectangle the program was loaded into are stored densely
+/// and everything else is stored sparsely, so the space is unbounded.
+/// The least and greatest positions that have been written to are tracked
+/// and the cursor wraps around at those bounds.
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub struct Space<Cell> {
     grid: Grid<Cell>,
     map: HashMap<Position, Cell>,
-    rows: usize,
-    cols: usize,
+    least: Position,
+    greatest: Position,
 }
 
 impl<Cell> Space<Cell>
 where
-    Cell: From<u8> + Default,
+    Cell: From<char> + Default,
 {
+    /// Creates a space from UTF-8 program text with one code point per cell.
+    /// Invalid UTF-8 sequences are replaced with U+FFFD.
     pub fn new(program: &[u8]) -> Self {
-        let mut cols = 0;
-        let mut rows = 0;
-        let mut last_line = 0;
-        for (i, c) in program.iter().enumerate() {
-            if *c == b'\n' {
-                cols = std::cmp::max(i - last_line, cols);
-                last_line = i + 1;
-                rows += 1;
-            }
-        }
-        if last_line != program.len() {
-            cols = std::cmp::max(program.len() - last_line, cols);
-            rows += 1;
+        let text = String::from_utf8_lossy(program);
+        let mut lines: Vec<&str> = text.split('\n').collect();
+        if lines.last() == Some(&"") {
+            lines.pop();
         }
+        let rows = lines.len();
+        let cols = lines
+            .iter()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0);
 
         let mut grid = Grid::new(rows, cols);
-
-        let mut last_line = 0;
-        let mut row = 0;
-        for (i, c) in program.iter().enumerate() {
-            if *c == b'\n' {
-                last_line = i + 1;
-                row += 1;
-                continue;
+        for (y, line) in lines.iter().enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                grid[(y, x)] = Cell::from(c);
             }
-            grid[(row, i - last_line)] = Cell::from(*c);
         }
 
-        Self {
-            grid,
-            map: HashMap::new(),
-            cols,
-            rows,
-        }
+        Self::from_grid(grid)
     }
 }
 
 impl<Cell> Space<Cell>
 where
-    Cell: Copy + Default,
+    Cell: From<u8> + Default,
 {
-    pub fn with_size(rows: u16, cols: u16) -> Self {
-        let rows = rows as usize;
-        let cols = cols as usize;
-        let grid = Grid::new(rows, cols);
-        Self {
-            grid,
-            map: Default::default(),
-            rows,
-            cols,
+    /// Creates a space with the fixed 80x25 dimensions of Befunge-93.
+    /// Each byte of the program occupies one cell like in the reference implementation
+    /// and anything outside of those dimensions is discarded.
+    pub fn new_93(program: &[u8]) -> Self {
+        let mut grid = Grid::new(TORUS_93_ROWS, TORUS_93_COLS);
+        let lines = program.split(|c| *c == b'\n').take(TORUS_93_ROWS);
+        for (y, line) in lines.enumerate() {
+            for (x, c) in line.iter().take(TORUS_93_COLS).enumerate() {
+                grid[(y, x)] = Cell::from(*c);
+            }
         }
-    }
 
-    pub fn rows(&self) -> u16 {
-        self.rows as u16
+        Self::from_grid(grid)
     }
+}
 
-    pub fn cols(&self) -> u16 {
-        self.cols as u16
+impl<Cell> Space<Cell>
+where
+    Cell: Copy + Default,
+{
+    pub fn with_size(rows: usize, cols: usize) -> Self {
+        Self::from_grid(Grid::new(rows, cols))
     }
 
     /// Retrieves the cell located at a position in the program
@@ -85,54 +92,185 @@ where
     }
 }
 
+impl<Cell> Space<Cell>
+where
+    Cell: PartialEq + Default,
+{
+    /// Updates the opcode at a specific position in the program
+    pub fn set_cell(&mut self, pos: Position, cell: Cell) {
+        if let Some((y, x)) = self.grid_index(pos) {
+            self.grid[(y, x)] = cell;
+            return;
+        }
+        // Writing an empty cell never needs to grow the space
+        if cell == Cell::default() {
+            self.map.remove(&pos);
+            return;
+        }
+        self.map.insert(pos, cell);
+        self.least.x = std::cmp::min(self.least.x, pos.x);
+        self.least.y = std::cmp::min(self.least.y, pos.y);
+        self.greatest.x = std::cmp::max(self.greatest.x, pos.x);
+        self.greatest.y = std::cmp::max(self.greatest.y, pos.y);
+    }
+}
+
 impl<Cell> Space<Cell> {
-    /// Gets a reference to the specified cell if it exists
-    pub fn lookup_cell(&self, pos: Position) -> Option<&Cell> {
-        let x = pos.x as usize;
-        let y = pos.y as usize;
-        if x >= self.grid.cols() || y >= self.grid.rows() {
-            self.map.get(&pos)
-        } else {
-            self.grid.get(y, x)
+    fn from_grid(grid: Grid<Cell>) -> Self {
+        // Even an empty program occupies at least one cell
+        let cols = std::cmp::max(grid.cols(), 1);
+        let rows = std::cmp::max(grid.rows(), 1);
+        Self {
+            grid,
+            map: HashMap::new(),
+            least: Position::ORIGIN,
+            greatest: Position {
+                x: cols as i32 - 1,
+                y: rows as i32 - 1,
+            },
         }
     }
 
-    /// Updates the opcode at a specific position in the program
-    pub fn set_cell(&mut self, pos: Position, cell: Cell) {
-        let x = pos.x as usize;
-        let y = pos.y as usize;
+    /// Reassembles a space from the parts exposed by
+    /// [`Space::grid`], [`Space::sparse_cells`], [`Space::least`] and [`Space::greatest`]
+    pub(crate) fn from_parts(
+        grid: Grid<Cell>,
+        sparse: impl IntoIterator<Item = (Position, Cell)>,
+        least: Position,
+        greatest: Position,
+    ) -> Self {
+        Self {
+            grid,
+            map: sparse.into_iter().collect(),
+            least,
+            greatest,
+        }
+    }
+
+    /// The rectangle of cells the program was loaded into
+    pub fn grid(&self) -> &Grid<Cell> {
+        &self.grid
+    }
+
+    /// The cells outside of the grid that have been written to
+    pub fn sparse_cells(&self) -> impl Iterator<Item = (Position, &Cell)> {
+        self.map.iter().map(|(pos, cell)| (*pos, cell))
+    }
+
+    /// The least x and y coordinates of any cell in the space
+    pub fn least(&self) -> Position {
+        self.least
+    }
+
+    /// The greatest x and y coordinates of any cell in the space
+    pub fn greatest(&self) -> Position {
+        self.greatest
+    }
+
+    /// The number of rows between the least and greatest bounds
+    pub fn rows(&self) -> usize {
+        (self.greatest.y as i64 - self.least.y as i64 + 1) as usize
+    }
+
+    /// The number of columns between the least and greatest bounds
+    pub fn cols(&self) -> usize {
+        (self.greatest.x as i64 - self.least.x as i64 + 1) as usize
+    }
+
+    fn grid_index(&self, pos: Position) -> Option<(usize, usize)> {
+        let x = usize::try_from(pos.x).ok()?;
+        let y = usize::try_from(pos.y).ok()?;
         if x >= self.grid.cols() || y >= self.grid.rows() {
-            self.map.insert(pos, cell);
+            None
         } else {
-            self.grid[(y, x)] = cell;
+            Some((y, x))
+        }
+    }
+
+    /// Gets a reference to the specified cell if it exists
+    pub fn lookup_cell(&self, pos: Position) -> Option<&Cell> {
+        match self.grid_index(pos) {
+            Some((y, x)) => self.grid.get(y, x),
+            None => self.map.get(&pos),
         }
-        self.cols = std::cmp::max(self.cols, x + 1);
-        self.rows = std::cmp::max(self.rows, y + 1);
     }
 
     pub fn move_pos(&self, pos: Position, dir: Direction) -> Position {
         let Position { x, y } = pos;
-        let cols = self.cols as u8;
-        let rows = self.rows as u8;
+        let Position { x: min_x, y: min_y } = self.least;
+        let Position { x: max_x, y: max_y } = self.greatest;
         match dir {
             Direction::Right => {
-                let x = x + 1;
-                let x = if x >= cols { 0 } else { x };
+                let x = if x >= max_x { min_x } else { x + 1 };
                 Position { x, y }
             }
             Direction::Left => {
-                let x = if x == 0 { cols } else { x - 1 };
+                let x = if x <= min_x { max_x } else { x - 1 };
                 Position { x, y }
             }
             Direction::Up => {
-                let y = if y == 0 { rows } else { y - 1 };
+                let y = if y <= min_y { max_y } else { y - 1 };
                 Position { x, y }
             }
             Direction::Down => {
-                let y = y + 1;
-                let y = if y >= rows { 0 } else { y };
+                let y = if y >= max_y { min_y } else { y + 1 };
                 Position { x, y }
             }
+            Direction::Delta(..) => self.move_pos_by(pos, dir, 1),
+        }
+    }
+
+    /// How many steps a position can take backwards and forwards along a delta
+    /// before it would leave the bounds
+    fn line_extent(&self, pos: Position, (dx, dy): (i32, i32)) -> (i64, i64) {
+        let steps = |at: i32, least: i32, greatest: i32, delta: i32| {
+            let (at, least, greatest, delta) =
+                (at as i64, least as i64, greatest as i64, delta as i64);
+            match delta.signum() {
+                1 => ((at - least) / delta, (greatest - at) / delta),
+                -1 => ((greatest - at) / -delta, (at - least) / -delta),
+                _ => (i64::MAX, i64::MAX),
+            }
+        };
+        let (back_x, forward_x) = steps(pos.x, self.least.x, self.greatest.x, dx);
+        let (back_y, forward_y) = steps(pos.y, self.least.y, self.greatest.y, dy);
+        (back_x.min(back_y).max(0), forward_x.min(forward_y).max(0))
+    }
+
+    /// Moves a position by many cells at once, wrapping the same way as that many calls to [`Space::move_pos`]
+    pub fn move_pos_by(&self, pos: Position, dir: Direction, cells: u64) -> Position {
+        if cells == 0 {
+            return pos;
+        }
+        if let Direction::Delta(dx, dy) = dir {
+            // A flying IP wraps around to the far end of the line it is on within the bounds,
+            // so it goes around that line like a cardinal one goes around a row or column
+            let (back, forward) = self.line_extent(pos, (dx, dy));
+            let length = (back + forward + 1) as i128;
+            let along = (back as i128 + cells as i128).rem_euclid(length) - back as i128;
+            return Position {
+                x: (pos.x as i128 + dx as i128 * along) as i32,
+                y: (pos.y as i128 + dy as i128 * along) as i32,
+            };
+        }
+        // The first move brings a position outside of the bounds back inside them
+        let pos = self.move_pos(pos, dir);
+        let (dx, dy) = dir.delta();
+        let moves = cells as i128 - 1;
+        let wrap = |at: i32, least: i32, size: usize, delta: i32| {
+            let offset = (at as i128 - least as i128) + delta as i128 * moves;
+            (offset.rem_euclid(size as i128) + least as i128) as i32
+        };
+        match dir {
+            Direction::Left | Direction::Right => Position {
+                x: wrap(pos.x, self.least.x, self.cols(), dx),
+                y: pos.y,
+            },
+            Direction::Up | Direction::Down => Position {
+                x: pos.x,
+                y: wrap(pos.y, self.least.y, self.rows(), dy),
+            },
+            Direction::Delta(..) => unreachable!("flying IPs are moved above"),
         }
     }
 }
@@ -140,6 +278,7 @@ impl<Cell> Space<Cell> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::GridCell;
 
     #[test]
     fn test_insert_origin() {
@@ -186,4 +325,100 @@ mod tests {
         space.set_cell(pos, 2);
         assert_eq!(space.get_cell(pos), 2);
     }
+
+    #[test]
+    fn test_wrap() {
+        let space: Space<u8> = Space::with_size(3, 4);
+        let corner = Position { x: 3, y: 2 };
+        assert_eq!(space.move_pos(Position::ORIGIN, Direction::Left).x, 3);
+        assert_eq!(space.move_pos(Position::ORIGIN, Direction::Up).y, 2);
+        assert_eq!(space.move_pos(corner, Direction::Right).x, 0);
+        assert_eq!(space.move_pos(corner, Direction::Down).y, 0);
+    }
+
+    #[test]
+    fn test_move_pos_by() {
+        let mut space: Space<u8> = Space::with_size(3, 4);
+        space.set_cell(Position { x: -2, y: 0 }, 1);
+        let start = Position { x: 1, y: 2 };
+        for dir in [
+            Direction::Right,
+            Direction::Left,
+            Direction::Up,
+            Direction::Down,
+        ] {
+            let mut pos = start;
+            for cells in 0..20 {
+                assert_eq!(pos, space.move_pos_by(start, dir, cells));
+                pos = space.move_pos(pos, dir);
+            }
+        }
+        let far = space.move_pos_by(start, Direction::Left, i32::MAX as u64);
+        assert_eq!(Position { x: 0, y: 2 }, far);
+    }
+
+    #[test]
+    fn test_move_flying() {
+        let space: Space<u8> = Space::with_size(3, 4);
+        // The line through (1, 2) going down and right only holds (0, 1) and (1, 2)
+        let diagonal = Direction::Delta(1, 1);
+        let start = Position { x: 1, y: 2 };
+        assert_eq!(Position { x: 0, y: 1 }, space.move_pos(start, diagonal));
+        assert_eq!(start, space.move_pos(Position { x: 0, y: 1 }, diagonal));
+        // A knight's move wraps back to where it can go furthest the other way
+        let knight = Direction::Delta(2, -1);
+        let start = Position { x: 3, y: 1 };
+        assert_eq!(Position { x: 1, y: 2 }, space.move_pos(start, knight));
+        for dir in [diagonal, knight] {
+            let mut pos = start;
+            for cells in 0..20 {
+                assert_eq!(pos, space.move_pos_by(start, dir, cells));
+                pos = space.move_pos(pos, dir);
+            }
+        }
+    }
+
+    #[test]
+    fn test_new_93() {
+        let mut program = vec![b'1'; 100];
+        program.extend(b"\n2".repeat(30));
+        let space: Space<u8> = Space::new_93(&program);
+        assert_eq!(space.cols(), 80);
+        assert_eq!(space.rows(), 25);
+        assert_eq!(space.get_cell(Position { x: 79, y: 0 }), b'1');
+        assert_eq!(space.get_cell(Position { x: 0, y: 24 }), b'2');
+        assert_eq!(space.get_cell(Position { x: 1, y: 24 }), 0);
+    }
+
+    #[test]
+    fn test_insert_negative() {
+        let mut space: Space<u8> = Space::with_size(2, 2);
+        let pos = Position { x: -5, y: -1000 };
+        space.set_cell(pos, 2);
+        assert_eq!(space.get_cell(pos), 2);
+        assert_eq!(space.least(), pos);
+        assert_eq!(space.greatest(), Position { x: 1, y: 1 });
+        assert_eq!(space.move_pos(Position::ORIGIN, Direction::Up).y, -1);
+        assert_eq!(space.move_pos(pos, Direction::Left).x, 1);
+        assert_eq!(space.move_pos(pos, Direction::Up).y, 1);
+    }
+
+    #[test]
+    fn test_insert_empty_outside() {
+        let mut space: Space<u8> = Space::with_size(2, 2);
+        space.set_cell(Position { x: 1000, y: 1000 }, 0);
+        assert_eq!(space.greatest(), Position { x: 1, y: 1 });
+    }
+
+    #[test]
+    fn test_new_utf8() {
+        let space: Space<GridCell> = Space::new("aé\n漢字".as_bytes());
+        assert_eq!(space.cols(), 2);
+        assert_eq!(space.rows(), 2);
+        assert_eq!(space.get_cell(Position { x: 1, y: 0 }), GridCell::from('é'));
+        assert_eq!(
+            space.get_cell(Position { x: 1, y: 1 }),
+            GridCell::from('字')
+        );
+    }
 }
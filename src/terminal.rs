@@ -1,11 +1,8 @@
-use std::collections::VecDeque;
-
 use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
 
-use crate::{
-    io::{IO, try_read_number},
-    tui::ListenForKey,
-};
+use bft::{BufferedInput, IO, Input, PendingInput};
+
+use crate::tui::ListenForKey;
 
 /// Simulates the behavior of a terminal prompt.
 /// Allows input to be edited until committed (using newline)
@@ -16,8 +13,9 @@ pub struct VirtualTerminal {
     /// The index of newline characters
     /// Used to determine where lines start and end.
     newline_indices: Vec<usize>,
-    /// A deque of input that has been committed and can be read
-    available_input: VecDeque<u8>,
+    /// Input that has been committed and can be read, and whether the user
+    /// has ended it (using Ctrl+D)
+    available_input: BufferedInput,
     /// Uncommitted user input that can still be modified
     /// Treated as "floating" on top of / after the display data
     uncommitted: Vec<u8>,
@@ -34,7 +32,7 @@ impl Default for VirtualTerminal {
         Self {
             display: Vec::with_capacity(512),
             newline_indices: Vec::with_capacity(32),
-            available_input: VecDeque::with_capacity(512),
+            available_input: BufferedInput::with_capacity(512),
             uncommitted: Vec::with_capacity(64),
             cursor: 0,
             dirty: false,
@@ -46,8 +44,14 @@ impl ListenForKey for VirtualTerminal {
     type Output = ();
 
     fn on_key_event(&mut self, event: KeyEvent) -> Self::Output {
+        if self.available_input.is_closed() {
+            return;
+        }
         if matches!(event.kind, KeyEventKind::Press | KeyEventKind::Repeat) {
             match event.code {
+                KeyCode::Char('d') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                    self.close()
+                }
                 KeyCode::Backspace => self.backspace(),
                 KeyCode::Enter => self.commit(),
                 KeyCode::Left => self.left(),
@@ -61,6 +65,14 @@ impl ListenForKey for VirtualTerminal {
 }
 
 impl VirtualTerminal {
+    /// Creates a terminal with input that was received before it was opened
+    pub fn with_pending_input(input: PendingInput) -> Self {
+        Self {
+            available_input: input.into(),
+            ..Self::default()
+        }
+    }
+
     fn left(&mut self) {
         if self.cursor == 0 {
             return;
@@ -124,7 +136,7 @@ impl VirtualTerminal {
         self.newline_indices.push(i);
         self.uncommitted.push(b'\n');
         // Append the uncommitted buffer to the input and display
-        self.available_input.extend(&self.uncommitted);
+        self.available_input.push(&self.uncommitted);
         self.display.extend(&self.uncommitted);
         // Clear the uncommitted buffer
         self.uncommitted.clear();
@@ -133,6 +145,16 @@ impl VirtualTerminal {
         self.dirty = true;
     }
 
+    /// Commits any uncommitted input and ends the input stream
+    fn close(&mut self) {
+        self.available_input.push(&self.uncommitted);
+        self.display.extend(&self.uncommitted);
+        self.uncommitted.clear();
+        self.cursor = 0;
+        self.available_input.close();
+        self.dirty = true;
+    }
+
     // get a line of committed terminal output
     pub fn get_line(&self, line: usize) -> Option<&[u8]> {
         let newlines = self.newline_indices.len();
@@ -180,20 +202,12 @@ impl VirtualTerminal {
 }
 
 impl IO for VirtualTerminal {
-    fn read_byte(&mut self) -> Option<u8> {
-        self.available_input.pop_front()
+    fn read_byte(&mut self) -> Input<u8> {
+        self.available_input.read_byte()
     }
 
-    fn read_number(&mut self) -> Option<u8> {
-        let iter = self.available_input.iter().copied();
-        let (skip, output) = match try_read_number(iter) {
-            Ok((bytes, n)) => (bytes, Some(n)),
-            Err(skip) => (skip, None),
-        };
-        for _ in 0..skip {
-            self.available_input.pop_front();
-        }
-        output
+    fn read_number(&mut self) -> Input<i32> {
+        self.available_input.read_number()
     }
 
     fn write(&mut self, buf: &[u8]) {
@@ -206,6 +220,23 @@ impl IO for VirtualTerminal {
         self.display.extend_from_slice(buf);
         self.dirty = true;
     }
+
+    /// Output can only be taken back if no input has been shown after it
+    fn unwrite(&mut self, buf: &[u8]) {
+        if !self.display.ends_with(buf) {
+            return;
+        }
+        let len = self.display.len() - buf.len();
+        self.display.truncate(len);
+        while self.newline_indices.last().is_some_and(|i| *i >= len) {
+            self.newline_indices.pop();
+        }
+        self.dirty = true;
+    }
+
+    fn pending_input(&self) -> PendingInput {
+        self.available_input.pending()
+    }
 }
 
 #[cfg(test)]
@@ -261,10 +292,21 @@ mod tests {
         assert_eq!(t.get_line(0), Some(b"Input number!12".as_slice()));
         assert_eq!(t.get_line(1), Some(b"".as_slice()));
         // Check input is available
-        let input: Vec<_> = t.available_input.iter().copied().collect();
-        assert_eq!(input, vec![b'1', b'2', b'\n']);
+        assert_eq!(t.available_input.pending().bytes, vec![b'1', b'2', b'\n']);
         // Read number from input
         let n = t.read_number();
-        assert_eq!(n, Some(12));
+        assert_eq!(n, Input::Value(12));
+        assert_eq!(t.read_number(), Input::Pending);
+    }
+
+    #[test]
+    fn test_close() {
+        let mut t = VirtualTerminal::default();
+        t.input_key('7', NONE);
+        t.close();
+        // The end of input finishes the number
+        assert_eq!(t.read_number(), Input::Value(7));
+        assert_eq!(t.read_number(), Input::Closed);
+        assert_eq!(t.read_byte(), Input::Closed);
     }
 }
@@ -0,0 +1,690 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use thiserror::Error;
+
+use crate::{
+    core::{Direction, GridCell, Position, StackCell},
+    fingerprint::{Fingerprints, Semantics},
+    interpreter::{InstructionPointer, Interpreter},
+    io::{IO, Input, ScriptedIO, ScriptedInput},
+    random::ScriptedRandom,
+    record::{Event, Record, Step},
+    snapshot::{Reader, Snapshot, SnapshotError, Writer},
+};
+
+pub mod jsonl;
+pub mod text;
+
+/// The bytes every trace file starts with
+const MAGIC: &[u8; 4] = b"BFTT";
+/// The version of the trace format written by this build.
+/// It must change whenever the layout below changes.
+pub const VERSION: u32 = 1;
+
+/// Every step of a run and the events that happened during it,
+/// starting from a snapshot of the interpreter before the first step.
+///
+/// Traces are saved in the same kind of binary format as snapshots,
+/// and written one step at a time while the program runs.
+///
+/// ```text
+/// magic                "BFTT"
+/// version              u32
+/// start                list of u8, a snapshot without any pending input
+/// steps                until the end of the file, each one is
+///                      x i32, y i32, instruction i32, waited u8, list of events
+/// event                kind u8 in the order of `Event`'s variants, then its fields in order:
+///                      positions are x i32, y i32, cells and exit codes are i32,
+///                      directions are as in snapshots, flags are u8, output is a list of u8,
+///                      old stacks are lists of i32, semantics are laid out like in snapshots,
+///                      IPs are laid out like in snapshots and queues are lists of them,
+///                      inputs are 0 for Value followed by the value (u8 or i32),
+///                      1 for Pending or 2 for Closed
+/// ```
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Trace {
+    pub start: Snapshot,
+    pub steps: Vec<Step>,
+    pub events: Vec<Event>,
+}
+
+#[derive(Debug, Error)]
+pub enum TraceError {
+    #[error("Could not access trace file")]
+    IO(#[from] io::Error),
+    #[error("Not a trace file")]
+    NotATrace,
+    #[error("Trace format version {0} is not supported (expected {VERSION})")]
+    UnsupportedVersion(u32),
+    #[error("Trace file ended unexpectedly")]
+    Truncated,
+    #[error("Trace contains an invalid {0}")]
+    Invalid(&'static str),
+    #[error("Trace uses fingerprint {0:#x} which is not available")]
+    UnknownFingerprint(i32),
+    #[error("Trace starts from an invalid snapshot")]
+    Snapshot(#[source] SnapshotError),
+    #[error("Replay went to {found} for step {step}, but the trace went to {expected}")]
+    Diverged {
+        step: usize,
+        expected: Position,
+        found: Position,
+    },
+}
+
+impl From<SnapshotError> for TraceError {
+    fn from(error: SnapshotError) -> Self {
+        match error {
+            SnapshotError::Truncated => TraceError::Truncated,
+            SnapshotError::Invalid(what) => TraceError::Invalid(what),
+            SnapshotError::UnknownFingerprint(id) => TraceError::UnknownFingerprint(id),
+            error => TraceError::Snapshot(error),
+        }
+    }
+}
+
+impl Trace {
+    /// Reads a trace from a file, resolving fingerprints with the standard ones
+    pub fn load(path: &Path) -> Result<Self, TraceError> {
+        let bytes = fs::read(path)?;
+        Self::decode(&bytes, &Fingerprints::default())
+    }
+
+    /// Reads a trace, looking up the fingerprints it uses in a registry.
+    /// A step cut off at the end, by a run that was stopped while writing it, is left out.
+    pub fn decode(bytes: &[u8], fingerprints: &Fingerprints) -> Result<Self, TraceError> {
+        let mut r = Reader(bytes);
+        if r.take(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err(TraceError::NotATrace);
+        }
+        let version = r.u32()?;
+        if version != VERSION {
+            return Err(TraceError::UnsupportedVersion(version));
+        }
+        let len = r.len()?;
+        let start = Snapshot::decode(r.take(len)?, fingerprints).map_err(TraceError::Snapshot)?;
+
+        let mut steps = Vec::new();
+        let mut events = Vec::new();
+        while !r.0.is_empty() {
+            match read_step(&mut r, fingerprints) {
+                Ok((step, step_events)) => {
+                    steps.push(step);
+                    events.extend(step_events);
+                }
+                Err(SnapshotError::Truncated) => break,
+                Err(error) => return Err(error.into()),
+            }
+        }
+        Ok(Self {
+            start,
+            steps,
+            events,
+        })
+    }
+
+    /// The events of each step, in the order they were executed
+    pub fn step_events(&self) -> impl Iterator<Item = (&Step, &[Event])> {
+        let mut start = 0;
+        self.steps.iter().map(move |step| {
+            let events = &self.events[start..start + step.events];
+            start += step.events;
+            (step, events)
+        })
+    }
+
+    /// Creates an interpreter that runs the traced program again from where the trace started.
+    /// It reads the input the run read and makes the same choices for "?",
+    /// writing output to an IO.
+    pub fn interpreter<IOImpl: IO>(
+        &self,
+        io: IOImpl,
+    ) -> Interpreter<ScriptedIO<IOImpl>, (), ScriptedRandom> {
+        let inputs = self.events.iter().filter_map(|event| match event {
+            Event::ReadByte { input } => Some(ScriptedInput::Byte(*input)),
+            Event::ReadNumber { input } => Some(ScriptedInput::Number(*input)),
+            _ => None,
+        });
+        let directions = self.events.iter().filter_map(|event| match event {
+            Event::Random { dir } => Some(*dir),
+            _ => None,
+        });
+        let io = ScriptedIO::new(inputs, io);
+        Interpreter::from_snapshot(self.start.clone(), io, ())
+            .with_random(ScriptedRandom::new(directions))
+    }
+}
+
+/// Writes every step a program takes to a trace as soon as it is committed
+pub struct TraceWriter<W: Write> {
+    writer: W,
+    step: Option<Step>,
+    events: Vec<Event>,
+    /// The first error writing failed with, after which nothing more is written
+    error: Option<io::Error>,
+}
+
+impl TraceWriter<BufWriter<File>> {
+    /// Creates a trace file for a run starting from a snapshot
+    pub fn create(path: &Path, start: &Snapshot) -> Result<Self, TraceError> {
+        let file = BufWriter::new(File::create(path)?);
+        Self::new(file, start)
+    }
+}
+
+impl<W: Write> TraceWriter<W> {
+    /// Starts a trace of a run from a snapshot, leaving out the snapshot's pending input
+    pub fn new(mut writer: W, start: &Snapshot) -> Result<Self, TraceError> {
+        let mut start = start.clone();
+        start.input = Default::default();
+        let snapshot = start.encode();
+        let mut w = Writer::default();
+        w.bytes(MAGIC);
+        w.u32(VERSION);
+        w.len(snapshot.len());
+        w.bytes(&snapshot);
+        writer.write_all(&w.0)?;
+        Ok(Self {
+            writer,
+            step: None,
+            events: Vec::new(),
+            error: None,
+        })
+    }
+
+    /// Writes out everything that has been recorded,
+    /// returning the first error writing failed with if there was one
+    pub fn finish(&mut self) -> Result<(), TraceError> {
+        if let Some(error) = self.error.take() {
+            return Err(error.into());
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn record(&mut self, event: Event) {
+        self.events.push(event);
+    }
+}
+
+impl<W: Write> Record for TraceWriter<W> {
+    fn start_step(&mut self, at: Position, instruction: GridCell) {
+        self.events.clear();
+        self.step = Some(Step {
+            at,
+            instruction,
+            events: 0,
+            waited: false,
+        });
+    }
+
+    /// Forgets a step that waited for input, unless it changed something before it did
+    fn rollback_step(&mut self) {
+        if self.events.is_empty() {
+            self.step = None;
+        } else {
+            if let Some(step) = &mut self.step {
+                step.waited = true;
+            }
+            self.commit_step();
+        }
+    }
+
+    fn commit_step(&mut self) {
+        let Some(step) = self.step.take() else {
+            return;
+        };
+        if self.error.is_some() {
+            return;
+        }
+        let mut w = Writer::default();
+        write_step(&mut w, &step, &self.events);
+        if let Err(error) = self.writer.write_all(&w.0) {
+            self.error = Some(error);
+        }
+    }
+
+    fn replace(&mut self, at: Position, old: GridCell, new: GridCell) {
+        self.record(Event::Replace { at, old, new });
+    }
+
+    fn pop(&mut self, old: StackCell) {
+        self.record(Event::Pop { old });
+    }
+
+    fn pop_bottom(&mut self) {
+        self.record(Event::PopBottom);
+    }
+
+    fn push(&mut self, new: StackCell) {
+        self.record(Event::Push { new });
+    }
+
+    fn enter_quote(&mut self) {
+        self.record(Event::EnterQuote);
+    }
+
+    fn exit_quote(&mut self) {
+        self.record(Event::ExitQuote);
+    }
+
+    fn begin_stack(&mut self) {
+        self.record(Event::BeginStack);
+    }
+
+    fn end_stack(&mut self, old: &[StackCell]) {
+        self.record(Event::EndStack { old: old.to_vec() });
+    }
+
+    fn pop_under(&mut self, old: StackCell) {
+        self.record(Event::PopUnder { old });
+    }
+
+    fn pop_under_bottom(&mut self) {
+        self.record(Event::PopUnderBottom);
+    }
+
+    fn push_under(&mut self, new: StackCell) {
+        self.record(Event::PushUnder { new });
+    }
+
+    fn storage_offset(&mut self, old: Position, new: Position) {
+        self.record(Event::StorageOffset { old, new });
+    }
+
+    fn move_cursor(&mut self, old: Position, new: Position) {
+        // Moves that follow each other within a step are merged into one, like in a timeline
+        if let Some(Event::Move { new: last, .. }) = self.events.last_mut() {
+            *last = new;
+        } else {
+            self.record(Event::Move { old, new });
+        }
+    }
+
+    fn turn(&mut self, old: Direction, new: Direction) {
+        self.record(Event::Turn { old, new });
+    }
+
+    fn output(&mut self, bytes: &[u8]) {
+        self.record(Event::Output {
+            bytes: bytes.to_vec(),
+        });
+    }
+
+    fn read_byte(&mut self, input: Input<u8>) {
+        self.record(Event::ReadByte { input });
+    }
+
+    fn read_number(&mut self, input: Input<i32>) {
+        self.record(Event::ReadNumber { input });
+    }
+
+    fn random(&mut self, dir: Direction) {
+        self.record(Event::Random { dir });
+    }
+
+    fn exit_code(&mut self, old: i32, new: i32) {
+        self.record(Event::ExitCode { old, new });
+    }
+
+    fn division_prompted(&mut self, prompted: bool) {
+        self.record(Event::DivisionPrompted { prompted });
+    }
+
+    fn semantics(&mut self, old: &Semantics, new: &Semantics) {
+        self.record(Event::Semantics {
+            old: Box::new(old.clone()),
+            new: Box::new(new.clone()),
+        });
+    }
+
+    fn split(&mut self, child: &InstructionPointer) {
+        self.record(Event::Split {
+            child: Box::new(child.clone()),
+        });
+    }
+
+    fn next_ip(&mut self) {
+        self.record(Event::NextIp);
+    }
+
+    fn end_ip(&mut self, old: &InstructionPointer) {
+        self.record(Event::EndIp {
+            old: Box::new(old.clone()),
+        });
+    }
+
+    fn clear_queue(&mut self, old: &[InstructionPointer]) {
+        self.record(Event::ClearQueue { old: old.to_vec() });
+    }
+}
+
+fn write_step(w: &mut Writer, step: &Step, events: &[Event]) {
+    w.position(step.at);
+    w.i32(step.instruction.0);
+    w.bool(step.waited);
+    w.len(events.len());
+    for event in events {
+        write_event(w, event);
+    }
+}
+
+fn write_event(w: &mut Writer, event: &Event) {
+    match event {
+        Event::Replace { at, old, new } => {
+            w.u8(0);
+            w.position(*at);
+            w.i32(old.0);
+            w.i32(new.0);
+        }
+        Event::Pop { old } => {
+            w.u8(1);
+            w.i32(old.0);
+        }
+        Event::PopBottom => w.u8(2),
+        Event::Push { new } => {
+            w.u8(3);
+            w.i32(new.0);
+        }
+        Event::EnterQuote => w.u8(4),
+        Event::ExitQuote => w.u8(5),
+        Event::BeginStack => w.u8(6),
+        Event::EndStack { old } => {
+            w.u8(7);
+            w.cells(old);
+        }
+        Event::PopUnder { old } => {
+            w.u8(8);
+            w.i32(old.0);
+        }
+        Event::PopUnderBottom => w.u8(9),
+        Event::PushUnder { new } => {
+            w.u8(10);
+            w.i32(new.0);
+        }
+        Event::StorageOffset { old, new } => {
+            w.u8(11);
+            w.position(*old);
+            w.position(*new);
+        }
+        Event::Move { old, new } => {
+            w.u8(12);
+            w.position(*old);
+            w.position(*new);
+        }
+        Event::Turn { old, new } => {
+            w.u8(13);
+            w.direction(*old);
+            w.direction(*new);
+        }
+        Event::Output { bytes } => {
+            w.u8(14);
+            w.len(bytes.len());
+            w.bytes(bytes);
+        }
+        Event::ReadByte { input } => {
+            w.u8(15);
+            write_input(w, *input, |w, byte| w.u8(byte));
+        }
+        Event::ReadNumber { input } => {
+            w.u8(16);
+            write_input(w, *input, |w, number| w.i32(number));
+        }
+        Event::Random { dir } => {
+            w.u8(17);
+            w.direction(*dir);
+        }
+        Event::ExitCode { old, new } => {
+            w.u8(18);
+            w.i32(*old);
+            w.i32(*new);
+        }
+        Event::DivisionPrompted { prompted } => {
+            w.u8(19);
+            w.bool(*prompted);
+        }
+        Event::Semantics { old, new } => {
+            w.u8(20);
+            w.semantics(old);
+            w.semantics(new);
+        }
+        Event::Split { child } => {
+            w.u8(21);
+            w.ip(child);
+        }
+        Event::NextIp => w.u8(22),
+        Event::EndIp { old } => {
+            w.u8(23);
+            w.ip(old);
+        }
+        Event::ClearQueue { old } => {
+            w.u8(24);
+            w.len(old.len());
+            for ip in old {
+                w.ip(ip);
+            }
+        }
+    }
+}
+
+fn write_input<T>(w: &mut Writer, input: Input<T>, value: impl Fn(&mut Writer, T)) {
+    match input {
+        Input::Value(v) => {
+            w.u8(0);
+            value(w, v);
+        }
+        Input::Pending => w.u8(1),
+        Input::Closed => w.u8(2),
+    }
+}
+
+fn read_step(
+    r: &mut Reader,
+    fingerprints: &Fingerprints,
+) -> Result<(Step, Vec<Event>), SnapshotError> {
+    let at = r.position()?;
+    let instruction = GridCell(r.i32()?);
+    let waited = r.bool()?;
+    let mut events = Vec::new();
+    for _ in 0..r.len()? {
+        events.push(read_event(r, fingerprints)?);
+    }
+    let step = Step {
+        at,
+        instruction,
+        events: events.len(),
+        waited,
+    };
+    Ok((step, events))
+}
+
+fn read_event(r: &mut Reader, fingerprints: &Fingerprints) -> Result<Event, SnapshotError> {
+    let event = match r.u8()? {
+        0 => Event::Replace {
+            at: r.position()?,
+            old: GridCell(r.i32()?),
+            new: GridCell(r.i32()?),
+        },
+        1 => Event::Pop {
+            old: StackCell(r.i32()?),
+        },
+        2 => Event::PopBottom,
+        3 => Event::Push {
+            new: StackCell(r.i32()?),
+        },
+        4 => Event::EnterQuote,
+        5 => Event::ExitQuote,
+        6 => Event::BeginStack,
+        7 => Event::EndStack { old: r.cells()? },
+        8 => Event::PopUnder {
+            old: StackCell(r.i32()?),
+        },
+        9 => Event::PopUnderBottom,
+        10 => Event::PushUnder {
+            new: StackCell(r.i32()?),
+        },
+        11 => Event::StorageOffset {
+            old: r.position()?,
+            new: r.position()?,
+        },
+        12 => Event::Move {
+            old: r.position()?,
+            new: r.position()?,
+        },
+        13 => Event::Turn {
+            old: r.direction()?,
+            new: r.direction()?,
+        },
+        14 => {
+            let len = r.len()?;
+            Event::Output {
+                bytes: r.take(len)?.to_vec(),
+            }
+        }
+        15 => Event::ReadByte {
+            input: read_input(r, |r| r.u8())?,
+        },
+        16 => Event::ReadNumber {
+            input: read_input(r, |r| r.i32())?,
+        },
+        17 => Event::Random {
+            dir: r.direction()?,
+        },
+        18 => Event::ExitCode {
+            old: r.i32()?,
+            new: r.i32()?,
+        },
+        19 => Event::DivisionPrompted {
+            prompted: r.bool()?,
+        },
+        20 => Event::Semantics {
+            old: Box::new(r.semantics(fingerprints)?),
+            new: Box::new(r.semantics(fingerprints)?),
+        },
+        21 => Event::Split {
+            child: Box::new(r.ip(fingerprints)?),
+        },
+        22 => Event::NextIp,
+        23 => Event::EndIp {
+            old: Box::new(r.ip(fingerprints)?),
+        },
+        24 => {
+            let mut old = Vec::new();
+            for _ in 0..r.len()? {
+                old.push(r.ip(fingerprints)?);
+            }
+            Event::ClearQueue { old }
+        }
+        _ => return Err(SnapshotError::Invalid("event")),
+    };
+    Ok(event)
+}
+
+fn read_input<'a, T>(
+    r: &mut Reader<'a>,
+    value: impl Fn(&mut Reader<'a>) -> Result<T, SnapshotError>,
+) -> Result<Input<T>, SnapshotError> {
+    match r.u8()? {
+        0 => Ok(Input::Value(value(r)?)),
+        1 => Ok(Input::Pending),
+        2 => Ok(Input::Closed),
+        _ => Err(SnapshotError::Invalid("input")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        core::Dialect,
+        interpreter::{Settings, Status},
+        io::VecIO,
+        record::Timeline,
+    };
+
+    /// Runs a Funge-98 program that reads input, uses "?", stacks, fingerprints and "t",
+    /// recording it to a timeline and a trace
+    fn traced() -> Interpreter<VecIO, (Timeline, TraceWriter<Vec<u8>>)> {
+        let program = concat!(
+            " v\n",
+            ">?<\n",
+            r#" >~&+#@2{1u0}"AMOR"4($$X."AMOR"4)#@t"Z"51p3q"#,
+        );
+        let settings = Settings {
+            dialect: Dialect::Funge98,
+            ..Default::default()
+        };
+        let interpreter = Interpreter::builder()
+            .with_settings(settings)
+            .with_io(VecIO::with_input(b"x12\n").close_input())
+            .with_seed(5)
+            .build(program.as_bytes());
+        let start = interpreter.snapshot();
+        let writer = TraceWriter::new(Vec::new(), &start).unwrap();
+        let io = VecIO::with_input(b"x12\n").close_input();
+        let mut interpreter = Interpreter::from_snapshot(start, io, (Timeline::default(), writer));
+        assert_eq!(Status::Terminated, interpreter.run());
+        interpreter.recorder_mut().1.finish().unwrap();
+        interpreter
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let interpreter = traced();
+        let (timeline, writer) = interpreter.recorder();
+        let trace = Trace::decode(&writer.writer, &Fingerprints::default()).unwrap();
+        assert_eq!(timeline.steps(), &trace.steps[..]);
+        assert_eq!(timeline.events(), &trace.events[..]);
+        assert!(trace.start.input.bytes.is_empty());
+
+        // A step cut off at the end is left out
+        let cut = &writer.writer[..writer.writer.len() - 3];
+        let partial = Trace::decode(cut, &Fingerprints::default()).unwrap();
+        assert_eq!(trace.steps[..trace.steps.len() - 1], partial.steps[..]);
+
+        assert!(matches!(
+            Trace::decode(b"BFTS", &Fingerprints::default()),
+            Err(TraceError::NotATrace)
+        ));
+    }
+
+    #[test]
+    fn test_replay() {
+        let interpreter = traced();
+        let trace =
+            Trace::decode(&interpreter.recorder().1.writer, &Fingerprints::default()).unwrap();
+        let mut replayed = trace.interpreter(VecIO::default());
+        for step in trace.steps.iter().filter(|step| !step.waited) {
+            assert_eq!(step.at, replayed.current_position());
+            replayed.step();
+        }
+        assert_eq!(interpreter.io().output(), replayed.io().inner().output());
+        assert_eq!(interpreter.exit_code(), replayed.exit_code());
+        assert_eq!(Status::Terminated, replayed.step());
+    }
+
+    #[test]
+    fn test_apply_steps() {
+        let interpreter = traced();
+        let trace =
+            Trace::decode(&interpreter.recorder().1.writer, &Fingerprints::default()).unwrap();
+        let io = VecIO::default();
+        let mut applied = Interpreter::from_snapshot(trace.start.clone(), io, Timeline::default());
+        for (step, events) in trace.step_events() {
+            applied.apply_step(step, events);
+        }
+        let mut expected = interpreter.snapshot();
+        expected.input = Default::default();
+        assert_eq!(expected, applied.snapshot());
+        assert_eq!(interpreter.io().output(), applied.io().output());
+        assert_eq!(interpreter.steps(), applied.steps());
+        assert_eq!(
+            interpreter.recorder().0.events(),
+            applied.recorder().events()
+        );
+    }
+}
@@ -0,0 +1,423 @@
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    core::{Direction, GridCell, Mode, Position, StackCell},
+    fingerprint::Semantics,
+    interpreter::InstructionPointer,
+    io::Input,
+    record::Record,
+    snapshot::Snapshot,
+};
+
+/// The version of the JSON Lines schema written by this build.
+/// It must change whenever a field below is removed or changes meaning.
+pub const VERSION: u32 = 1;
+
+/// Writes every step of a run as one JSON object per line, for scripts to read.
+/// Every object has every field:
+///
+/// ```text
+/// version       always 1
+/// step          the number of steps written before this one
+/// ip            id of the IP that executed the step
+/// at            {"x": i32, "y": i32} where the instruction was executed
+/// opcode        the value of the cell that was executed
+/// instruction   the opcode as a one character string, or null if it isn't printable
+/// direction     "up", "down", "left", "right" or "(dx, dy)" for any other delta,
+///               the way the IP moves afterwards
+/// mode          "normal" or "quote", the mode the instruction was executed in
+/// pops          values popped from the top stack in order, null for a pop from an empty stack
+/// pushes        values pushed onto the top stack in order
+/// pops_under    values popped from the stack under the top one by "u" and "}", null when empty
+/// pushes_under  values pushed onto the stack under the top one by "u" and "{"
+/// writes        cells changed, each {"x": i32, "y": i32, "old": i32, "new": i32}
+/// input         bytes read by "~"
+/// numbers       numbers read by "&"
+/// end_of_input  whether a read found that the input had ended
+/// output        bytes written by "." and ","
+/// waited        whether the step stopped to wait for input, and will be executed again
+/// ```
+///
+/// A step that waited for input without changing anything is left out.
+pub struct JsonLinesWriter<W: Write> {
+    writer: W,
+    /// The direction and mode of every IP, with the one that executes next first
+    ips: VecDeque<IpState>,
+    steps: u64,
+    step: Option<StepLine>,
+    /// The first error writing failed with, after which nothing more is written
+    error: Option<io::Error>,
+}
+
+struct IpState {
+    id: u32,
+    dir: Direction,
+    mode: Mode,
+}
+
+impl IpState {
+    fn new(ip: &InstructionPointer) -> Self {
+        Self {
+            id: ip.id,
+            dir: ip.cursor.dir,
+            mode: ip.cursor.mode,
+        }
+    }
+}
+
+/// What has happened so far during the step being executed
+struct StepLine {
+    ip: Option<u32>,
+    at: Position,
+    instruction: GridCell,
+    /// The way the IP moves once the step is over
+    dir: Option<Direction>,
+    mode: Option<Mode>,
+    changed: bool,
+    pops: Vec<Option<StackCell>>,
+    pushes: Vec<StackCell>,
+    pops_under: Vec<Option<StackCell>>,
+    pushes_under: Vec<StackCell>,
+    writes: Vec<(Position, GridCell, GridCell)>,
+    input: Vec<u8>,
+    numbers: Vec<i32>,
+    end_of_input: bool,
+    output: Vec<u8>,
+}
+
+impl JsonLinesWriter<BufWriter<File>> {
+    /// Creates a JSON Lines file for a run starting from a snapshot
+    pub fn create(path: &Path, start: &Snapshot) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(Self::new(file, start))
+    }
+}
+
+impl<W: Write> JsonLinesWriter<W> {
+    /// Starts writing the steps of a run from a snapshot, which gives the IPs' directions and modes
+    pub fn new(writer: W, start: &Snapshot) -> Self {
+        Self {
+            writer,
+            ips: start.ips.iter().map(IpState::new).collect(),
+            steps: 0,
+            step: None,
+            error: None,
+        }
+    }
+
+    /// Writes out everything that has been recorded,
+    /// returning the first error writing failed with if there was one
+    pub fn finish(&mut self) -> io::Result<()> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        self.writer.flush()
+    }
+
+    /// The step being executed, marked as having changed something
+    fn changed(&mut self) -> Option<&mut StepLine> {
+        let step = self.step.as_mut()?;
+        step.changed = true;
+        Some(step)
+    }
+
+    fn write_step(&mut self, step: StepLine, waited: bool) {
+        if self.error.is_some() {
+            return;
+        }
+        let mut line = String::new();
+        _ = write!(line, r#"{{"version":{VERSION},"step":{}"#, self.steps);
+        match step.ip {
+            Some(id) => _ = write!(line, r#","ip":{id}"#),
+            None => line.push_str(r#","ip":null"#),
+        }
+        let Position { x, y } = step.at;
+        _ = write!(
+            line,
+            r#","at":{{"x":{x},"y":{y}}},"opcode":{}"#,
+            step.instruction.0
+        );
+        match char::from_u32(step.instruction.0 as u32) {
+            Some(c) if !c.is_control() => {
+                let escape = if matches!(c, '"' | '\\') { "\\" } else { "" };
+                _ = write!(line, r#","instruction":"{escape}{c}""#);
+            }
+            _ => line.push_str(r#","instruction":null"#),
+        }
+        let mode = match step.mode {
+            Some(Mode::Quote) => "quote",
+            _ => "normal",
+        };
+        let dir = step.dir.unwrap_or(Direction::Right);
+        _ = write!(line, r#","direction":"{dir}","mode":"{mode}""#);
+        let cell = |cell: &StackCell| cell.0;
+        let popped =
+            |cell: &Option<StackCell>| cell.map_or("null".to_string(), |c| c.0.to_string());
+        list(&mut line, "pops", step.pops.iter().map(popped));
+        list(&mut line, "pushes", step.pushes.iter().map(cell));
+        list(&mut line, "pops_under", step.pops_under.iter().map(popped));
+        list(
+            &mut line,
+            "pushes_under",
+            step.pushes_under.iter().map(cell),
+        );
+        let write = |(at, old, new): &(Position, GridCell, GridCell)| {
+            format!(
+                r#"{{"x":{},"y":{},"old":{},"new":{}}}"#,
+                at.x, at.y, old.0, new.0
+            )
+        };
+        list(&mut line, "writes", step.writes.iter().map(write));
+        list(&mut line, "input", step.input.iter());
+        list(&mut line, "numbers", step.numbers.iter());
+        _ = write!(line, r#","end_of_input":{}"#, step.end_of_input);
+        list(&mut line, "output", step.output.iter());
+        _ = writeln!(line, r#","waited":{waited}}}"#);
+
+        self.steps += 1;
+        if let Err(error) = self.writer.write_all(line.as_bytes()) {
+            self.error = Some(error);
+        }
+    }
+}
+
+/// Appends a field holding a list of values
+fn list<T: std::fmt::Display>(line: &mut String, name: &str, values: impl Iterator<Item = T>) {
+    _ = write!(line, r#","{name}":["#);
+    for (i, value) in values.enumerate() {
+        if i > 0 {
+            line.push(',');
+        }
+        _ = write!(line, "{value}");
+    }
+    line.push(']');
+}
+
+impl<W: Write> Record for JsonLinesWriter<W> {
+    fn start_step(&mut self, at: Position, instruction: GridCell) {
+        let ip = self.ips.front();
+        self.step = Some(StepLine {
+            ip: ip.map(|ip| ip.id),
+            at,
+            instruction,
+            dir: ip.map(|ip| ip.dir),
+            mode: ip.map(|ip| ip.mode),
+            changed: false,
+            pops: Vec::new(),
+            pushes: Vec::new(),
+            pops_under: Vec::new(),
+            pushes_under: Vec::new(),
+            writes: Vec::new(),
+            input: Vec::new(),
+            numbers: Vec::new(),
+            end_of_input: false,
+            output: Vec::new(),
+        });
+    }
+
+    /// Forgets a step that waited for input, unless it changed something before it did
+    fn rollback_step(&mut self) {
+        if let Some(step) = self.step.take()
+            && step.changed
+        {
+            self.write_step(step, true);
+        }
+    }
+
+    fn commit_step(&mut self) {
+        if let Some(step) = self.step.take() {
+            self.write_step(step, false);
+        }
+    }
+
+    fn replace(&mut self, at: Position, old: GridCell, new: GridCell) {
+        if let Some(step) = self.changed() {
+            step.writes.push((at, old, new));
+        }
+    }
+
+    fn pop(&mut self, old: StackCell) {
+        if let Some(step) = self.changed() {
+            step.pops.push(Some(old));
+        }
+    }
+
+    fn pop_bottom(&mut self) {
+        if let Some(step) = self.changed() {
+            step.pops.push(None);
+        }
+    }
+
+    fn push(&mut self, new: StackCell) {
+        if let Some(step) = self.changed() {
+            step.pushes.push(new);
+        }
+    }
+
+    fn enter_quote(&mut self) {
+        self.changed();
+        if let Some(ip) = self.ips.front_mut() {
+            ip.mode = Mode::Quote;
+        }
+    }
+
+    fn exit_quote(&mut self) {
+        self.changed();
+        if let Some(ip) = self.ips.front_mut() {
+            ip.mode = Mode::Normal;
+        }
+    }
+
+    fn begin_stack(&mut self) {
+        self.changed();
+    }
+
+    fn end_stack(&mut self, _old: &[StackCell]) {
+        self.changed();
+    }
+
+    fn pop_under(&mut self, old: StackCell) {
+        if let Some(step) = self.changed() {
+            step.pops_under.push(Some(old));
+        }
+    }
+
+    fn pop_under_bottom(&mut self) {
+        if let Some(step) = self.changed() {
+            step.pops_under.push(None);
+        }
+    }
+
+    fn push_under(&mut self, new: StackCell) {
+        if let Some(step) = self.changed() {
+            step.pushes_under.push(new);
+        }
+    }
+
+    fn storage_offset(&mut self, _old: Position, _new: Position) {
+        self.changed();
+    }
+
+    fn move_cursor(&mut self, _old: Position, _new: Position) {
+        self.changed();
+    }
+
+    fn turn(&mut self, _old: Direction, new: Direction) {
+        if let Some(step) = self.changed() {
+            step.dir = Some(new);
+        }
+        if let Some(ip) = self.ips.front_mut() {
+            ip.dir = new;
+        }
+    }
+
+    fn output(&mut self, bytes: &[u8]) {
+        if let Some(step) = self.changed() {
+            step.output.extend_from_slice(bytes);
+        }
+    }
+
+    fn read_byte(&mut self, input: Input<u8>) {
+        if let Some(step) = self.changed() {
+            match input {
+                Input::Value(byte) => step.input.push(byte),
+                Input::Closed => step.end_of_input = true,
+                Input::Pending => {}
+            }
+        }
+    }
+
+    fn read_number(&mut self, input: Input<i32>) {
+        if let Some(step) = self.changed() {
+            match input {
+                Input::Value(number) => step.numbers.push(number),
+                Input::Closed => step.end_of_input = true,
+                Input::Pending => {}
+            }
+        }
+    }
+
+    fn random(&mut self, _dir: Direction) {
+        self.changed();
+    }
+
+    fn exit_code(&mut self, _old: i32, _new: i32) {
+        self.changed();
+    }
+
+    fn division_prompted(&mut self, _prompted: bool) {
+        self.changed();
+    }
+
+    fn semantics(&mut self, _old: &Semantics, _new: &Semantics) {
+        self.changed();
+    }
+
+    fn split(&mut self, child: &InstructionPointer) {
+        self.changed();
+        self.ips.push_back(IpState::new(child));
+    }
+
+    fn next_ip(&mut self) {
+        self.changed();
+        self.ips.rotate_left(1);
+    }
+
+    fn end_ip(&mut self, _old: &InstructionPointer) {
+        self.changed();
+        self.ips.pop_front();
+    }
+
+    fn clear_queue(&mut self, _old: &[InstructionPointer]) {
+        self.changed();
+        self.ips.truncate(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        interpreter::{Interpreter, Status},
+        io::VecIO,
+    };
+
+    fn lines(program: &[u8], input: &[u8]) -> Vec<String> {
+        let interpreter = Interpreter::builder()
+            .with_io(VecIO::with_input(input).close_input())
+            .build(program);
+        let start = interpreter.snapshot();
+        let writer = JsonLinesWriter::new(Vec::new(), &start);
+        let io = VecIO::with_input(input).close_input();
+        let mut interpreter = Interpreter::from_snapshot(start, io, writer);
+        assert_eq!(Status::Terminated, interpreter.run());
+        interpreter.recorder_mut().finish().unwrap();
+        let output = String::from_utf8(interpreter.recorder().writer.clone()).unwrap();
+        output.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_lines() {
+        let lines = lines(b"~&+\"A\"v\n@,,p00<", b"x12");
+        assert_eq!(
+            r#"{"version":1,"step":0,"ip":0,"at":{"x":0,"y":0},"opcode":126,"instruction":"~","direction":"right","mode":"normal","pops":[],"pushes":[120],"pops_under":[],"pushes_under":[],"writes":[],"input":[120],"numbers":[],"end_of_input":false,"output":[],"waited":false}"#,
+            lines[0]
+        );
+        assert!(lines[1].contains(r#""instruction":"&""#));
+        assert!(lines[1].contains(r#""numbers":[12]"#));
+        assert!(lines[2].contains(r#""pops":[12,120],"pushes":[132]"#));
+        assert!(lines[3].contains(r#""instruction":"\"""#));
+        assert!(lines[4].contains(r#""mode":"quote","pops":[],"pushes":[65]"#));
+        assert!(lines[6].contains(r#""direction":"down""#));
+        assert!(lines[10].contains(r#""writes":[{"x":0,"y":0,"old":126,"new":65}]"#));
+        assert!(lines[11].contains(r#""output":[194,132]"#));
+        assert!(lines[12].contains(r#""pops":[null]"#));
+        assert!(lines[13].ends_with(r#""instruction":"@","direction":"left","mode":"normal","pops":[],"pushes":[],"pops_under":[],"pushes_under":[],"writes":[],"input":[],"numbers":[],"end_of_input":false,"output":[],"waited":false}"#));
+    }
+}
@@ -0,0 +1,116 @@
+use std::{collections::VecDeque, fmt::Write};
+
+use crate::{
+    core::{Direction, Position},
+    record::{Event, Step},
+    snapshot::Snapshot,
+};
+
+/// Formats the steps of a trace as lines of the human-readable log format
+/// sketched in `docs/designs/timeline.md`:
+///
+/// ```text
+/// + (3,0) > pop(1) pop(0) push(1)
+/// ```
+///
+/// Each line is the instruction, the position it was executed at,
+/// the direction the IP moves in afterwards and the stack effects of the step.
+/// Popping from an empty stack shows as `pop()`, and pops and pushes on the stack
+/// under the top one, by "u" and "}", show as `pop_under` and `push_under`.
+pub struct TextTrace {
+    /// The direction of every IP, with the one that executes next first
+    directions: VecDeque<Direction>,
+}
+
+impl TextTrace {
+    /// Starts following the IPs of a trace from the state it started in
+    pub fn new(start: &Snapshot) -> Self {
+        Self {
+            directions: start.ips.iter().map(|ip| ip.cursor.dir).collect(),
+        }
+    }
+
+    /// Formats the next step of the trace.
+    /// Every step has to be given in order, even ones that aren't shown,
+    /// to keep track of which way each IP is going.
+    pub fn line(&mut self, step: &Step, events: &[Event]) -> String {
+        let mut effects = String::new();
+        let mut dir = self.directions.front().copied();
+        for event in events {
+            match event {
+                Event::Pop { old } => _ = write!(effects, " pop({})", old.0),
+                Event::PopBottom => effects.push_str(" pop()"),
+                Event::Push { new } => _ = write!(effects, " push({})", new.0),
+                Event::PopUnder { old } => _ = write!(effects, " pop_under({})", old.0),
+                Event::PopUnderBottom => effects.push_str(" pop_under()"),
+                Event::PushUnder { new } => _ = write!(effects, " push_under({})", new.0),
+                Event::Turn { old: _, new } => {
+                    if let Some(current) = self.directions.front_mut() {
+                        *current = *new;
+                    }
+                    dir = Some(*new);
+                }
+                Event::Split { child } => self.directions.push_back(child.cursor.dir),
+                Event::NextIp => self.directions.rotate_left(1),
+                Event::EndIp { old: _ } => _ = self.directions.pop_front(),
+                Event::ClearQueue { old: _ } => self.directions.truncate(1),
+                _ => {}
+            }
+        }
+        let instruction = match char::from_u32(step.instruction.0 as u32) {
+            Some(c) if !c.is_control() => c.to_string(),
+            _ => step.instruction.0.to_string(),
+        };
+        let dir = dir.map_or(' ', Direction::arrow);
+        let Position { x, y } = step.at;
+        format!("{instruction} ({x},{y}) {dir}{effects}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        interpreter::{Interpreter, Status},
+        io::VecIO,
+        record::Timeline,
+    };
+
+    fn lines(program: &[u8]) -> Vec<String> {
+        let mut interpreter = Interpreter::builder()
+            .with_io(VecIO::default())
+            .with_recorder(Timeline::default())
+            .build(program);
+        let start = interpreter.snapshot();
+        assert_eq!(Status::Terminated, interpreter.run());
+        let timeline = interpreter.recorder();
+        let mut text = TextTrace::new(&start);
+        let mut events = timeline.events();
+        timeline
+            .steps()
+            .iter()
+            .map(|step| {
+                let (step_events, rest) = events.split_at(step.events);
+                events = rest;
+                text.line(step, step_events)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_lines() {
+        assert_eq!(
+            vec![
+                "0 (0,0) > push(0)",
+                "1 (1,0) > push(1)",
+                "+ (2,0) > pop(1) pop(0) push(1)",
+                "v (3,0) v",
+                "< (3,1) <",
+                ". (2,1) < pop(1)",
+                "$ (1,1) < pop()",
+                "@ (0,1) <",
+            ],
+            lines(b"01+v\n@$.<")
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::io;
 use std::time::{Duration, Instant};
 
@@ -12,11 +13,12 @@ pub use draw::{Draw, DrawBorder};
 pub use tabs::{FocusedTab, Tabs};
 pub use window::Window;
 
-use crate::core::Position;
+use bft::Position;
+
 use crate::debugger::Debugger;
 use crate::tui::draw::{CursorDisplay, ProgramCellCursor, ProgramCellReset, Sidebar};
-use crate::tui::layout::TabHeadingY;
-use crate::tui::tabs::CommandEvent;
+use crate::tui::layout::{ProgramView, TabHeadingY};
+use crate::tui::tabs::{CommandEvent, TimelineView};
 use crate::tui::window::WindowX;
 
 use crossterm::event::{Event, KeyCode, KeyEvent, MouseEvent};
@@ -24,10 +26,10 @@ use crossterm::event::{Event, KeyCode, KeyEvent, MouseEvent};
 const TICKS_PER_SECOND: u64 = 40;
 const MILLIS_PER_TICK: u64 = 1000 / TICKS_PER_SECOND;
 
-pub fn run_tui(name: String, program: Vec<u8>) -> Result<(), crate::Error> {
+pub fn run_tui(name: String, debugger: Debugger) -> Result<(), crate::Error> {
     let title = format!("Befunge Tools: {}", name);
     let mut window = Window::new()?;
-    let mut tui = Tui::new(title, program);
+    let mut tui = Tui::new(title, debugger, &window);
 
     tui.init(&mut window)?;
 
@@ -102,15 +104,25 @@ struct Tui {
     debugger: Debugger,
     tabs: Tabs,
     counter: u64,
+    /// Where the IPs other than the next one to execute were last drawn
+    other_positions: Vec<Position>,
+    /// Whether the sidebar needs to be redrawn without the debugger updating
+    sidebar_dirty: bool,
+    /// The part of the program space that was last drawn
+    view: ProgramView,
 }
 
 impl Tui {
-    fn new(title: String, program: Vec<u8>) -> Self {
+    fn new(title: String, debugger: Debugger, window: &Window) -> Self {
+        let view = ProgramView::new(debugger.interpreter.space(), window);
         Self {
             title,
-            debugger: Debugger::new(program),
+            debugger,
             tabs: Default::default(),
             counter: 0,
+            other_positions: Vec::new(),
+            sidebar_dirty: false,
+            view,
         }
     }
 
@@ -147,22 +159,42 @@ impl Tui {
         let debugger_updated = self.debugger.tick();
         let new_pos = self.debugger.current_position();
         self.tabs.position = new_pos;
+        // Writing outside of the space grows it, which can move everything in the program view
+        let view = ProgramView::new(self.debugger.interpreter.space(), window);
+        let view_moved = view != self.view;
+        self.view = view;
+        let timeline = self.debugger.timeline();
+        let history = TimelineView {
+            start: timeline.start(),
+            position: timeline.position(),
+            end: timeline.end(),
+        };
+        if history != self.tabs.timeline {
+            self.tabs.timeline = history;
+            self.tabs.dirty |= self.tabs.focused == FocusedTab::Timeline;
+        }
+        if let Some(error) = self.debugger.take_error() {
+            self.tabs.commands.show_error(&error);
+            self.tabs.dirty = true;
+        }
 
         // Check if tabs or terminal are dirty
         let tabs_dirty = self.tabs.dirty;
         let terminal_dirty = self.debugger.io_mut().dirty();
 
         // Return early if nothing has changed
-        let nothing_changed = !resized && !debugger_updated && !tabs_dirty && !terminal_dirty;
+        let sidebar_dirty = std::mem::take(&mut self.sidebar_dirty);
+        let redraw_all = resized || view_moved;
+        let nothing_changed =
+            !redraw_all && !debugger_updated && !tabs_dirty && !terminal_dirty && !sidebar_dirty;
         if nothing_changed {
             return Ok(());
         }
 
         window.start_frame()?;
 
-        let redraw_all = resized;
-        let redraw_top = resized;
-        let redraw_bot = resized || tabs_dirty || terminal_dirty;
+        let redraw_top = redraw_all;
+        let redraw_bot = redraw_all || tabs_dirty || terminal_dirty;
 
         if redraw_all {
             // redraw everything on resize
@@ -181,10 +213,10 @@ impl Tui {
             (self.debugger.io(), &self.tabs).draw(window)?;
         }
 
-        self.update_program_cursor(old_pos, new_pos, window)?;
+        self.update_program_cursors(old_pos, new_pos, redraw_all, window)?;
 
         // If top wasn't redrawn and the debugger has updated, redraw the sidebar
-        if !redraw_top && debugger_updated {
+        if !redraw_top && (debugger_updated || sidebar_dirty) {
             log::info!("Draw sidebar");
             let sidebar = Sidebar {
                 debugger: &self.debugger,
@@ -202,35 +234,51 @@ impl Tui {
         window.end_frame()
     }
 
-    fn update_program_cursor(
+    fn update_program_cursors(
         &mut self,
         old_pos: Position,
         new_pos: Position,
+        redraw: bool,
         window: &mut Window,
     ) -> io::Result<()> {
-        if new_pos != old_pos {
+        let others = self.debugger.other_positions();
+        let moved = new_pos != old_pos || others != self.other_positions;
+        if moved {
             self.counter = 0;
-            ProgramCellReset {
-                debugger: &self.debugger,
-                pos: old_pos,
+            let old_others = self.other_positions.iter().copied();
+            for pos in std::iter::once(old_pos).chain(old_others) {
+                ProgramCellReset {
+                    debugger: &self.debugger,
+                    view: &self.view,
+                    pos,
+                }
+                .draw(window)?;
             }
-            .draw(window)?;
-            ProgramCellCursor {
-                debugger: &self.debugger,
-                pos: new_pos,
-                background_on: true,
+        }
+        if moved || redraw {
+            for &pos in &others {
+                ProgramCellCursor {
+                    debugger: &self.debugger,
+                    view: &self.view,
+                    pos,
+                    background: styles::OTHER_CURSOR,
+                }
+                .draw(window)?;
             }
-            .draw(window)?;
+            self.other_positions = others;
+        }
+        let background = if self.counter < 20 {
+            styles::CURSOR_ON
         } else {
-            let background_on = self.counter < 20;
-            ProgramCellCursor {
-                debugger: &self.debugger,
-                pos: new_pos,
-                background_on,
-            }
-            .draw(window)?;
+            styles::CURSOR_OFF
+        };
+        ProgramCellCursor {
+            debugger: &self.debugger,
+            view: &self.view,
+            pos: new_pos,
+            background,
         }
-        Ok(())
+        .draw(window)
     }
 }
 
@@ -245,9 +293,30 @@ impl ListenForKey for Tui {
             match command_event {
                 CommandEvent::Load { path } => todo!("Load program in '{}'", path),
                 CommandEvent::Step { n } => self.debugger.add_steps(n),
+                CommandEvent::ReverseStep { n } => self.debugger.add_reverse_steps(n),
                 CommandEvent::Run => self.debugger.start_running(),
+                CommandEvent::ReverseContinue => self.debugger.start_running_back(),
                 CommandEvent::Pause => self.debugger.pause(),
                 CommandEvent::Breakpoint { pos } => self.debugger.toggle_breakpoint(pos),
+                CommandEvent::BreakOnUnderflow => {
+                    let on = self.debugger.toggle_break_on_underflow();
+                    log::info!("Break on underflow: {}", on);
+                }
+                CommandEvent::SelectIp { id } => {
+                    self.debugger.select_ip(id);
+                    self.sidebar_dirty = true;
+                }
+                CommandEvent::Goto { step } => {
+                    if !self.debugger.seek(step) {
+                        let timeline = self.debugger.timeline();
+                        self.tabs.commands.output = Cow::Owned(format!(
+                            "Step {} is not in the history, which has steps {} to {}",
+                            step,
+                            timeline.start(),
+                            timeline.end()
+                        ));
+                    }
+                }
                 CommandEvent::Quit => return Some(QuitEvent),
                 CommandEvent::PassToTerminal => {
                     self.debugger.io_mut().on_key_event(event);
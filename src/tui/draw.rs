@@ -1,11 +1,14 @@
+use bft::{Directions, InstructionPointer, Modes, Position, StackCell};
+
 use crate::{
-    analyze::{self, Directions},
-    core::{Position, StackCell},
     debugger::Debugger,
     terminal::VirtualTerminal,
     tui::{
         Tui,
-        layout::{self, ProgramX, ProgramY, SidebarX, SidebarY, TabHeadingY, TabY, program_cols},
+        layout::{
+            self, ProgramView, ProgramX, ProgramY, SidebarX, SidebarY, TabHeadingY, TabY,
+            program_cols,
+        },
         styles,
         tabs::{CommandsView, ConsoleView, FocusedTab, Tabs, TimelineView},
         text::{self, t, tw},
@@ -16,6 +19,9 @@ use crate::{
 use core::str;
 use std::io;
 
+use crossterm::style::{Color, ContentStyle};
+use unicode_width::UnicodeWidthChar;
+
 pub trait DrawBorder {
     fn draw_border(&self, window: &mut Window) -> io::Result<()>;
 }
@@ -49,6 +55,7 @@ impl Draw for Tui {
         StackHeading.draw(window)?;
         ProgramDisplay {
             debugger: &self.debugger,
+            view: &self.view,
         }
         .draw(window)?;
         Sidebar {
@@ -62,7 +69,7 @@ impl Draw for Tui {
 
 pub fn stack_slots(window: &Window) -> u16 {
     let rows = layout::stack_rows(window);
-    if rows % 2 == 0 {
+    if rows.is_multiple_of(2) {
         // -3 is for the Stack header and dead row
         // / 2 is because each element requires a divider
         (rows - 3) / 2
@@ -128,11 +135,10 @@ impl Draw for Sidebar<'_> {
 
         let even_parity = layout::stack_rows_parity_even(window);
         let room = stack_slots(window);
-        let stack_height = self.debugger.stack_height();
+        let entries = stack_entries(self.debugger.shown_ip());
+        let stack_height = entries.len() as u16;
         window.set_style(styles::CYAN_HEADING)?;
 
-        let number_x = SidebarX(1);
-        let symbol_x = SidebarX(5);
         let last_y = SidebarY::max(window);
 
         if stack_height > room {
@@ -140,11 +146,7 @@ impl Draw for Sidebar<'_> {
             let skip_x = SidebarX(2);
 
             // Draw bottom value
-            let bottom = &self.debugger.interpreter.stack()[0];
-            window.move_to(number_x, last_y)?;
-            window.print(t(&format!("{}", bottom.0)))?;
-            window.move_to(symbol_x, last_y)?;
-            print_label(*bottom, window)?;
+            print_entry(entries[0], last_y, window)?;
 
             // Draw skip count
             window.move_to(skip_x, last_y - 2)?;
@@ -153,22 +155,15 @@ impl Draw for Sidebar<'_> {
             // Draw top values
             let mut y = if even_parity { last_y - 5 } else { last_y - 4 };
             let top_start = (skipped + 1) as usize;
-            let stack_top = &self.debugger.interpreter.stack()[top_start..];
-            for cell in stack_top.iter() {
-                window.move_to(number_x, y)?;
-                window.print(t(&format!("{}", cell.0)))?;
-                window.move_to(symbol_x, y)?;
-                print_label(*cell, window)?;
+            for entry in entries[top_start..].iter() {
+                print_entry(*entry, y, window)?;
                 y = y - 2;
             }
         } else {
             let mut y = if even_parity { last_y - 1 } else { last_y };
             // Draw values
-            for cell in self.debugger.interpreter.stack().iter() {
-                window.move_to(number_x, y)?;
-                window.print(t(&format!("{}", cell.0)))?;
-                window.move_to(symbol_x, y)?;
-                print_label(*cell, window)?;
+            for entry in entries.iter() {
+                print_entry(*entry, y, window)?;
                 y = y - 2;
             }
         }
@@ -176,21 +171,58 @@ impl Draw for Sidebar<'_> {
     }
 }
 
-fn print_label(cell: StackCell, window: &mut Window) -> io::Result<()> {
-    if cell.0 <= (u8::MAX as i32) {
-        if let Some(label) = value_label(cell.0 as u8) {
-            window.print(t(label))?;
-        } else {
-            if let Some(c) = char::from_u32(cell.0 as u32) {
-                window.print_char('"')?;
-                window.print_char(c)?;
-                window.print_char('"')?;
-            }
+/// A slot in the stack sidebar
+#[derive(Clone, Copy)]
+enum StackEntry {
+    Value(StackCell),
+    /// Divides one stack of the stack stack from the next
+    Separator,
+}
+
+/// Every stack of an IP from the bottom of the stack stack to the top
+fn stack_entries(ip: &InstructionPointer) -> Vec<StackEntry> {
+    let mut entries = Vec::new();
+    for stack in ip.stacks_below.iter() {
+        entries.extend(stack.iter().copied().map(StackEntry::Value));
+        entries.push(StackEntry::Separator);
+    }
+    entries.extend(ip.stack.iter().copied().map(StackEntry::Value));
+    entries
+}
+
+fn print_entry(entry: StackEntry, y: SidebarY, window: &mut Window) -> io::Result<()> {
+    match entry {
+        StackEntry::Value(cell) => {
+            window.move_to(SidebarX(1), y)?;
+            window.print(t(&format!("{}", cell.0)))?;
+            window.move_to(SidebarX(5), y)?;
+            print_label(cell, window)?;
+        }
+        StackEntry::Separator => {
+            window.move_to(SidebarX(1), y)?;
+            window.set_style(styles::BORDER)?;
+            window.print(t("═══╪═══"))?;
+            window.set_style(styles::CYAN_HEADING)?;
         }
     }
     Ok(())
 }
 
+fn print_label(cell: StackCell, window: &mut Window) -> io::Result<()> {
+    if let Ok(value) = u8::try_from(cell.0)
+        && let Some(label) = value_label(value)
+    {
+        window.print(t(label))?;
+    } else if let Some(c) = char::from_u32(cell.0 as u32)
+        && c.width() == Some(1)
+    {
+        window.print_char('"')?;
+        window.print_char(c)?;
+        window.print_char('"')?;
+    }
+    Ok(())
+}
+
 fn value_label(value: u8) -> Option<&'static str> {
     // based on https://www.ascii-code.com/
     let code = match value {
@@ -356,7 +388,7 @@ impl Draw for VirtualTerminal {
         window.set_style(styles::PROGRAM_TEXT)?;
         let cols = layout::program_cols(window) as usize;
         let num_lines = self.num_lines();
-        let start = if num_lines > 7 { num_lines - 7 } else { 0 };
+        let start = num_lines.saturating_sub(7);
         VirtualTerminalDisplay {
             cols,
             num_lines,
@@ -391,13 +423,13 @@ impl VirtualTerminalDisplay<'_> {
         let Some(line) = self.term.get_line(line_index) else {
             return Ok(());
         };
-        // Slice it to the correct length
-        let line_len = std::cmp::min(line.len(), self.cols);
-        let line = &line[0..line_len];
+        // Slice it to the correct width
+        let line = String::from_utf8_lossy(line);
+        let (line, line_len) = text::fit_width(&line, self.cols);
         // Move to the right position and write the line
         let y = TabY(i as u16);
         window.move_to(WindowX(1), y)?;
-        window.write(line)?;
+        window.print(tw(line, line_len as u16))?;
 
         // Draw uncommitted if necessary
         if line_index == self.num_lines - 1 {
@@ -476,11 +508,28 @@ impl DrawBorder for TimelineView {
 
 impl Draw for TimelineView {
     fn draw(&self, window: &mut Window) -> io::Result<()> {
+        window.set_style(styles::PROGRAM_TEXT)?;
+        let max_width = program_cols(window) as usize;
+        let lines = [
+            format!("Step {} of {}", self.position, self.end),
+            format!("History reaches back to step {}", self.start),
+        ];
+        for (i, line) in (0..).zip(lines) {
+            window.move_to(WindowX(1), TabY(i))?;
+            let line = &line[0..line.len().min(max_width)];
+            window.print(tw(line, line.len() as u16))?;
+        }
+
+        // The bar shows where the current step is in the history
         let x = WindowX(1);
         let y = TabY(5).convert(window);
         let total = program_cols(window);
-        let bar = 0;
-        let offset = 0;
+        let bar = total.min(1);
+        let span = self.end - self.start;
+        let offset = match span {
+            0 => total - bar,
+            _ => ((self.position - self.start) * (total - bar) as usize / span) as u16,
+        };
         HorizontalScrollbar {
             x,
             y,
@@ -587,27 +636,20 @@ impl VerticalScrollbar {
 
 struct ProgramDisplay<'d> {
     debugger: &'d Debugger,
+    view: &'d ProgramView,
 }
 
 impl Draw for ProgramDisplay<'_> {
     fn draw(&self, window: &mut Window) -> io::Result<()> {
-        let cols = layout::program_cols(window);
-        let rows = layout::program_rows(window);
-        let space = self.debugger.interpreter.space();
-        for y in 0..rows {
+        for y in 0..self.view.rows() {
             window.move_to(ProgramX(0), ProgramY(y))?;
             let mut skipped = 0;
-            for x in 0..cols {
-                let pos = Position {
-                    x: x as u8,
-                    y: y as u8,
-                };
-                let cell = space.get_cell(pos);
+            for col in 0..self.view.cols() {
+                let pos = self.view.position(col, y);
+                let width = self.view.width(col);
                 let state = self.debugger.analysis.cell_states.get_cell(pos);
-                let c = char::from_u32(cell.0 as u32).unwrap_or('�');
-
-                if c == ' ' && state.modes() == analyze::Modes::None {
-                    skipped += 1;
+                if cell_char(self.debugger, pos) == ' ' && state.modes() == Modes::None {
+                    skipped += width;
                     continue;
                 }
 
@@ -617,109 +659,112 @@ impl Draw for ProgramDisplay<'_> {
                     skipped = 0;
                 }
 
-                if c == ' ' {
-                    if state.modes() == analyze::Modes::Quoted {
-                        window.set_style(styles::VISITED_QUOTED)?;
-                        window.print_char(' ')?;
-                        continue;
-                    }
-                    let c = state.directions().blank_char();
-                    window.set_style(styles::VISITED_EMPTY)?;
-                    window.print_char(c)?;
-                    continue;
-                }
-
-                let style = styles::for_cell(state.modes(), c);
+                let (style, c, fill) = cell_look(self.debugger, pos, width);
                 window.set_style(style)?;
                 window.print_char(c)?;
-                window.set_style(styles::PROGRAM_TEXT)?;
+                if let Some(fill) = fill {
+                    window.print_char(fill)?;
+                }
             }
+            window.set_style(styles::PROGRAM_TEXT)?;
         }
         Ok(())
     }
 }
 
-impl Directions {
-    fn blank_char(self) -> char {
-        match self {
-            Directions::None => ' ',
-            Directions::Horizontal => '─',
-            Directions::Vertical => '│',
-            Directions::Both => '┼',
+/// The character drawn for a program cell
+fn cell_char(debugger: &Debugger, pos: Position) -> char {
+    let cell = debugger.interpreter.space().get_cell(pos);
+    match char::from_u32(cell.0 as u32) {
+        Some(c) if matches!(c.width(), Some(1 | 2)) => c,
+        _ => char::REPLACEMENT_CHARACTER,
+    }
+}
+
+/// The style and character a program cell is drawn with,
+/// and what fills the second column when a narrow character is in a wide column
+fn cell_look(debugger: &Debugger, pos: Position, width: u16) -> (ContentStyle, char, Option<char>) {
+    let state = debugger.analysis.cell_states.get_cell(pos);
+    let c = cell_char(debugger, pos);
+    let (style, c, fill) = match (c, state.modes()) {
+        (' ', Modes::Quoted) => (styles::VISITED_QUOTED, ' ', ' '),
+        (' ', _) => {
+            let directions = state.directions();
+            // Horizontal paths carry on through the second column
+            let fill = match directions {
+                Directions::Horizontal | Directions::Both => '─',
+                _ => ' ',
+            };
+            (styles::VISITED_EMPTY, blank_char(directions), fill)
         }
+        _ => (styles::for_cell(state.modes(), c), c, ' '),
+    };
+    let narrow = c.width() == Some(1);
+    (style, c, (narrow && width == 2).then_some(fill))
+}
+
+/// The line drawn through a blank cell the IP can pass in these directions
+fn blank_char(directions: Directions) -> char {
+    match directions {
+        Directions::None => ' ',
+        Directions::Horizontal => '─',
+        Directions::Vertical => '│',
+        Directions::Both => '┼',
     }
 }
 
 pub struct ProgramCellReset<'d> {
     pub debugger: &'d Debugger,
+    pub view: &'d ProgramView,
     pub pos: Position,
 }
 
 impl Draw for ProgramCellReset<'_> {
     fn draw(&self, window: &mut Window) -> io::Result<()> {
-        // Skip drawing if out of bounds
-        let cols = layout::program_cols(window);
-        let rows = layout::program_rows(window);
-        if self.pos.x as u16 >= cols || self.pos.y as u16 >= rows {
-            return Ok(());
-        }
-        // Move to position
-        window.move_to(ProgramX(self.pos.x as u16), ProgramY(self.pos.y as u16))?;
-        // Get cell info
-        let cell = self.debugger.interpreter.space().get_cell(self.pos);
-        let state = self.debugger.analysis.cell_states.get_cell(self.pos);
-        let c = char::from_u32(cell.0 as u32).unwrap_or('�');
-        // Select character and style
-        let (style, c) = match (c, state.modes()) {
-            (' ', analyze::Modes::Quoted) => (styles::VISITED_QUOTED, ' '),
-            (' ', _) => (styles::VISITED_EMPTY, state.directions().blank_char()),
-            _ => (styles::for_cell(state.modes(), c), c),
-        };
-        window.set_style(style)?;
-        window.print_char(c)?;
-        window.set_style(styles::BORDER)?;
-        Ok(())
+        draw_cell(self.debugger, self.view, self.pos, None, window)
     }
 }
 
 pub struct ProgramCellCursor<'d> {
     pub debugger: &'d Debugger,
+    pub view: &'d ProgramView,
     pub pos: Position,
-    pub background_on: bool,
+    pub background: Option<Color>,
 }
 
 impl Draw for ProgramCellCursor<'_> {
     fn draw(&self, window: &mut Window) -> io::Result<()> {
-        // Skip drawing if out of bounds
-        let cols = layout::program_cols(window);
-        let rows = layout::program_rows(window);
-        if self.pos.x as u16 >= cols || self.pos.y as u16 >= rows {
-            return Ok(());
-        }
-        // Move to position
-        window.move_to(ProgramX(self.pos.x as u16), ProgramY(self.pos.y as u16))?;
-        // Get cell info
-        let cell = self.debugger.interpreter.space().get_cell(self.pos);
-        let state = self.debugger.analysis.cell_states.get_cell(self.pos);
-        let c = char::from_u32(cell.0 as u32).unwrap_or('�');
-        // Select character and style
-        let (mut style, c) = match (c, state.modes()) {
-            (' ', analyze::Modes::Quoted) => (styles::VISITED_QUOTED, ' '),
-            (' ', _) => (styles::VISITED_EMPTY, state.directions().blank_char()),
-            _ => (styles::for_cell(state.modes(), c), c),
-        };
-        if self.background_on {
-            style.background_color = styles::CURSOR_ON;
-        } else {
-            style.background_color = styles::CURSOR_OFF;
-        }
-        window.set_style(style)?;
-        window.print_char(c)?;
-        window.set_style(styles::BORDER)?;
-        Ok(())
+        draw_cell(self.debugger, self.view, self.pos, self.background, window)
     }
 }
 
+/// Draws a single program cell, with a background color if it is under a cursor
+fn draw_cell(
+    debugger: &Debugger,
+    view: &ProgramView,
+    pos: Position,
+    background: Option<Color>,
+    window: &mut Window,
+) -> io::Result<()> {
+    // Skip drawing if out of bounds
+    let Some((x, y)) = view.coords(pos) else {
+        return Ok(());
+    };
+    window.move_to(x, y)?;
+    let width = view.width((pos.x as i64 - view.origin.x as i64) as usize);
+    let (mut style, c, fill) = cell_look(debugger, pos, width);
+    if background.is_some() {
+        style.background_color = background;
+    }
+    window.set_style(style)?;
+    window.print_char(c)?;
+    if let Some(fill) = fill {
+        window.print_char(fill)?;
+    }
+    window.set_style(styles::BORDER)?;
+    Ok(())
+}
+
 pub struct CursorDisplay {
     pub pos: Position,
 }
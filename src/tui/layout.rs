@@ -1,3 +1,7 @@
+use bft::{GridCell, Position, Space};
+
+use unicode_width::UnicodeWidthChar;
+
 use crate::tui::window::{ConvertToWindowSpace, Window, WindowX, WindowY};
 
 const NON_PROGRAM_WIDTH: u16 = 10;
@@ -11,12 +15,101 @@ pub fn program_rows(window: &Window) -> u16 {
     window.height() - NON_PROGRAM_HEIGHT
 }
 
+/// The part of the program space shown in the program view.
+/// Columns of the program holding a wide character take up two columns of the view,
+/// so that every row stays lined up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgramView {
+    /// The program position shown in the top left corner
+    pub origin: Position,
+    /// Where each shown column of the program starts, followed by where the last one ends
+    starts: Vec<u16>,
+    rows: u16,
+}
+
+impl ProgramView {
+    /// Shows the space from its least position, so cells written at negative positions are visible
+    pub fn new(space: &Space<GridCell>, window: &Window) -> Self {
+        let width = |pos| cell_width(space.get_cell(pos));
+        Self::with_size(
+            space.least(),
+            program_cols(window),
+            program_rows(window),
+            width,
+        )
+    }
+
+    fn with_size(origin: Position, cols: u16, rows: u16, width: impl Fn(Position) -> u16) -> Self {
+        let mut view = Self {
+            origin,
+            starts: vec![0],
+            rows,
+        };
+        let mut end = 0;
+        for x in 0..cols {
+            let column = (0..rows)
+                .map(|y| width(view.position(x as usize, y)))
+                .max()
+                .unwrap_or(1);
+            if end + column > cols {
+                break;
+            }
+            end += column;
+            view.starts.push(end);
+        }
+        view
+    }
+
+    /// The number of program columns shown
+    pub fn cols(&self) -> usize {
+        self.starts.len() - 1
+    }
+
+    /// The number of program rows shown
+    pub fn rows(&self) -> u16 {
+        self.rows
+    }
+
+    /// The number of columns of the view a shown program column takes up
+    pub fn width(&self, col: usize) -> u16 {
+        self.starts[col + 1] - self.starts[col]
+    }
+
+    /// The location of a program position in the program view
+    /// or None if it does not fit in the window.
+    pub fn coords(&self, pos: Position) -> Option<(ProgramX, ProgramY)> {
+        let col = usize::try_from(pos.x as i64 - self.origin.x as i64).ok()?;
+        let y = u16::try_from(pos.y as i64 - self.origin.y as i64).ok()?;
+        if col >= self.cols() || y >= self.rows {
+            return None;
+        }
+        Some((ProgramX(self.starts[col]), ProgramY(y)))
+    }
+
+    /// The program position shown in a column and row of the program view
+    pub fn position(&self, col: usize, row: u16) -> Position {
+        Position {
+            x: self.origin.x.wrapping_add(col as i32),
+            y: self.origin.y.wrapping_add(row as i32),
+        }
+    }
+}
+
+/// The number of columns a program cell needs, which is 2 for wide characters like CJK and emoji
+pub fn cell_width(cell: GridCell) -> u16 {
+    let c = char::from_u32(cell.0 as u32);
+    match c.and_then(|c| c.width()) {
+        Some(2) => 2,
+        _ => 1,
+    }
+}
+
 pub fn stack_rows(window: &Window) -> u16 {
     program_rows(window)
 }
 
 pub fn stack_rows_parity_even(window: &Window) -> bool {
-    program_rows(window) % 2 == 0
+    program_rows(window).is_multiple_of(2)
 }
 
 macro_rules! wrapper_arithmetic {
@@ -103,3 +196,26 @@ impl ConvertToWindowSpace<WindowY> for TabHeadingY {
         WindowY(program_rows(window) + 1 + self.0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wide_columns() {
+        // "漢字" in the top row, next to each other
+        let wide = |pos: Position| if pos.y == 0 && pos.x < 2 { 2 } else { 1 };
+        let view = ProgramView::with_size(Position::ORIGIN, 10, 2, wide);
+        assert_eq!(8, view.cols());
+        assert_eq!(2, view.width(1));
+        assert_eq!(1, view.width(2));
+        let coords = |x, y| view.coords(Position { x, y });
+        assert_eq!(Some((ProgramX(2), ProgramY(0))), coords(1, 0));
+        // The row below stays lined up with the wide characters
+        assert_eq!(Some((ProgramX(2), ProgramY(1))), coords(1, 1));
+        assert_eq!(Some((ProgramX(4), ProgramY(1))), coords(2, 1));
+        assert_eq!(Some((ProgramX(9), ProgramY(1))), coords(7, 1));
+        assert_eq!(None, coords(8, 0));
+        assert_eq!(None, coords(-1, 0));
+    }
+}
@@ -1,6 +1,6 @@
 use crossterm::style::{Attribute, Attributes, Color, ContentStyle};
 
-use crate::analyze::Modes;
+use bft::Modes;
 
 use super::FocusedTab;
 
@@ -138,6 +138,7 @@ pub fn tab_heading(current: FocusedTab, focused: FocusedTab) -> ContentStyle {
 
 pub const CURSOR_ON: Option<Color> = Some(Color::Blue);
 pub const CURSOR_OFF: Option<Color> = None;
+pub const OTHER_CURSOR: Option<Color> = Some(Color::DarkGrey);
 
 pub fn for_cell(modes: Modes, c: char) -> ContentStyle {
     match modes {
@@ -2,11 +2,13 @@
 
 use core::fmt;
 use crossterm::event::{KeyCode, KeyEvent, MouseEvent, MouseEventKind};
-use std::{borrow::Cow, io};
+use std::{borrow::Cow, io, num::NonZeroU16, str::FromStr};
 use thiserror::Error;
+use unicode_width::UnicodeWidthStr;
+
+use bft::{ErrorKind, InterpreterError, Position};
 
 use crate::{
-    core::Position,
     terminal::VirtualTerminal,
     tui::{
         ListenForKey, ListenForMouse, Window,
@@ -55,8 +57,13 @@ pub enum Mode {
     Paused,
 }
 
+/// How far the timeline reaches, in steps
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
-pub struct TimelineView;
+pub struct TimelineView {
+    pub start: usize,
+    pub position: usize,
+    pub end: usize,
+}
 
 impl Tabs {
     fn focus_next(&mut self) {
@@ -97,7 +104,8 @@ impl Tabs {
                     Some(line) => {
                         let cols = layout::program_cols(window);
                         let max_w = cols - 2;
-                        let cursor = (line.len() + term.cursor()) as u16;
+                        let line_width = String::from_utf8_lossy(line).width();
+                        let cursor = (line_width + term.cursor()) as u16;
                         if cursor > max_w {
                             (WindowX(max_w), y)
                         } else {
@@ -162,23 +170,17 @@ impl ListenForMouse for Tabs {
                 //                     20      28           41       50
                 //                               30       39
                 match event.column {
-                    20..=28 => {
-                        if self.focused != FocusedTab::Console {
-                            self.focused = FocusedTab::Console;
-                            self.dirty = true;
-                        }
+                    20..=28 if self.focused != FocusedTab::Console => {
+                        self.focused = FocusedTab::Console;
+                        self.dirty = true;
                     }
-                    30..=39 => {
-                        if self.focused != FocusedTab::Commands {
-                            self.focused = FocusedTab::Commands;
-                            self.dirty = true;
-                        }
+                    30..=39 if self.focused != FocusedTab::Commands => {
+                        self.focused = FocusedTab::Commands;
+                        self.dirty = true;
                     }
-                    41..=50 => {
-                        if self.focused != FocusedTab::Timeline {
-                            self.focused = FocusedTab::Timeline;
-                            self.dirty = true;
-                        }
+                    41..=50 if self.focused != FocusedTab::Timeline => {
+                        self.focused = FocusedTab::Timeline;
+                        self.dirty = true;
                     }
                     _ => {}
                 }
@@ -192,9 +194,14 @@ enum Command {
     Help,
     Load { path: String },
     Step { n: u16 },
+    ReverseStep { n: u16 },
     Run,
+    ReverseContinue,
     Pause,
     Breakpoint { pos: Position },
+    BreakOnUnderflow,
+    SelectIp { id: Option<u32> },
+    Goto { step: usize },
     Quit,
 }
 
@@ -204,9 +211,15 @@ impl fmt::Display for Command {
             Command::Help => write!(f, "Help"),
             Command::Load { path } => write!(f, "Load '{}'", path),
             Command::Step { n } => write!(f, "Step {}", *n),
+            Command::ReverseStep { n } => write!(f, "Reverse step {}", *n),
             Command::Run => write!(f, "Run"),
+            Command::ReverseContinue => write!(f, "Reverse continue"),
             Command::Pause => write!(f, "Pause"),
             Command::Breakpoint { pos } => write!(f, "Breakpoint at {}", pos),
+            Command::BreakOnUnderflow => write!(f, "Break on underflow"),
+            Command::SelectIp { id: Some(id) } => write!(f, "Show IP {}", id),
+            Command::SelectIp { id: None } => write!(f, "Show next IP"),
+            Command::Goto { step } => write!(f, "Go to step {}", step),
             Command::Quit => write!(f, "Quit"),
         }
     }
@@ -225,9 +238,14 @@ impl Default for CommandsView {
 pub enum CommandEvent {
     Load { path: String },
     Step { n: u16 },
+    ReverseStep { n: u16 },
     Run,
+    ReverseContinue,
     Pause,
     Breakpoint { pos: Position },
+    BreakOnUnderflow,
+    SelectIp { id: Option<u32> },
+    Goto { step: usize },
     Quit,
     PassToTerminal,
 }
@@ -292,10 +310,21 @@ impl ListenForKey for CommandsView {
                             };
                             Some(CommandEvent::Step { n })
                         }
+                        Command::ReverseStep { n } => {
+                            self.output = match n {
+                                1 => Cow::Borrowed("Stepping back 1 step"),
+                                _ => Cow::Owned(format!("Stepping back {} steps", n)),
+                            };
+                            Some(CommandEvent::ReverseStep { n })
+                        }
                         Command::Run => {
                             self.output = Cow::Borrowed("Running...");
                             Some(CommandEvent::Run)
                         }
+                        Command::ReverseContinue => {
+                            self.output = Cow::Borrowed("Running back...");
+                            Some(CommandEvent::ReverseContinue)
+                        }
                         Command::Pause => {
                             self.output = Cow::Borrowed("Paused");
                             Some(CommandEvent::Pause)
@@ -304,6 +333,21 @@ impl ListenForKey for CommandsView {
                             self.output = Cow::Owned(format!("Setting breakpoint at {}", pos));
                             Some(CommandEvent::Breakpoint { pos })
                         }
+                        Command::BreakOnUnderflow => {
+                            self.output = Cow::Borrowed("Toggling break on underflow");
+                            Some(CommandEvent::BreakOnUnderflow)
+                        }
+                        Command::SelectIp { id } => {
+                            self.output = match id {
+                                Some(id) => Cow::Owned(format!("Showing the stack of IP {}", id)),
+                                None => Cow::Borrowed("Showing the stack of the next IP"),
+                            };
+                            Some(CommandEvent::SelectIp { id })
+                        }
+                        Command::Goto { step } => {
+                            self.output = Cow::Owned(format!("Going to step {}", step));
+                            Some(CommandEvent::Goto { step })
+                        }
                         Command::Quit => Some(CommandEvent::Quit),
                     }
                 }
@@ -326,7 +370,22 @@ impl ListenForKey for CommandsView {
 }
 
 impl CommandsView {
-    fn parse_command(&mut self) -> Result<Option<Command>, CommandError> {
+    /// Shows an error that stopped the program
+    pub fn show_error(&mut self, error: &InterpreterError) {
+        let mut output = format!(
+            "error: {} moving {} after {} steps",
+            error, error.direction, error.step
+        );
+        if let ErrorKind::InfiniteLoop { cells, .. } = &error.kind {
+            output.push_str("\nthrough");
+            for pos in cells {
+                output.push_str(&format!(" {}", pos));
+            }
+        }
+        self.output = Cow::Owned(output);
+    }
+
+    fn parse_command(&mut self) -> Result<Option<Command>, CommandError<'_>> {
         let mut args = self.input_contents.split(' ');
         if let Some(first) = args.next() {
             let (command, expected) = match first {
@@ -345,17 +404,26 @@ impl CommandsView {
                 }
                 "s" | "step" => {
                     if let Some(arg) = args.next() {
-                        let n = arg.parse().unwrap();
+                        let n = parse_steps(arg)?;
                         (Command::Step { n }, 1)
                     } else {
                         (Command::Step { n: 1 }, 0)
                     }
                 }
+                "rs" | "reverse-step" => {
+                    if let Some(arg) = args.next() {
+                        let n = parse_steps(arg)?;
+                        (Command::ReverseStep { n }, 1)
+                    } else {
+                        (Command::ReverseStep { n: 1 }, 0)
+                    }
+                }
                 "r" | "run" => (Command::Run, 0),
+                "rc" | "reverse-continue" => (Command::ReverseContinue, 0),
                 "p" | "pause" => (Command::Pause, 0),
                 "b" | "breakpoint" => {
                     let x = match args.next() {
-                        Some(arg) => arg.parse().unwrap(),
+                        Some(arg) => parse_arg(arg, "a coordinate")?,
                         None => {
                             return Err(CommandError::TooFewArguments {
                                 command: Command::Breakpoint {
@@ -366,7 +434,7 @@ impl CommandsView {
                         }
                     };
                     let y = match args.next() {
-                        Some(arg) => arg.parse().unwrap(),
+                        Some(arg) => parse_arg(arg, "a coordinate")?,
                         None => {
                             return Err(CommandError::TooFewArguments {
                                 command: Command::Load { path: "".into() },
@@ -379,6 +447,27 @@ impl CommandsView {
                     };
                     (command, 2)
                 }
+                "u" | "underflow" => (Command::BreakOnUnderflow, 0),
+                "i" | "ip" => {
+                    if let Some(arg) = args.next() {
+                        let id = parse_arg(arg, "an IP id")?;
+                        (Command::SelectIp { id: Some(id) }, 1)
+                    } else {
+                        (Command::SelectIp { id: None }, 0)
+                    }
+                }
+                "g" | "goto" => {
+                    let step = match args.next() {
+                        Some(arg) => parse_arg(arg, "a step number")?,
+                        None => {
+                            return Err(CommandError::TooFewArguments {
+                                command: Command::Goto { step: 0 },
+                                expected: 1,
+                            });
+                        }
+                    };
+                    (Command::Goto { step }, 1)
+                }
                 "q" | "quit" => (Command::Quit, 0),
                 "" => return Ok(None),
                 arg => return Err(CommandError::UnknownCommand { arg }),
@@ -409,9 +498,24 @@ enum CommandError<'a> {
     TooFewArguments { command: Command, expected: u16 },
     #[error("error: unknown command alias '{arg}'")]
     UnknownCommand { arg: &'a str },
+    #[error("error: expected {expected}, but found '{arg}'")]
+    InvalidArgument {
+        arg: &'a str,
+        expected: &'static str,
+    },
 }
 
-const HELP_OUTPUT: &str = "step  │ s [n]     │ takes a step\nrun   │ r         │ runs the program\npause │ p         │ pauses the execution\nbreak │ b <x> <y> │ places a breakpoint\nquit  │ q         │ exits the debugger";
+const HELP_OUTPUT: &str = "step  │ s [n]      │ takes a step\nrun   │ r, p       │ runs or pauses the program\nback  │ rs [n], rc │ steps back, or runs back to a breakpoint\nbreak │ b <x> <y>  │ places a breakpoint, u pauses on empty stack pops\ngoto  │ g <step>   │ goes back or forward to a step\nip    │ i [id]     │ shows the stack of an IP\nquit  │ q          │ exits the debugger";
+
+fn parse_arg<'a, T: FromStr>(arg: &'a str, expected: &'static str) -> Result<T, CommandError<'a>> {
+    arg.parse()
+        .map_err(|_| CommandError::InvalidArgument { arg, expected })
+}
+
+/// Parses a number of steps to take, which can't be 0
+fn parse_steps(arg: &str) -> Result<u16, CommandError<'_>> {
+    parse_arg::<NonZeroU16>(arg, "a number of steps from 1 to 65535").map(NonZeroU16::get)
+}
 
 fn try_collect<'a>(mut args: impl Iterator<Item = &'a str>) -> Option<Vec<&'a str>> {
     if let Some(arg) = args.next() {
@@ -3,6 +3,7 @@
 use std::io::{self, Write};
 
 use crossterm::{QueueableCommand, cursor::MoveRight};
+use unicode_width::UnicodeWidthChar;
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct Text<S> {
@@ -48,6 +49,20 @@ pub const fn tn(n: u8) -> Text<&'static str> {
     t(numbers[n as usize])
 }
 
+/// Truncates a string to fit in a number of terminal columns
+/// and returns it with the number of columns it occupies.
+pub fn fit_width(s: &str, max: usize) -> (&str, usize) {
+    let mut width = 0;
+    for (i, c) in s.char_indices() {
+        let w = c.width().unwrap_or(0);
+        if width + w > max {
+            return (&s[..i], width);
+        }
+        width += w;
+    }
+    (s, width)
+}
+
 const SOURCE_UNITS: u16 = 256;
 
 pub struct SliceSource<const STRIDE: u16, S> {
@@ -203,7 +218,7 @@ pub fn sidebar(i: u16, rows: u16, even: bool, collapse: bool) -> StaticText {
                 tw("╟───┬───╢", 9)
             } else if even && i == row_last {
                 tw("║   │   ║", 9)
-            } else if i % 2 == 0 {
+            } else if i.is_multiple_of(2) {
                 tw("║   │   ║", 9)
             } else {
                 tw("╟───┼───╢", 9)
@@ -211,7 +226,7 @@ pub fn sidebar(i: u16, rows: u16, even: bool, collapse: bool) -> StaticText {
         } else {
             if even && i == row_last {
                 tw("╟───┴───╢", 9)
-            } else if i % 2 == 0 {
+            } else if i.is_multiple_of(2) {
                 tw("║   │   ║", 9)
             } else {
                 tw("╟───┼───╢", 9)
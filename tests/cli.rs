@@ -0,0 +1,39 @@
+use std::process::{Command, Output};
+
+fn run_bft(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_bft"))
+        .args(args)
+        .output()
+        .expect("bft runs")
+}
+
+#[test]
+fn test_run_prints_seed() {
+    let path = std::env::temp_dir().join(format!("bft_seed_{}.b93", std::process::id()));
+    // Prints 1 or 2, or nothing, depending on which way "?" goes
+    std::fs::write(&path, "v\n?1.@\n2\n.\n@\n").unwrap();
+    let path = path.to_str().unwrap();
+
+    let output = run_bft(&["run", path]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let seed = stderr
+        .lines()
+        .find_map(|line| line.strip_prefix("Random seed: "))
+        .expect("the seed is printed");
+
+    // The printed seed reproduces the run, and isn't printed again when it is given
+    let seeded = run_bft(&["run", path, "--seed", seed]);
+    assert_eq!(output.stdout, seeded.stdout);
+    assert!(seeded.stderr.is_empty());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_run_without_random_hides_seed() {
+    let path = std::env::temp_dir().join(format!("bft_no_seed_{}.b93", std::process::id()));
+    std::fs::write(&path, "1.@\n").unwrap();
+    let output = run_bft(&["run", path.to_str().unwrap()]);
+    assert_eq!(b"1 ", &output.stdout[..]);
+    assert!(output.stderr.is_empty());
+    std::fs::remove_file(&path).unwrap();
+}